@@ -20,6 +20,16 @@ pub enum IrwinError {
 
     /// Header value errors
     HeaderError(reqwest::header::InvalidHeaderValue),
+
+    /// Paging stopped making progress (a page repeated records already seen)
+    PaginationError(String),
+
+    /// Local file errors (sync cursor, incident store)
+    IoError(std::io::Error),
+
+    /// Local incident store errors
+    #[cfg(feature = "sqlite")]
+    StoreError(rusqlite::Error),
 }
 
 impl fmt::Display for IrwinError {
@@ -33,12 +43,29 @@ impl fmt::Display for IrwinError {
             IrwinError::SerializationError(err) => write!(f, "Serialization error: {}", err),
             IrwinError::UrlError(err) => write!(f, "URL error: {}", err),
             IrwinError::HeaderError(err) => write!(f, "Header error: {}", err),
+            IrwinError::PaginationError(msg) => write!(f, "Pagination error: {}", msg),
+            IrwinError::IoError(err) => write!(f, "IO error: {}", err),
+            #[cfg(feature = "sqlite")]
+            IrwinError::StoreError(err) => write!(f, "Store error: {}", err),
         }
     }
 }
 
 impl std::error::Error for IrwinError {}
 
+impl IrwinError {
+    /// ArcGIS "invalid token" (498) or "token required" (499)
+    pub fn is_invalid_token(&self) -> bool {
+        matches!(
+            self,
+            IrwinError::ApiError {
+                status: 498 | 499,
+                ..
+            }
+        )
+    }
+}
+
 impl From<reqwest::Error> for IrwinError {
     fn from(err: reqwest::Error) -> Self {
         IrwinError::HttpClientError(err)
@@ -62,3 +89,16 @@ impl From<reqwest::header::InvalidHeaderValue> for IrwinError {
         IrwinError::HeaderError(err)
     }
 }
+
+impl From<std::io::Error> for IrwinError {
+    fn from(err: std::io::Error) -> Self {
+        IrwinError::IoError(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for IrwinError {
+    fn from(err: rusqlite::Error) -> Self {
+        IrwinError::StoreError(err)
+    }
+}
@@ -0,0 +1,326 @@
+use serde_json::{json, Map, Value};
+
+use crate::incident::{Feature, FeatureSet, Geometry, Ring};
+use crate::spatial::WGS84;
+
+/// Columns [`to_csv`] writes when none are selected
+pub const DEFAULT_CSV_COLUMNS: &[&str] = &[
+    "IrwinID",
+    "IncidentName",
+    "UniqueFireIdentifier",
+    "IncidentTypeKind",
+    "InitialLatitude",
+    "InitialLongitude",
+    "DailyAcres",
+    "PercentContained",
+    "ModifiedOnDateTime",
+    "IsValid",
+];
+
+/// A GeoJSON FeatureCollection of `features`
+///
+/// Attributes become `properties` under their IRWIN names, unknown ones
+/// included. Points and perimeter polygons are converted (ArcGIS rings are
+/// regrouped into polygons and rewound counter-clockwise, per RFC 7946);
+/// a feature without geometry is placed at its point of origin, or gets a
+/// null geometry if it has none. GeoJSON is always WGS84, so geometry the
+/// feature set's `spatialReference` puts in any other system (e.g. Web
+/// Mercator, without `outSR=4326`) is dropped for the point of origin too.
+pub fn to_geojson(features: &FeatureSet) -> Value {
+    let wgs84 = is_wgs84(features);
+    json!({
+        "type": "FeatureCollection",
+        "features": features
+            .features
+            .iter()
+            .map(|f| geojson_feature(f, wgs84))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Whether the feature set's geometry is WGS84; assumed when it doesn't say
+fn is_wgs84(features: &FeatureSet) -> bool {
+    let Some(sr) = features.extra.get("spatialReference") else {
+        return true;
+    };
+    sr.get("latestWkid")
+        .or_else(|| sr.get("wkid"))
+        .and_then(Value::as_u64)
+        == Some(WGS84.into())
+}
+
+fn geojson_feature(feature: &Feature, wgs84: bool) -> Value {
+    let geometry = feature
+        .geometry
+        .as_ref()
+        .filter(|_| wgs84)
+        .and_then(geojson_geometry)
+        .or_else(|| {
+            let (lat, lon) = if wgs84 {
+                feature.location()?
+            } else {
+                feature.attributes.poo()?
+            };
+            Some(json!({"type": "Point", "coordinates": [lon, lat]}))
+        })
+        .unwrap_or(Value::Null);
+    let properties = serde_json::to_value(&feature.attributes).unwrap_or_default();
+    json!({"type": "Feature", "geometry": geometry, "properties": properties})
+}
+
+fn geojson_geometry(geometry: &Geometry) -> Option<Value> {
+    match geometry {
+        Geometry::Point { x, y } => Some(json!({"type": "Point", "coordinates": [x, y]})),
+        Geometry::Polygon { rings } => polygons(rings),
+        Geometry::Other(m) => {
+            if let Some(points) = m.get("points") {
+                Some(json!({"type": "MultiPoint", "coordinates": points}))
+            } else {
+                m.get("paths")
+                    .map(|paths| json!({"type": "MultiLineString", "coordinates": paths}))
+            }
+        }
+    }
+}
+
+/// ArcGIS rings to a GeoJSON Polygon or MultiPolygon
+///
+/// Clockwise rings are outer boundaries; each counter-clockwise ring is a
+/// hole in the outer ring that contains it.
+fn polygons(rings: &[Ring]) -> Option<Value> {
+    let mut polys: Vec<Vec<Ring>> = Vec::new();
+    let mut holes: Vec<&Ring> = Vec::new();
+    for ring in rings.iter().filter(|r| !r.is_empty()) {
+        if signed_area(ring) <= 0.0 {
+            polys.push(vec![ring.iter().rev().copied().collect()]);
+        } else {
+            holes.push(ring);
+        }
+    }
+    for hole in holes {
+        let hole_cw: Ring = hole.iter().rev().copied().collect();
+        match polys.iter_mut().find(|p| contains(&p[0], hole[0])) {
+            Some(poly) => poly.push(hole_cw),
+            // an orphan hole is most likely an outer ring wound the wrong way
+            None => polys.push(vec![hole.clone()]),
+        }
+    }
+    match polys.len() {
+        0 => None,
+        1 => Some(json!({"type": "Polygon", "coordinates": polys[0]})),
+        _ => Some(json!({"type": "MultiPolygon", "coordinates": polys})),
+    }
+}
+
+/// Shoelace area; negative for clockwise rings
+fn signed_area(ring: &Ring) -> f64 {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| a[0] * b[1] - b[0] * a[1])
+        .sum::<f64>()
+        / 2.0
+}
+
+fn contains(ring: &Ring, [x, y]: [f64; 2]) -> bool {
+    let mut inside = false;
+    for (a, b) in ring.iter().zip(ring.iter().cycle().skip(1)) {
+        if (a[1] > y) != (b[1] > y) && x < a[0] + (y - a[1]) * (b[0] - a[0]) / (b[1] - a[1]) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// CSV (RFC 4180) with a header row and one row per feature
+///
+/// `columns` are attribute names; an empty selection means
+/// [`DEFAULT_CSV_COLUMNS`]. Missing and null values are empty cells, and
+/// non-scalar values are written as JSON. Text starting with `=`, `+`, `-`,
+/// `@`, a tab or a carriage return gets a leading `'`, so spreadsheets show
+/// it rather than evaluate it as a formula.
+pub fn to_csv(features: &FeatureSet, columns: &[&str]) -> String {
+    let columns = if columns.is_empty() {
+        DEFAULT_CSV_COLUMNS
+    } else {
+        columns
+    };
+    let mut out = String::new();
+    push_row(&mut out, columns.iter().map(|c| c.to_string()));
+    for feature in &features.features {
+        let attributes = match serde_json::to_value(&feature.attributes) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+        push_row(&mut out, columns.iter().map(|c| cell(attributes.get(*c))));
+    }
+    out
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) if s.starts_with(['=', '+', '-', '@', '\t', '\r']) => {
+            format!("'{}", s)
+        }
+        Some(Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+    }
+}
+
+fn push_row(out: &mut String, cells: impl Iterator<Item = String>) {
+    let cells: Vec<String> = cells
+        .map(|c| {
+            if c.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", c.replace('"', "\"\""))
+            } else {
+                c
+            }
+        })
+        .collect();
+    out.push_str(&cells.join(","));
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::Incident;
+
+    fn feature(name: &str, geometry: Option<Geometry>) -> Feature {
+        let mut attributes = Incident {
+            irwin_id: Some("{A-1}".to_string()),
+            incident_name: Some(name.to_string()),
+            poo_latitude: Some(38.6),
+            poo_longitude: Some(-121.5),
+            daily_acres: Some(12.5),
+            ..Default::default()
+        };
+        attributes
+            .extra
+            .insert("FireCause".to_string(), "Human".into());
+        Feature {
+            geometry,
+            ..attributes.into()
+        }
+    }
+
+    #[test]
+    fn test_geojson_points_and_perimeters() {
+        // clockwise outer ring with a counter-clockwise hole, plus a second
+        // clockwise outer ring
+        let outer: Ring = vec![[0.0, 0.0], [0.0, 4.0], [4.0, 4.0], [4.0, 0.0], [0.0, 0.0]];
+        let hole: Ring = vec![[1.0, 1.0], [2.0, 1.0], [2.0, 2.0], [1.0, 2.0], [1.0, 1.0]];
+        let island: Ring = vec![[5.0, 5.0], [5.0, 6.0], [6.0, 6.0], [6.0, 5.0], [5.0, 5.0]];
+        let fs = FeatureSet {
+            features: vec![
+                feature("origin only", None),
+                feature(
+                    "perimeter",
+                    Some(Geometry::Polygon {
+                        rings: vec![outer, hole, island],
+                    }),
+                ),
+            ],
+            ..Default::default()
+        };
+        let gj = to_geojson(&fs);
+        assert_eq!(gj["type"], "FeatureCollection");
+
+        let point = &gj["features"][0];
+        assert_eq!(point["geometry"]["type"], "Point");
+        assert_eq!(point["geometry"]["coordinates"], json!([-121.5, 38.6]));
+        assert_eq!(point["properties"]["IncidentName"], "origin only");
+        assert_eq!(point["properties"]["FireCause"], "Human");
+
+        let multi = &gj["features"][1]["geometry"];
+        assert_eq!(multi["type"], "MultiPolygon");
+        let first = &multi["coordinates"][0];
+        assert_eq!(first.as_array().unwrap().len(), 2, "outer ring plus hole");
+        // exterior rewound counter-clockwise
+        assert_eq!(first[0][1], json!([4.0, 0.0]));
+        assert_eq!(multi["coordinates"][1].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_geojson_skips_geometry_outside_wgs84() {
+        let mercator = FeatureSet {
+            features: vec![
+                feature(
+                    "projected",
+                    Some(Geometry::Point {
+                        x: -13_525_000.0,
+                        y: 4_665_000.0,
+                    }),
+                ),
+                Feature {
+                    geometry: Some(Geometry::Point {
+                        x: -13_525_000.0,
+                        y: 4_665_000.0,
+                    }),
+                    ..Incident::default().into()
+                },
+            ],
+            extra: serde_json::from_value(json!({
+                "spatialReference": {"wkid": 102100, "latestWkid": 3857}
+            }))
+            .unwrap(),
+            ..Default::default()
+        };
+        let gj = to_geojson(&mercator);
+        assert_eq!(
+            gj["features"][0]["geometry"]["coordinates"],
+            json!([-121.5, 38.6])
+        );
+        assert_eq!(gj["features"][1]["geometry"], Value::Null);
+
+        let mut wgs84 = mercator;
+        wgs84.extra["spatialReference"] = json!({"wkid": 4326});
+        wgs84.features[0].geometry = Some(Geometry::Point { x: -121.0, y: 38.0 });
+        let gj = to_geojson(&wgs84);
+        assert_eq!(
+            gj["features"][0]["geometry"]["coordinates"],
+            json!([-121.0, 38.0])
+        );
+    }
+
+    #[test]
+    fn test_csv_neutralizes_formulas() {
+        let mut f = feature("=HYPERLINK(\"http://x\")", None);
+        f.attributes
+            .extra
+            .insert("FireCause".to_string(), "@SUM(A1)".into());
+        let fs = FeatureSet {
+            features: vec![f],
+            ..Default::default()
+        };
+        let csv = to_csv(
+            &fs,
+            &[
+                "IncidentName",
+                "FireCause",
+                "InitialLongitude",
+                "DailyAcres",
+            ],
+        );
+        assert_eq!(
+            csv.lines().nth(1).unwrap(),
+            "\"'=HYPERLINK(\"\"http://x\"\")\",'@SUM(A1),-121.5,12.5"
+        );
+    }
+
+    #[test]
+    fn test_csv_columns_and_quoting() {
+        let fs = FeatureSet {
+            features: vec![feature("Smith, \"Big\" Fire", None)],
+            ..Default::default()
+        };
+        let csv = to_csv(&fs, &["IncidentName", "DailyAcres", "FireCause", "Missing"]);
+        assert_eq!(
+            csv,
+            "IncidentName,DailyAcres,FireCause,Missing\r\n\
+             \"Smith, \"\"Big\"\" Fire\",12.5,Human,\r\n"
+        );
+        let default = to_csv(&fs, &[]);
+        assert!(default.starts_with("IrwinID,IncidentName,"));
+    }
+}
@@ -0,0 +1,289 @@
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::error::IrwinError;
+
+/// An ArcGIS feature set as returned by the IRWIN incidents query
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeatureSet {
+    /// Matching incident features
+    #[serde(default)]
+    pub features: Vec<Feature>,
+
+    /// More records matched than the server returned in this response
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exceeded_transfer_limit: bool,
+
+    /// Cursor for the next incremental sync (`includeLastSyncDateTime=true`), epoch ms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sync_date_time: Option<i64>,
+
+    /// Any other top-level members (geometryType, spatialReference, fields, ...)
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl FeatureSet {
+    /// Parse a saved ArcGIS query response
+    pub fn from_json(text: &str) -> Result<Self, IrwinError> {
+        parse_response(text)
+    }
+}
+
+/// One incident record plus its optional geometry
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Feature {
+    pub attributes: Incident,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub geometry: Option<Geometry>,
+
+    /// IRWIN extensions attached to the feature (resources, relationships, ...)
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Feature {
+    /// Point of origin as `(lat, lon)`, falling back to a point geometry
+    pub fn location(&self) -> Option<(f64, f64)> {
+        self.attributes.poo().or(match &self.geometry {
+            Some(Geometry::Point { x, y }) => Some((*y, *x)),
+            _ => None,
+        })
+    }
+}
+
+/// A feature with no geometry or extensions
+impl From<Incident> for Feature {
+    fn from(attributes: Incident) -> Self {
+        Feature {
+            attributes,
+            geometry: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// IRWIN incident attributes
+///
+/// Only the fields callers commonly use are typed; every other attribute the
+/// query returned is kept in `extra`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    #[serde(rename = "IrwinID", default, skip_serializing_if = "Option::is_none")]
+    pub irwin_id: Option<String>,
+
+    #[serde(
+        rename = "IncidentName",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub incident_name: Option<String>,
+
+    #[serde(
+        rename = "UniqueFireIdentifier",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub unique_fire_identifier: Option<String>,
+
+    /// Point of origin latitude, decimal degrees
+    #[serde(
+        rename = "InitialLatitude",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub poo_latitude: Option<f64>,
+
+    /// Point of origin longitude, decimal degrees
+    #[serde(
+        rename = "InitialLongitude",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub poo_longitude: Option<f64>,
+
+    #[serde(
+        rename = "DailyAcres",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub daily_acres: Option<f64>,
+
+    #[serde(
+        rename = "PercentContained",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub percent_contained: Option<f64>,
+
+    /// Epoch milliseconds
+    #[serde(
+        rename = "ModifiedOnDateTime",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub modified_on_date_time: Option<i64>,
+
+    /// ArcGIS sends this as 0/1; `true`/`false` is accepted too
+    #[serde(
+        rename = "IsValid",
+        default,
+        deserialize_with = "flag",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub is_valid: Option<bool>,
+
+    /// `FI` for fires
+    #[serde(
+        rename = "IncidentTypeKind",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub incident_type_kind: Option<String>,
+
+    /// Every other attribute returned, untouched
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Incident {
+    /// Point of origin as `(lat, lon)`
+    pub fn poo(&self) -> Option<(f64, f64)> {
+        Some((self.poo_latitude?, self.poo_longitude?))
+    }
+
+    /// IrwinID without braces, lowercased, for use as a stable key
+    pub fn normalized_irwin_id(&self) -> Option<String> {
+        self.irwin_id
+            .as_deref()
+            .map(|id| id.trim_matches(['{', '}']).to_lowercase())
+    }
+
+    pub fn is_fire(&self) -> bool {
+        self.incident_type_kind.as_deref() == Some("FI")
+    }
+}
+
+/// A closed polygon ring of `[x, y]` (lon, lat in WGS84) vertices; ArcGIS
+/// winds outer rings clockwise and holes counter-clockwise
+pub type Ring = Vec<[f64; 2]>;
+
+/// Feature geometry; incidents are points, other layers may return polygons
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Geometry {
+    Point { x: f64, y: f64 },
+    Polygon { rings: Vec<Ring> },
+    Other(Map<String, Value>),
+}
+
+/// ArcGIS error object, which is returned with HTTP 200
+#[derive(Debug, Deserialize)]
+struct ArcGisError {
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    details: Vec<Value>,
+}
+
+/// Parse an ArcGIS JSON response, turning `{"error": {...}}` bodies into
+/// [`IrwinError::ApiError`]
+pub(crate) fn parse_response<T: DeserializeOwned>(text: &str) -> Result<T, IrwinError> {
+    let mut value: Value = serde_json::from_str(text)?;
+    if let Some(err) = value.as_object_mut().and_then(|o| o.remove("error")) {
+        let err: ArcGisError = serde_json::from_value(err)?;
+        let mut message = err.message.unwrap_or_else(|| "unknown error".to_string());
+        let details: Vec<String> = err
+            .details
+            .iter()
+            .filter_map(|d| d.as_str().map(str::to_string))
+            .filter(|d| !d.is_empty())
+            .collect();
+        if !details.is_empty() {
+            message = format!("{} ({})", message, details.join("; "));
+        }
+        return Err(IrwinError::ApiError {
+            status: err.code.and_then(|c| u16::try_from(c).ok()).unwrap_or(0),
+            message,
+        });
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+fn flag<'de, D: Deserializer<'de>>(d: D) -> Result<Option<bool>, D::Error> {
+    Ok(match Option::<Value>::deserialize(d)? {
+        Some(Value::Bool(b)) => Some(b),
+        Some(Value::Number(n)) => n.as_f64().map(|v| v != 0.0),
+        Some(Value::String(s)) => match s.as_str() {
+            "1" | "true" | "True" | "Y" => Some(true),
+            "0" | "false" | "False" | "N" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_feature_set_keeps_unknown_fields() {
+        let body = r#"{
+            "geometryType": "esriGeometryPoint",
+            "features": [{
+                "attributes": {
+                    "IrwinID": "{ABC-1}",
+                    "IncidentName": "Ridge",
+                    "UniqueFireIdentifier": "2024-CAXXX-000123",
+                    "InitialLatitude": 38.6,
+                    "InitialLongitude": -121.5,
+                    "DailyAcres": 1200.5,
+                    "PercentContained": 10,
+                    "ModifiedOnDateTime": 1700000000000,
+                    "IsValid": 1,
+                    "IncidentTypeKind": "FI",
+                    "FireOutDateTime": null
+                },
+                "geometry": {"x": -121.5, "y": 38.6}
+            }],
+            "nextSyncDateTime": 1700000100000
+        }"#;
+        let fs: FeatureSet = parse_response(body).unwrap();
+        assert_eq!(fs.next_sync_date_time, Some(1_700_000_100_000));
+        assert_eq!(fs.extra["geometryType"], "esriGeometryPoint");
+
+        let f = &fs.features[0];
+        let i = &f.attributes;
+        assert_eq!(i.normalized_irwin_id().as_deref(), Some("abc-1"));
+        assert_eq!(i.daily_acres, Some(1200.5));
+        assert_eq!(i.percent_contained, Some(10.0));
+        assert_eq!(i.is_valid, Some(true));
+        assert!(i.is_fire());
+        assert!(i.extra.contains_key("FireOutDateTime"));
+        assert_eq!(f.geometry, Some(Geometry::Point { x: -121.5, y: 38.6 }));
+        assert_eq!(f.location(), Some((38.6, -121.5)));
+
+        // unknown attributes survive a round trip
+        let back = serde_json::to_value(&fs).unwrap();
+        assert!(back["features"][0]["attributes"]["FireOutDateTime"].is_null());
+        assert_eq!(back["geometryType"], "esriGeometryPoint");
+    }
+
+    #[test]
+    fn test_error_body_is_api_error() {
+        let body = r#"{"error": {"code": 498, "message": "Invalid token.", "details": []}}"#;
+        match parse_response::<FeatureSet>(body) {
+            Err(IrwinError::ApiError { status, message }) => {
+                assert_eq!(status, 498);
+                assert_eq!(message, "Invalid token.");
+            }
+            other => panic!("expected ApiError, got {:?}", other),
+        }
+    }
+}
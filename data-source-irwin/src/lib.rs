@@ -1,20 +1,49 @@
 use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::Client;
+use std::collections::HashSet;
 use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
 
 pub mod error;
+pub mod export;
+pub mod incident;
+pub mod pagination;
+pub mod perimeter;
+pub mod spatial;
+#[cfg(feature = "sqlite")]
+pub mod store;
+pub mod sync;
+#[cfg(test)]
+mod test_server;
+mod token;
 pub mod types;
+pub mod where_clause;
 
 use error::IrwinError;
+use incident::parse_response;
+use token::{parse_token_response, CachedToken, TokenCache, TOKEN_EXPIRATION_MINUTES};
 
 // Re-export types for public API
-pub use types::environments;
+pub use export::{to_csv, to_geojson};
+pub use incident::{Feature, FeatureSet, Geometry, Incident, Ring};
+pub use pagination::IncidentPages;
+pub use perimeter::{DistanceSource, FireDistance, FiresNear};
+pub use spatial::{DistanceUnit, NearbyIncident, SpatialRel};
+#[cfg(feature = "sqlite")]
+pub use store::SqliteIncidentStore;
+pub use sync::{IncidentStore, IncidentSync, PartialSync, SyncCursor, SyncEvent};
+pub use types::{environments, layers};
 pub use types::{IncidentQuery, IncidentQueryBuilder, IrwinConfig};
+pub use where_clause::{Literal, WhereClause};
 
-/// A stateless client for the IRWIN Incidents API
+/// A client for the IRWIN Incidents API
+///
+/// The only state it keeps is the authentication token, which is reused until
+/// shortly before it expires. Share one client (e.g. in an `Arc`) across tasks.
 pub struct IrwinClient {
     config: IrwinConfig,
     http_client: Client,
+    token: TokenCache,
 }
 
 impl IrwinClient {
@@ -31,6 +60,7 @@ impl IrwinClient {
         Ok(Self {
             config,
             http_client,
+            token: TokenCache::default(),
         })
     }
 
@@ -51,109 +81,204 @@ impl IrwinClient {
     }
 
     /// Query incidents using the provided query parameters
-    pub async fn query_incidents(&self, query: &IncidentQuery) -> Result<String, IrwinError> {
-        let mut params = Vec::new();
-
-        // Add required parameters
-        params.push(("f".to_string(), "json".to_string()));
-
-        // Add query parameters
-        if let Some(where_clause) = &query.where_clause {
-            params.push(("where".to_string(), where_clause.clone()));
-        }
-
-        if let Some(out_fields) = &query.out_fields {
-            params.push(("outFields".to_string(), out_fields.clone()));
-        }
-
-        if let Some(return_geometry) = &query.return_geometry {
-            params.push(("returnGeometry".to_string(), return_geometry.clone()));
-        }
-
-        // Add IRWIN-specific extensions
-        if query.include_ads_status {
-            params.push(("includeADSStatus".to_string(), "true".to_string()));
-        }
-
-        if query.include_resources {
-            params.push(("includeResources".to_string(), "true".to_string()));
-        }
-
-        if query.include_relationships {
-            params.push(("includeRelationships".to_string(), "true".to_string()));
-        }
-
-        if query.include_last_sync_date_time {
-            params.push(("includeLastSyncDateTime".to_string(), "true".to_string()));
-        }
+    ///
+    /// Returns a single page: when `exceeded_transfer_limit` is set there are
+    /// more records. Use [`query_all_incidents`](Self::query_all_incidents)
+    /// or [`incident_pages`](Self::incident_pages) to get them all.
+    pub async fn query_incidents(&self, query: &IncidentQuery) -> Result<FeatureSet, IrwinError> {
+        self.query_layer(layers::INCIDENTS, query).await
+    }
 
-        if query.include_ffr {
-            params.push(("includeFFR".to_string(), "true".to_string()));
+    /// Query one page of any feature layer (e.g. [`layers::PERIMETERS`]),
+    /// given by its path relative to the base URL
+    pub async fn query_layer(
+        &self,
+        layer: &str,
+        query: &IncidentQuery,
+    ) -> Result<FeatureSet, IrwinError> {
+        let token = self.token().await?;
+        match self.query_with_token(layer, query, &token).await {
+            // revoked or expired early: one more try with a fresh token
+            Err(e) if e.is_invalid_token() => {
+                self.token.invalidate(&token).await;
+                let token = self.token().await?;
+                self.query_with_token(layer, query, &token).await
+            }
+            other => other,
         }
+    }
 
-        // Generate token and add to params
-        let token = self.generate_token().await?;
-        params.push(("token".to_string(), token));
-
-        // Build URL with query parameters
-        let mut url = format!(
-            "{}/Irwin/Incidents/FeatureServer/0/query",
-            self.config.base_url
-        );
-        let query_string: String = params
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect::<Vec<_>>()
-            .join("&");
-
-        url.push_str(&format!("?{}", query_string));
+    async fn query_with_token(
+        &self,
+        layer: &str,
+        query: &IncidentQuery,
+        token: &str,
+    ) -> Result<FeatureSet, IrwinError> {
+        let mut params = vec![("f".to_string(), "json".to_string())];
+        params.extend(query.params());
+        params.push(("token".to_string(), token.to_string()));
 
-        // Make the request
-        let response = self.http_client.get(&url).send().await?;
+        let url = encoded_url(&self.config.base_url, &format!("{}/query", layer), &params)?;
+        let response = self.http_client.get(url).send().await?;
 
         if response.status().is_success() {
             let text = response.text().await?;
-            Ok(text)
+            parse_response(&text)
         } else {
             Err(IrwinError::ApiError {
                 status: response.status().as_u16(),
-                message: format!("Failed to query incidents: {}", response.status()),
+                message: format!("Failed to query {}: {}", layer, response.status()),
             })
         }
     }
 
     /// Query incidents by IRWIN IDs
-    pub async fn query_by_irwin_ids(&self, irwin_ids: &[String]) -> Result<String, IrwinError> {
-        let where_clause = format!("IrwinID IN ({})", irwin_ids.join(","));
+    pub async fn query_by_irwin_ids(&self, irwin_ids: &[String]) -> Result<FeatureSet, IrwinError> {
         let query = IncidentQueryBuilder::new()
-            .where_clause(&where_clause)
+            .filter(&WhereClause::in_list("IrwinID", irwin_ids))
             .build();
 
-        self.query_incidents(&query).await
+        self.query_all_incidents(&query).await
     }
 
     /// Query incidents by unique fire identifiers
     pub async fn query_by_unique_fire_identifiers(
         &self,
         fire_ids: &[String],
-    ) -> Result<String, IrwinError> {
-        let where_clause = format!("UniqueFireIdentifier IN ({})", fire_ids.join(","));
+    ) -> Result<FeatureSet, IrwinError> {
         let query = IncidentQueryBuilder::new()
-            .where_clause(&where_clause)
+            .filter(&WhereClause::in_list("UniqueFireIdentifier", fire_ids))
             .build();
 
-        self.query_incidents(&query).await
+        self.query_all_incidents(&query).await
     }
 
-    /// Sync incidents since a specific timestamp
-    pub async fn sync_since(&self, timestamp: u64) -> Result<String, IrwinError> {
-        let where_clause = format!("ModifiedOnDateTime >= {}", timestamp);
+    /// Sync incidents modified at or after `timestamp` (epoch milliseconds)
+    ///
+    /// A one-shot query; [`IncidentSync`] keeps the cursor and reports changes.
+    pub async fn sync_since(&self, timestamp: u64) -> Result<FeatureSet, IrwinError> {
         let query = IncidentQueryBuilder::new()
-            .where_clause(&where_clause)
+            .filter(&modified_since(timestamp))
             .include_last_sync_date_time(true)
             .build();
 
-        self.query_incidents(&query).await
+        self.query_all_incidents(&query).await
+    }
+
+    /// Incidents within `radius_mi` statute miles of a WGS84 point, nearest
+    /// first, each with its great-circle distance
+    pub async fn incidents_near(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_mi: f64,
+    ) -> Result<Vec<NearbyIncident>, IrwinError> {
+        let query = IncidentQueryBuilder::new()
+            .near(lat, lon, radius_mi)
+            .return_geometry("true")
+            .build();
+        let features = self.query_all_incidents(&query).await?;
+        Ok(spatial::nearby(features, lat, lon, radius_mi))
+    }
+
+    /// Incidents inside a WGS84 polygon; rings are `[lon, lat]` pairs
+    pub async fn incidents_in_polygon(&self, rings: Vec<Ring>) -> Result<FeatureSet, IrwinError> {
+        let query = IncidentQueryBuilder::new()
+            .within_polygon(rings)
+            .return_geometry("true")
+            .build();
+        self.query_all_incidents(&query).await
+    }
+
+    /// Perimeters matching `query`, all pages, from the configured
+    /// perimeter layer
+    pub async fn query_perimeters(&self, query: &IncidentQuery) -> Result<FeatureSet, IrwinError> {
+        self.layer_pages(&self.config.perimeter_layer, query)
+            .collect()
+            .await
+    }
+
+    /// Perimeters of the given incidents; fails if the configured
+    /// `perimeter_irwin_id_field` isn't a plain field name
+    pub async fn perimeters_for(&self, irwin_ids: &[String]) -> Result<FeatureSet, IrwinError> {
+        let filter = WhereClause::try_in_list(&self.config.perimeter_irwin_id_field, irwin_ids)?;
+        let query = IncidentQueryBuilder::new()
+            .filter(&filter)
+            .return_geometry("true")
+            .out_sr(spatial::WGS84)
+            .build();
+        self.query_perimeters(&query).await
+    }
+
+    /// Fires within `radius_mi` of a WGS84 point, nearest first, measured to
+    /// the perimeter where one is mapped and to the point of origin otherwise
+    ///
+    /// Fires whose perimeter reaches into the radius are included even when
+    /// their point of origin lies outside it. If the perimeter layer can't be
+    /// queried, every fire is measured to its point of origin instead and the
+    /// error comes back as `perimeter_error`.
+    pub async fn fires_near(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_mi: f64,
+    ) -> Result<FiresNear, IrwinError> {
+        let near = IncidentQueryBuilder::new()
+            .near(lat, lon, radius_mi)
+            .return_geometry("true")
+            .build();
+        let mut incidents = self.query_all_incidents(&near).await?.features;
+        // perimeters only sharpen the answer; the incidents are enough for one
+        let (perimeters, perimeter_error) = match self.query_perimeters(&near).await {
+            Ok(perimeters) => (perimeters.features, None),
+            Err(e) => (Vec::new(), Some(e)),
+        };
+
+        let known: HashSet<String> = incidents
+            .iter()
+            .filter_map(|f| f.attributes.normalized_irwin_id())
+            .collect();
+        let missing: Vec<String> = perimeters
+            .iter()
+            .filter_map(|p| perimeter::perimeter_irwin_id(p, &self.config.perimeter_irwin_id_field))
+            .filter(|id| !known.contains(id))
+            .map(|id| format!("{{{}}}", id.to_uppercase()))
+            .collect();
+        if !missing.is_empty() {
+            incidents.extend(self.query_by_irwin_ids(&missing).await?.features);
+        }
+
+        let mut fires = perimeter::fire_distances(
+            lat,
+            lon,
+            &incidents,
+            &perimeters,
+            &self.config.perimeter_irwin_id_field,
+        );
+        fires.retain(|f| f.distance_mi <= radius_mi);
+        Ok(FiresNear {
+            fires,
+            perimeter_error,
+        })
+    }
+
+    /// Query incidents, following pagination until the server reports no
+    /// more records
+    pub async fn query_all_incidents(
+        &self,
+        query: &IncidentQuery,
+    ) -> Result<FeatureSet, IrwinError> {
+        self.incident_pages(query).collect().await
+    }
+
+    /// Page through all results of `query` one page at a time
+    pub fn incident_pages(&self, query: &IncidentQuery) -> IncidentPages<'_> {
+        self.layer_pages(layers::INCIDENTS, query)
+    }
+
+    /// Page through all results of `query` against another layer
+    pub fn layer_pages(&self, layer: &str, query: &IncidentQuery) -> IncidentPages<'_> {
+        IncidentPages::new(self, layer, query.clone())
     }
 
     /// Get current timestamp in milliseconds
@@ -164,46 +289,39 @@ impl IrwinClient {
             .as_millis() as u64
     }
 
-    /// Generate an authentication token
-    async fn generate_token(&self) -> Result<String, IrwinError> {
-        let mut params = Vec::new();
-        params.push(("username".to_string(), self.config.username.clone()));
-        params.push(("password".to_string(), self.config.password.clone()));
-        params.push(("client".to_string(), "referer".to_string()));
-        params.push(("referer".to_string(), self.config.referer.clone()));
-        params.push(("expiration".to_string(), "60".to_string()));
-        params.push(("f".to_string(), "json".to_string()));
-
-        let url = format!("{}/tokens/generateToken", self.config.base_url);
-
-        // Build form data manually since we don't have the form feature
-        let body = params
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect::<Vec<_>>()
-            .join("&");
+    /// The cached token, or a new one if it is missing or about to expire
+    async fn token(&self) -> Result<String, IrwinError> {
+        self.token
+            .get_or_refresh(Self::current_timestamp_ms(), || self.generate_token())
+            .await
+    }
 
+    /// Generate an authentication token
+    async fn generate_token(&self) -> Result<CachedToken, IrwinError> {
+        let params = [
+            ("username".to_string(), self.config.username.clone()),
+            ("password".to_string(), self.config.password.clone()),
+            ("client".to_string(), "referer".to_string()),
+            ("referer".to_string(), self.config.referer.clone()),
+            (
+                "expiration".to_string(),
+                TOKEN_EXPIRATION_MINUTES.to_string(),
+            ),
+            ("f".to_string(), "json".to_string()),
+        ];
+
+        let url = encoded_url(&self.config.base_url, "/tokens/generateToken", &[])?;
         let response = self
             .http_client
-            .post(&url)
+            .post(url)
             .header("Content-Type", "application/x-www-form-urlencoded")
-            .body(body)
+            .body(form_body(&params))
             .send()
             .await?;
 
         if response.status().is_success() {
             let text = response.text().await?;
-            // Parse the JSON response to extract the token
-            let token_response: serde_json::Value = serde_json::from_str(&text)?;
-
-            if let Some(token) = token_response["token"].as_str() {
-                Ok(token.to_string())
-            } else {
-                Err(IrwinError::ApiError {
-                    status: 0,
-                    message: "No token in response".to_string(),
-                })
-            }
+            parse_token_response(&text, Self::current_timestamp_ms())
         } else {
             Err(IrwinError::ApiError {
                 status: response.status().as_u16(),
@@ -213,6 +331,29 @@ impl IrwinClient {
     }
 }
 
+/// `ModifiedOnDateTime >= timestamp '...'`, the incremental sync filter
+pub fn modified_since(timestamp_ms: u64) -> WhereClause {
+    WhereClause::gte(
+        "ModifiedOnDateTime",
+        Literal::datetime_ms(i64::try_from(timestamp_ms).unwrap_or(i64::MAX)),
+    )
+}
+
+/// `base` + `path` with every parameter percent-encoded into the query string
+fn encoded_url(base: &str, path: &str, params: &[(String, String)]) -> Result<Url, IrwinError> {
+    Ok(Url::parse_with_params(
+        &format!("{}{}", base, path),
+        params,
+    )?)
+}
+
+/// `application/x-www-form-urlencoded` body for `params`
+fn form_body(params: &[(String, String)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -246,4 +387,141 @@ mod tests {
         assert!(query.include_resources);
         assert!(query.include_relationships);
     }
+
+    #[test]
+    fn test_params_are_percent_encoded() {
+        let params = vec![
+            (
+                "where".to_string(),
+                "IncidentName = 'A&B' AND x>=1".to_string(),
+            ),
+            ("token".to_string(), "t+k/=".to_string()),
+        ];
+        let url = encoded_url(
+            environments::TEST,
+            "/Irwin/Incidents/FeatureServer/0/query",
+            &params,
+        )
+        .unwrap();
+        assert_eq!(
+            url.query(),
+            Some("where=IncidentName+%3D+%27A%26B%27+AND+x%3E%3D1&token=t%2Bk%2F%3D")
+        );
+        let round_trip: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+        assert_eq!(round_trip, params);
+
+        let body = form_body(&[
+            ("username".to_string(), "svc".to_string()),
+            ("password".to_string(), "p&ss=w0rd %".to_string()),
+        ]);
+        assert_eq!(body, "username=svc&password=p%26ss%3Dw0rd+%25");
+    }
+
+    #[test]
+    fn test_filter_sets_where_clause() {
+        let query = IncidentQueryBuilder::new()
+            .filter(&modified_since(0))
+            .build();
+        assert_eq!(
+            query.where_clause.as_deref(),
+            Some("ModifiedOnDateTime >= timestamp '1970-01-01 00:00:00'")
+        );
+    }
+
+    #[test]
+    fn test_spatial_params() {
+        let query = IncidentQueryBuilder::new().near(38.6, -121.5, 25.0).build();
+        let params = query.params();
+        let get = |k: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == k)
+                .map(|(_, v)| v.as_str())
+        };
+
+        assert_eq!(get("geometry"), Some(r#"{"x":-121.5,"y":38.6}"#));
+        assert_eq!(get("geometryType"), Some("esriGeometryPoint"));
+        assert_eq!(get("spatialRel"), Some("esriSpatialRelIntersects"));
+        assert_eq!(get("distance"), Some("25"));
+        assert_eq!(get("units"), Some("esriSRUnit_StatuteMile"));
+        assert_eq!(get("inSR"), Some("4326"));
+        assert_eq!(get("outSR"), Some("4326"));
+
+        let query = IncidentQueryBuilder::new()
+            .within_polygon(vec![vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 0.0]]])
+            .build();
+        let params = query.params();
+        assert!(params.contains(&(
+            "geometry".to_string(),
+            "{\"rings\":[[[0.0,0.0],[1.0,0.0],[1.0,1.0],[0.0,0.0]]]}".to_string()
+        )));
+        assert!(params.contains(&(
+            "geometryType".to_string(),
+            "esriGeometryPolygon".to_string()
+        )));
+        assert!(!params.iter().any(|(k, _)| k == "distance"));
+    }
+
+    #[test]
+    fn test_paging_params() {
+        let query = IncidentQueryBuilder::new()
+            .where_clause("IsValid=1")
+            .order_by_fields("ModifiedOnDateTime ASC")
+            .result_offset(2000)
+            .result_record_count(1000)
+            .build();
+        let params = query.params();
+        let get = |k: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == k)
+                .map(|(_, v)| v.as_str())
+        };
+
+        assert_eq!(get("orderByFields"), Some("ModifiedOnDateTime ASC"));
+        assert_eq!(get("resultOffset"), Some("2000"));
+        assert_eq!(get("resultRecordCount"), Some("1000"));
+        assert_eq!(get("includeResources"), None);
+    }
+
+    #[tokio::test]
+    async fn test_fires_near_falls_back_to_point_of_origin_without_perimeters() {
+        let incidents = r#"{"features": [{"attributes": {
+            "IrwinID": "{A}", "InitialLatitude": 38.6, "InitialLongitude": -121.0
+        }}]}"#;
+        let (url, queries) = test_server::serve(vec![
+            incidents.to_string(),
+            r#"{"error": {"code": 400, "message": "Invalid layer"}}"#.to_string(),
+        ])
+        .await;
+
+        let found = test_server::client(&url)
+            .fires_near(38.5, -121.0, 25.0)
+            .await
+            .unwrap();
+        assert!(matches!(
+            found.perimeter_error,
+            Some(IrwinError::ApiError { status: 400, .. })
+        ));
+        let fires = found.fires;
+        assert_eq!(fires.len(), 1);
+        assert_eq!(fires[0].source, DistanceSource::PointOfOrigin);
+        assert!((fires[0].distance_mi - 6.9).abs() < 0.1);
+        assert!(queries.lock().unwrap()[1].contains(layers::PERIMETERS));
+    }
+
+    #[tokio::test]
+    async fn test_perimeters_for_rejects_a_bad_configured_field() {
+        let (url, queries) = test_server::serve(vec![]).await;
+        let mut config = IrwinConfig::new(url, "user".into(), "pass".into(), "referer".into());
+        config.perimeter_irwin_id_field = "IrwinID) OR (1=1".into();
+
+        let err = IrwinClient::new(config)
+            .unwrap()
+            .perimeters_for(&["{A}".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, IrwinError::RequestError(_)));
+        assert!(queries.lock().unwrap().is_empty());
+    }
 }
@@ -1,31 +1,103 @@
-use data_source_irwin::{environments, IncidentQueryBuilder, IrwinConfig};
+use data_source_irwin::{to_csv, to_geojson, FeatureSet};
+use std::io::Read;
+use std::process::ExitCode;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    println!("IRWIN Client Demo");
+const USAGE: &str = "\
+Convert a saved IRWIN query response (ArcGIS JSON) for GIS tools
 
-    // Example configuration (you would need real credentials)
-    let config = IrwinConfig::new(
-        environments::TEST.to_string(),
-        "your_username".to_string(),
-        "your_password".to_string(),
-        "your_referer".to_string(),
-    );
+Usage:
+    data-source-irwin export geojson [INPUT]
+    data-source-irwin export csv [--columns A,B,...] [INPUT]
 
-    println!("Configuration created successfully");
+INPUT defaults to stdin; output goes to stdout. CSV columns are IRWIN
+attribute names (default: IrwinID, IncidentName, UniqueFireIdentifier, ...).";
 
-    // Example query
-    let query = IncidentQueryBuilder::new()
-        .where_clause("IsValid=1 AND IncidentTypeKind='FI'")
-        .out_fields("IrwinID,IncidentName,UniqueFireIdentifier")
-        .return_geometry("true")
-        .include_resources(true)
-        .include_relationships(true)
-        .build();
+enum Format {
+    GeoJson,
+    Csv { columns: Vec<String> },
+}
 
-    println!("Query built: {:?}", query);
+struct Export {
+    format: Format,
+    input: Option<String>,
+}
 
-    // Note: This would require real credentials to actually work
-    println!("Client is ready for use with valid credentials");
+fn parse_args(args: &[String]) -> Result<Export, String> {
+    let (format, rest) = match args {
+        [cmd, format, rest @ ..] if cmd == "export" => (format.as_str(), rest),
+        _ => return Err("expected `export <geojson|csv>`".to_string()),
+    };
+    let mut columns = Vec::new();
+    let mut input = None;
+    let mut rest = rest.iter();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--columns" if format == "csv" => {
+                let list = rest.next().ok_or("--columns needs a value")?;
+                columns = list
+                    .split(',')
+                    .map(|c| c.trim().to_string())
+                    .filter(|c| !c.is_empty())
+                    .collect();
+            }
+            a if a.starts_with('-') && a != "-" => return Err(format!("unknown option {}", a)),
+            a if input.is_none() => input = Some(a.to_string()).filter(|a| a != "-"),
+            a => return Err(format!("unexpected argument {}", a)),
+        }
+    }
+    let format = match format {
+        "geojson" => Format::GeoJson,
+        "csv" => Format::Csv { columns },
+        other => {
+            return Err(format!(
+                "unknown format {} (expected geojson or csv)",
+                other
+            ))
+        }
+    };
+    Ok(Export { format, input })
+}
+
+fn run(export: Export) -> Result<String, Box<dyn std::error::Error>> {
+    let text = match &export.input {
+        Some(path) => std::fs::read_to_string(path)?,
+        None => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    let features = FeatureSet::from_json(&text)?;
+    Ok(match export.format {
+        Format::GeoJson => serde_json::to_string_pretty(&to_geojson(&features))? + "\n",
+        Format::Csv { columns } => {
+            let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+            to_csv(&features, &columns)
+        }
+    })
+}
 
-    Ok(())
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if matches!(args.first().map(String::as_str), Some("-h" | "--help")) {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let export = match parse_args(&args) {
+        Ok(export) => export,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(export) {
+        Ok(out) => {
+            print!("{}", out);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
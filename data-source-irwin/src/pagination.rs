@@ -0,0 +1,220 @@
+use std::collections::HashSet;
+
+use crate::error::IrwinError;
+use crate::incident::{Feature, FeatureSet};
+use crate::types::IncidentQuery;
+use crate::IrwinClient;
+
+/// Sort order used when paging a query that doesn't set one; without a
+/// stable order the server may repeat or skip records between pages
+pub const DEFAULT_PAGE_ORDER: &str = "OBJECTID ASC";
+
+/// Row id field every page is checked against for repeats
+const OBJECT_ID_FIELD: &str = "OBJECTID";
+
+/// Walks every page of a layer query, following `exceededTransferLimit`
+///
+/// Created by [`IrwinClient::incident_pages`]. Each call to
+/// [`next_page`](Self::next_page) fetches one page; `None` means the server
+/// reported no more records (or the previous page failed). A page that
+/// repeats an `OBJECTID` already returned fails with
+/// [`IrwinError::PaginationError`] rather than looping on a server that
+/// ignores `resultOffset`.
+pub struct IncidentPages<'a> {
+    client: &'a IrwinClient,
+    layer: String,
+    query: IncidentQuery,
+    offset: u64,
+    seen: HashSet<i64>,
+    done: bool,
+}
+
+impl<'a> IncidentPages<'a> {
+    pub(crate) fn new(client: &'a IrwinClient, layer: &str, mut query: IncidentQuery) -> Self {
+        if query.order_by_fields.is_none() {
+            query.order_by_fields = Some(DEFAULT_PAGE_ORDER.to_string());
+        }
+        if let Some(fields) = &mut query.out_fields {
+            let listed = fields
+                .split(',')
+                .any(|f| f.trim() == "*" || f.trim().eq_ignore_ascii_case(OBJECT_ID_FIELD));
+            if !listed {
+                fields.push(',');
+                fields.push_str(OBJECT_ID_FIELD);
+            }
+        }
+        Self {
+            client,
+            layer: layer.to_string(),
+            offset: query.result_offset.unwrap_or(0),
+            query,
+            seen: HashSet::new(),
+            done: false,
+        }
+    }
+
+    /// Fetch the next page
+    pub async fn next_page(&mut self) -> Option<Result<FeatureSet, IrwinError>> {
+        if self.done {
+            return None;
+        }
+        let mut query = self.query.clone();
+        query.result_offset = Some(self.offset);
+        let page = self.client.query_layer(&self.layer, &query).await;
+        let page = page.and_then(|page| self.advance(&page).map(|()| page));
+        if page.is_err() {
+            self.done = true;
+        }
+        Some(page)
+    }
+
+    /// Move past `page`; stop on the last page and on an empty page (a
+    /// server that keeps claiming more but sends nothing), and fail on a page
+    /// repeating records an earlier one returned
+    fn advance(&mut self, page: &FeatureSet) -> Result<(), IrwinError> {
+        let repeated = page
+            .features
+            .iter()
+            .filter_map(object_id)
+            .find(|id| !self.seen.insert(*id));
+        if let Some(id) = repeated {
+            return Err(IrwinError::PaginationError(format!(
+                "page at offset {} repeats {OBJECT_ID_FIELD} {id}",
+                self.offset
+            )));
+        }
+        if page.exceeded_transfer_limit && !page.features.is_empty() {
+            self.offset += page.features.len() as u64;
+        } else {
+            self.done = true;
+        }
+        Ok(())
+    }
+
+    /// Fetch all remaining pages and merge them into one feature set
+    ///
+    /// Top-level members other than `features` come from the first page.
+    pub async fn collect(mut self) -> Result<FeatureSet, IrwinError> {
+        let mut all: Option<FeatureSet> = None;
+        while let Some(page) = self.next_page().await {
+            let page = page?;
+            match &mut all {
+                None => all = Some(page),
+                Some(all) => {
+                    all.features.extend(page.features);
+                    if all.next_sync_date_time.is_none() {
+                        all.next_sync_date_time = page.next_sync_date_time;
+                    }
+                }
+            }
+        }
+        let mut all = all.unwrap_or_default();
+        all.exceeded_transfer_limit = false;
+        Ok(all)
+    }
+}
+
+fn object_id(f: &Feature) -> Option<i64> {
+    f.attributes.extra.get(OBJECT_ID_FIELD)?.as_i64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::Incident;
+    use crate::test_server::{client, serve};
+    use crate::types::layers;
+    use crate::IncidentQueryBuilder;
+    use std::ops::Range;
+
+    fn page(ids: Range<i64>, more: bool) -> FeatureSet {
+        FeatureSet {
+            features: ids
+                .map(|id| {
+                    let mut attributes = Incident::default();
+                    attributes.extra.insert(OBJECT_ID_FIELD.into(), id.into());
+                    Feature::from(attributes)
+                })
+                .collect(),
+            exceeded_transfer_limit: more,
+            ..Default::default()
+        }
+    }
+
+    fn body(page: FeatureSet) -> String {
+        serde_json::to_string(&page).unwrap()
+    }
+
+    #[test]
+    fn test_pages_advance_until_the_server_reports_no_more() {
+        let client = client("http://127.0.0.1:9");
+        let query = IncidentQueryBuilder::new()
+            .out_fields("IrwinID")
+            .result_offset(10)
+            .build();
+        let mut pages = IncidentPages::new(&client, layers::INCIDENTS, query);
+        assert_eq!(
+            pages.query.order_by_fields.as_deref(),
+            Some(DEFAULT_PAGE_ORDER)
+        );
+        assert_eq!(pages.query.out_fields.as_deref(), Some("IrwinID,OBJECTID"));
+        assert_eq!(pages.offset, 10);
+
+        pages.advance(&page(0..2000, true)).unwrap();
+        assert_eq!(pages.offset, 2010);
+        assert!(!pages.done);
+
+        pages.advance(&page(2000..2500, false)).unwrap();
+        assert!(pages.done);
+
+        // an empty page ends paging even if the flag is still set
+        let mut pages = IncidentPages::new(
+            &client,
+            layers::INCIDENTS,
+            IncidentQueryBuilder::new().build(),
+        );
+        pages.advance(&page(0..0, true)).unwrap();
+        assert!(pages.done);
+    }
+
+    #[test]
+    fn test_a_page_repeating_records_is_an_error() {
+        let client = client("http://127.0.0.1:9");
+        let mut pages = IncidentPages::new(
+            &client,
+            layers::INCIDENTS,
+            IncidentQueryBuilder::new().build(),
+        );
+        pages.advance(&page(0..100, true)).unwrap();
+        let err = pages.advance(&page(50..150, true)).unwrap_err();
+        assert!(matches!(err, IrwinError::PaginationError(_)), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_next_page_follows_offsets_until_the_last_page() {
+        let (url, queries) = serve(vec![body(page(0..2, true)), body(page(2..3, false))]).await;
+        let client = client(&url);
+        let mut pages = client.incident_pages(&IncidentQueryBuilder::new().build());
+
+        assert_eq!(pages.next_page().await.unwrap().unwrap().features.len(), 2);
+        assert_eq!(pages.next_page().await.unwrap().unwrap().features.len(), 1);
+        assert!(pages.next_page().await.is_none());
+
+        let queries = queries.lock().unwrap();
+        assert_eq!(queries.len(), 2);
+        assert!(queries[0].contains("resultOffset=0"), "{}", queries[0]);
+        assert!(queries[1].contains("resultOffset=2"), "{}", queries[1]);
+    }
+
+    #[tokio::test]
+    async fn test_collect_fails_when_the_server_ignores_the_offset() {
+        let (url, queries) = serve(vec![body(page(0..2, true)); 5]).await;
+        let client = client(&url);
+        let err = client
+            .query_all_incidents(&IncidentQueryBuilder::new().build())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, IrwinError::PaginationError(_)), "{err}");
+        assert_eq!(queries.lock().unwrap().len(), 2);
+    }
+}
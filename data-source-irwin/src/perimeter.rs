@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::error::IrwinError;
+use crate::incident::{Feature, Geometry, Ring};
+use crate::spatial::{great_circle_mi, EARTH_RADIUS_MI};
+
+/// Perimeter attribute that links a polygon to its incident, unless
+/// `IrwinConfig::perimeter_irwin_id_field` says otherwise
+pub const PERIMETER_IRWIN_ID_FIELD: &str = "IrwinID";
+
+/// What a [`FireDistance`] was measured to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistanceSource {
+    Perimeter,
+    PointOfOrigin,
+}
+
+/// Shortest distance from a point to one fire
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FireDistance {
+    /// Normalized IrwinID
+    pub irwin_id: Option<String>,
+    pub incident: Feature,
+    /// 0 when the point is inside the perimeter
+    pub distance_mi: f64,
+    pub source: DistanceSource,
+}
+
+/// Fires found by [`IrwinClient::fires_near`](crate::IrwinClient::fires_near)
+#[derive(Debug)]
+pub struct FiresNear {
+    /// Nearest first
+    pub fires: Vec<FireDistance>,
+    /// Why the perimeter layer couldn't be queried, in which case every fire
+    /// was measured to its point of origin
+    pub perimeter_error: Option<IrwinError>,
+}
+
+impl Geometry {
+    /// Polygon rings, if this is a polygon
+    pub fn rings(&self) -> Option<&[Ring]> {
+        match self {
+            Geometry::Polygon { rings } => Some(rings),
+            _ => None,
+        }
+    }
+}
+
+/// Normalized IrwinID of a perimeter feature, read from `id_field`
+///
+/// Perimeter layers don't agree on the field name (`IrwinID`, `IRWINID`,
+/// `poly_IRWINID`, ...), so when `id_field` is missing any attribute named
+/// like one is accepted.
+pub fn perimeter_irwin_id(feature: &Feature, id_field: &str) -> Option<String> {
+    let attrs = &feature.attributes;
+    let named = |matches: &dyn Fn(&str) -> bool| {
+        attrs
+            .extra
+            .iter()
+            .find(|(k, _)| matches(&k.to_ascii_lowercase()))
+            .and_then(|(_, v)| v.as_str())
+            .map(|id| id.trim_matches(['{', '}']).to_lowercase())
+    };
+    let id_field = id_field.to_ascii_lowercase();
+    named(&|k| k == id_field)
+        .or_else(|| attrs.normalized_irwin_id())
+        .or_else(|| named(&|k| k == "irwinid" || k.ends_with("_irwinid")))
+}
+
+/// Distance in statute miles from `(lat, lon)` to a WGS84 polygon; 0 inside
+///
+/// Edges are measured in a local flat projection centred on the point,
+/// which is accurate to well under a percent at wildfire distances (up to a
+/// few hundred miles). `None` for a polygon without vertices.
+pub fn distance_to_polygon_mi(lat: f64, lon: f64, rings: &[Ring]) -> Option<f64> {
+    let cos_lat = lat.to_radians().cos();
+    let project = |[x, y]: [f64; 2]| {
+        let dlon = (x - lon + 540.0).rem_euclid(360.0) - 180.0;
+        (
+            dlon.to_radians() * cos_lat * EARTH_RADIUS_MI,
+            (y - lat).to_radians() * EARTH_RADIUS_MI,
+        )
+    };
+
+    let mut inside = false;
+    let mut nearest: Option<f64> = None;
+    for ring in rings {
+        let points: Vec<(f64, f64)> = ring.iter().copied().map(project).collect();
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            // even-odd ray cast from the origin, so holes count as outside
+            if (a.1 > 0.0) != (b.1 > 0.0) && a.0 + (0.0 - a.1) * (b.0 - a.0) / (b.1 - a.1) > 0.0 {
+                inside = !inside;
+            }
+            let d = origin_to_segment(a, b);
+            nearest = Some(nearest.map_or(d, |n| n.min(d)));
+        }
+    }
+    nearest.map(|d| if inside { 0.0 } else { d })
+}
+
+fn origin_to_segment(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (-(a.0 * dx + a.1 * dy) / len2).clamp(0.0, 1.0)
+    };
+    (a.0 + t * dx).hypot(a.1 + t * dy)
+}
+
+/// Distance from `(lat, lon)` to each incident, nearest first
+///
+/// Measured to the closest of the incident's perimeters (matched on
+/// IrwinID, read from each perimeter's `id_field`), falling back to its
+/// point of origin. Incidents with neither are left out.
+pub fn fire_distances(
+    lat: f64,
+    lon: f64,
+    incidents: &[Feature],
+    perimeters: &[Feature],
+    id_field: &str,
+) -> Vec<FireDistance> {
+    let mut to_perimeter: HashMap<String, f64> = HashMap::new();
+    for p in perimeters {
+        let (Some(id), Some(rings)) = (
+            perimeter_irwin_id(p, id_field),
+            p.geometry.as_ref().and_then(Geometry::rings),
+        ) else {
+            continue;
+        };
+        if let Some(d) = distance_to_polygon_mi(lat, lon, rings) {
+            let best = to_perimeter.entry(id).or_insert(d);
+            *best = best.min(d);
+        }
+    }
+
+    let mut fires: Vec<FireDistance> = incidents
+        .iter()
+        .filter_map(|incident| {
+            let irwin_id = incident.attributes.normalized_irwin_id();
+            let (distance_mi, source) = match irwin_id.as_ref().and_then(|id| to_perimeter.get(id))
+            {
+                Some(&d) => (d, DistanceSource::Perimeter),
+                None => (
+                    great_circle_mi((lat, lon), incident.location()?),
+                    DistanceSource::PointOfOrigin,
+                ),
+            };
+            Some(FireDistance {
+                irwin_id,
+                incident: incident.clone(),
+                distance_mi,
+                source,
+            })
+        })
+        .collect();
+    fires.sort_by(|a, b| a.distance_mi.total_cmp(&b.distance_mi));
+    fires
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::Incident;
+
+    /// Square of side `2 * half` degrees centred on (lat, lon), as [lon, lat]
+    fn square(lat: f64, lon: f64, half: f64) -> Ring {
+        vec![
+            [lon - half, lat - half],
+            [lon - half, lat + half],
+            [lon + half, lat + half],
+            [lon + half, lat - half],
+            [lon - half, lat - half],
+        ]
+    }
+
+    #[test]
+    fn test_distance_to_polygon() {
+        let outer = square(38.0, -121.0, 0.5);
+        assert_eq!(
+            distance_to_polygon_mi(38.0, -121.0, std::slice::from_ref(&outer)),
+            Some(0.0)
+        );
+
+        // one degree of latitude north of the top edge, about 69 miles
+        let d = distance_to_polygon_mi(39.5, -121.0, std::slice::from_ref(&outer)).unwrap();
+        assert!((d - 69.1).abs() < 0.5, "{}", d);
+
+        // inside a hole: distance to its east/west edges, 0.1 degree of
+        // longitude at 38N
+        let hole = square(38.0, -121.0, 0.1);
+        let d = distance_to_polygon_mi(38.0, -121.0, &[outer, hole]).unwrap();
+        assert!((d - 5.44).abs() < 0.05, "{}", d);
+
+        assert_eq!(distance_to_polygon_mi(38.0, -121.0, &[]), None);
+    }
+
+    #[test]
+    fn test_fire_distances_prefer_perimeter() {
+        let incident = |id: &str, lat: f64| {
+            Feature::from(Incident {
+                irwin_id: Some(format!("{{{}}}", id)),
+                poo_latitude: Some(lat),
+                poo_longitude: Some(-121.0),
+                ..Default::default()
+            })
+        };
+        let mut perimeter = Feature {
+            geometry: Some(Geometry::Polygon {
+                rings: vec![square(39.0, -121.0, 0.5)],
+            }),
+            ..Incident::default().into()
+        };
+        perimeter
+            .attributes
+            .extra
+            .insert("poly_IRWINID".to_string(), "{A}".into());
+
+        // "a" started 69 miles away but its perimeter reaches the point;
+        // "b" has no perimeter and is measured from its origin
+        let fires = fire_distances(
+            38.5,
+            -121.0,
+            &[incident("A", 39.5), incident("B", 38.6)],
+            &[perimeter],
+            PERIMETER_IRWIN_ID_FIELD,
+        );
+        assert_eq!(fires[0].irwin_id.as_deref(), Some("a"));
+        assert_eq!(fires[0].source, DistanceSource::Perimeter);
+        assert!(fires[0].distance_mi < 0.01);
+        assert_eq!(fires[1].source, DistanceSource::PointOfOrigin);
+        assert!((fires[1].distance_mi - 6.9).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_perimeter_irwin_id_prefers_the_configured_field() {
+        let mut perimeter = Feature::from(Incident::default());
+        let extra = &mut perimeter.attributes.extra;
+        extra.insert("poly_IRWINID".to_string(), "{OLD}".into());
+        extra.insert("FireGUID".to_string(), "{NEW}".into());
+
+        assert_eq!(
+            perimeter_irwin_id(&perimeter, "fireguid").as_deref(),
+            Some("new")
+        );
+        // falls back to anything named like an IrwinID
+        assert_eq!(
+            perimeter_irwin_id(&perimeter, PERIMETER_IRWIN_ID_FIELD).as_deref(),
+            Some("old")
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use serde::Serialize;
+
+use crate::incident::{Feature, FeatureSet, Geometry, Incident};
+
+/// WGS84 latitude/longitude; `inSR`/`outSR` for the convenience queries
+pub const WGS84: u32 = 4326;
+
+/// Mean Earth radius used for great-circle distances
+pub const EARTH_RADIUS_MI: f64 = 3958.8;
+
+/// How the query geometry must relate to a feature for it to match
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpatialRel {
+    Intersects,
+    Contains,
+    Within,
+    EnvelopeIntersects,
+}
+
+impl SpatialRel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpatialRel::Intersects => "esriSpatialRelIntersects",
+            SpatialRel::Contains => "esriSpatialRelContains",
+            SpatialRel::Within => "esriSpatialRelWithin",
+            SpatialRel::EnvelopeIntersects => "esriSpatialRelEnvelopeIntersects",
+        }
+    }
+}
+
+/// Units for the `distance` buffer around the query geometry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DistanceUnit {
+    StatuteMile,
+    NauticalMile,
+    Kilometer,
+    Meter,
+    Foot,
+}
+
+impl DistanceUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceUnit::StatuteMile => "esriSRUnit_StatuteMile",
+            DistanceUnit::NauticalMile => "esriSRUnit_NauticalMile",
+            DistanceUnit::Kilometer => "esriSRUnit_Kilometer",
+            DistanceUnit::Meter => "esriSRUnit_Meter",
+            DistanceUnit::Foot => "esriSRUnit_Foot",
+        }
+    }
+}
+
+impl Geometry {
+    /// ArcGIS `geometryType` for this shape, if it can be told from its members
+    pub fn esri_type(&self) -> Option<&'static str> {
+        match self {
+            Geometry::Point { .. } => Some("esriGeometryPoint"),
+            Geometry::Polygon { .. } => Some("esriGeometryPolygon"),
+            Geometry::Other(m) if m.contains_key("xmin") => Some("esriGeometryEnvelope"),
+            Geometry::Other(m) if m.contains_key("points") => Some("esriGeometryMultipoint"),
+            Geometry::Other(m) if m.contains_key("paths") => Some("esriGeometryPolyline"),
+            Geometry::Other(_) => None,
+        }
+    }
+}
+
+/// An incident with its great-circle distance from the query point
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NearbyIncident {
+    #[serde(flatten)]
+    pub feature: Feature,
+    pub distance_mi: f64,
+}
+
+impl NearbyIncident {
+    pub fn incident(&self) -> &Incident {
+        &self.feature.attributes
+    }
+}
+
+/// Great-circle (haversine) distance in statute miles between two
+/// `(lat, lon)` points in decimal degrees
+pub fn great_circle_mi(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let (dlat, dlon) = (lat2 - lat1, lon2 - lon1);
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_MI * a.sqrt().min(1.0).asin()
+}
+
+/// Features within `radius_mi` of `(lat, lon)`, nearest first
+///
+/// Distance is measured to [`Feature::location`]; features without one are
+/// dropped. The server's buffer is only approximately geodesic, so this is
+/// also what trims its result to the exact radius.
+pub fn nearby(features: FeatureSet, lat: f64, lon: f64, radius_mi: f64) -> Vec<NearbyIncident> {
+    let mut near: Vec<NearbyIncident> = features
+        .features
+        .into_iter()
+        .filter_map(|feature| {
+            let distance_mi = great_circle_mi((lat, lon), feature.location()?);
+            (distance_mi <= radius_mi).then_some(NearbyIncident {
+                feature,
+                distance_mi,
+            })
+        })
+        .collect();
+    near.sort_by(|a, b| a.distance_mi.total_cmp(&b.distance_mi));
+    near
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(name: &str, lat: f64, lon: f64) -> Feature {
+        Incident {
+            incident_name: Some(name.to_string()),
+            poo_latitude: Some(lat),
+            poo_longitude: Some(lon),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_great_circle_distance() {
+        // Sacramento to San Francisco, about 75 miles
+        let d = great_circle_mi((38.5816, -121.4944), (37.7749, -122.4194));
+        assert!((d - 75.3).abs() < 0.5, "{}", d);
+        assert_eq!(great_circle_mi((10.0, 20.0), (10.0, 20.0)), 0.0);
+    }
+
+    #[test]
+    fn test_nearby_trims_and_sorts() {
+        let mut unplaced = at("unplaced", 0.0, 0.0);
+        unplaced.attributes.poo_latitude = None;
+        let fs = FeatureSet {
+            features: vec![
+                at("far", 39.5, -121.5),
+                at("near", 38.61, -121.5),
+                at("mid", 38.8, -121.5),
+                unplaced,
+            ],
+            ..Default::default()
+        };
+        let near = nearby(fs, 38.6, -121.5, 25.0);
+        let names: Vec<_> = near
+            .iter()
+            .map(|n| n.incident().incident_name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["near", "mid"]);
+        assert!(near[0].distance_mi < 1.0);
+    }
+}
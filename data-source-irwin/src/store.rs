@@ -0,0 +1,352 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+
+use crate::error::IrwinError;
+use crate::incident::{Feature, FeatureSet};
+use crate::spatial::{nearby, NearbyIncident};
+use crate::sync::IncidentStore;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS incidents (
+    irwin_id       TEXT PRIMARY KEY,
+    unique_fire_id TEXT,
+    name           TEXT,
+    lat            REAL,
+    lon            REAL,
+    is_valid       INTEGER NOT NULL,
+    modified_ms    INTEGER,
+    record         TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS incidents_unique_fire_id ON incidents (unique_fire_id);
+CREATE INDEX IF NOT EXISTS incidents_name ON incidents (name COLLATE NOCASE);
+CREATE INDEX IF NOT EXISTS incidents_location ON incidents (lat, lon);
+CREATE TABLE IF NOT EXISTS meta (
+    key   TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+";
+
+/// Statute miles per degree of latitude, for the radius pre-filter
+const MI_PER_DEG_LAT: f64 = 69.0;
+
+/// SQLite file of synced incidents that answers queries without network access
+///
+/// Each feature is kept as JSON next to the columns the lookups use. Records
+/// marked invalid stay in the store (so a sync can tell when they come back)
+/// but only [`by_irwin_id`](Self::by_irwin_id) returns them.
+pub struct SqliteIncidentStore {
+    conn: Connection,
+}
+
+impl SqliteIncidentStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IrwinError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> Result<Self, IrwinError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, IrwinError> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Lookup by IrwinID, with or without braces, in any case
+    pub fn by_irwin_id(&self, irwin_id: &str) -> Result<Option<Feature>, IrwinError> {
+        let key = irwin_id.trim_matches(['{', '}']).to_lowercase();
+        self.conn
+            .query_row(
+                "SELECT record FROM incidents WHERE irwin_id = ?1",
+                params![key],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .map(|record| Ok(serde_json::from_str(&record)?))
+            .transpose()
+    }
+
+    pub fn by_unique_fire_identifier(&self, ufi: &str) -> Result<Vec<Feature>, IrwinError> {
+        self.select("unique_fire_id = ?1", params![ufi])
+    }
+
+    /// Incidents whose name contains `name`, ignoring ASCII case
+    pub fn by_name(&self, name: &str) -> Result<Vec<Feature>, IrwinError> {
+        let pattern = format!(
+            "%{}%",
+            name.replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_")
+        );
+        self.select("name LIKE ?1 ESCAPE '\\'", params![pattern])
+    }
+
+    /// Incidents located inside a lat/lon box (no antimeridian wrap)
+    pub fn in_bbox(
+        &self,
+        min_lat: f64,
+        min_lon: f64,
+        max_lat: f64,
+        max_lon: f64,
+    ) -> Result<Vec<Feature>, IrwinError> {
+        self.select(
+            "lat BETWEEN ?1 AND ?2 AND lon BETWEEN ?3 AND ?4",
+            params![min_lat, max_lat, min_lon, max_lon],
+        )
+    }
+
+    /// Incidents within `radius_mi` of a point, nearest first; the offline
+    /// counterpart of [`IrwinClient::incidents_near`](crate::IrwinClient::incidents_near)
+    ///
+    /// A search box crossing the antimeridian is split in two, so sites near
+    /// ±180° still see incidents on the far side of it.
+    pub fn near(
+        &self,
+        lat: f64,
+        lon: f64,
+        radius_mi: f64,
+    ) -> Result<Vec<NearbyIncident>, IrwinError> {
+        let dlat = radius_mi / MI_PER_DEG_LAT;
+        let cos_lat = lat.to_radians().cos();
+        let dlon = if cos_lat * 180.0 * MI_PER_DEG_LAT > radius_mi {
+            radius_mi / (MI_PER_DEG_LAT * cos_lat)
+        } else {
+            180.0
+        };
+        let (south, north) = (lat - dlat, lat + dlat);
+        let (west, east) = (lon - dlon, lon + dlon);
+        let mut features = if dlon >= 180.0 {
+            self.in_bbox(south, -180.0, north, 180.0)?
+        } else {
+            self.in_bbox(south, west.max(-180.0), north, east.min(180.0))?
+        };
+        if dlon < 180.0 && west < -180.0 {
+            features.extend(self.in_bbox(south, west + 360.0, north, 180.0)?);
+        }
+        if dlon < 180.0 && east > 180.0 {
+            features.extend(self.in_bbox(south, -180.0, north, east - 360.0)?);
+        }
+        Ok(nearby(
+            FeatureSet {
+                features,
+                ..Default::default()
+            },
+            lat,
+            lon,
+            radius_mi,
+        ))
+    }
+
+    /// When the last sync run completed, epoch ms; `None` if never
+    pub fn last_synced_ms(&self) -> Result<Option<u64>, IrwinError> {
+        let ms: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'last_synced_ms'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(ms.map(|ms| ms as u64))
+    }
+
+    /// How old the data is at `now_ms`; `None` if it was never synced
+    pub fn staleness_ms(&self, now_ms: u64) -> Result<Option<u64>, IrwinError> {
+        Ok(self
+            .last_synced_ms()?
+            .map(|synced| now_ms.saturating_sub(synced)))
+    }
+
+    /// Number of stored records, invalid ones included
+    pub fn len(&self) -> Result<usize, IrwinError> {
+        let n: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM incidents", [], |row| row.get(0))?;
+        Ok(n as usize)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, IrwinError> {
+        Ok(self.len()? == 0)
+    }
+
+    fn select(
+        &self,
+        condition: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<Feature>, IrwinError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT record FROM incidents WHERE is_valid = 1 AND {} ORDER BY irwin_id",
+            condition
+        ))?;
+        let records = stmt
+            .query_map(params, |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        records
+            .iter()
+            .map(|record| Ok(serde_json::from_str(record)?))
+            .collect()
+    }
+}
+
+impl IncidentStore for SqliteIncidentStore {
+    fn get(&self, irwin_id: &str) -> Result<Option<Feature>, IrwinError> {
+        self.by_irwin_id(irwin_id)
+    }
+
+    fn upsert(&mut self, irwin_id: &str, feature: Feature) -> Result<(), IrwinError> {
+        upsert_row(&self.conn, irwin_id, &feature)
+    }
+
+    /// One transaction per page
+    fn upsert_page(&mut self, records: Vec<(String, Feature)>) -> Result<(), IrwinError> {
+        let tx = self.conn.transaction()?;
+        for (irwin_id, feature) in &records {
+            upsert_row(&tx, irwin_id, feature)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn mark_synced(&mut self, at_ms: u64) -> Result<(), IrwinError> {
+        self.conn.execute(
+            "INSERT INTO meta (key, value) VALUES ('last_synced_ms', ?1)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![at_ms as i64],
+        )?;
+        Ok(())
+    }
+}
+
+fn upsert_row(conn: &Connection, irwin_id: &str, feature: &Feature) -> Result<(), IrwinError> {
+    let attrs = &feature.attributes;
+    let (lat, lon) = feature.location().unzip();
+    conn.execute(
+        "INSERT INTO incidents (irwin_id, unique_fire_id, name, lat, lon, is_valid, modified_ms, record)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+         ON CONFLICT (irwin_id) DO UPDATE SET
+             unique_fire_id = excluded.unique_fire_id,
+             name = excluded.name,
+             lat = excluded.lat,
+             lon = excluded.lon,
+             is_valid = excluded.is_valid,
+             modified_ms = excluded.modified_ms,
+             record = excluded.record",
+        params![
+            irwin_id,
+            attrs.unique_fire_identifier,
+            attrs.incident_name,
+            lat,
+            lon,
+            attrs.is_valid != Some(false),
+            attrs.modified_on_date_time,
+            serde_json::to_string(feature)?,
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::Incident;
+
+    fn feature(id: &str, name: &str, lat: f64, lon: f64, valid: bool) -> Feature {
+        Incident {
+            irwin_id: Some(format!("{{{}}}", id.to_uppercase())),
+            incident_name: Some(name.to_string()),
+            unique_fire_identifier: Some(format!("2024-CA-{}", id)),
+            poo_latitude: Some(lat),
+            poo_longitude: Some(lon),
+            is_valid: Some(valid),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn store() -> SqliteIncidentStore {
+        let mut store = SqliteIncidentStore::open_in_memory().unwrap();
+        for f in [
+            feature("a", "Ridge Fire", 38.61, -121.5, true),
+            feature("b", "100% Creek", 38.8, -121.5, true),
+            feature("c", "Far Ridge", 40.0, -121.5, true),
+            feature("d", "Ridge Merged", 38.6, -121.5, false),
+        ] {
+            let id = f.attributes.normalized_irwin_id().unwrap();
+            store.upsert(&id, f).unwrap();
+        }
+        store
+    }
+
+    fn names(features: &[Feature]) -> Vec<&str> {
+        features
+            .iter()
+            .map(|f| f.attributes.incident_name.as_deref().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_lookups() {
+        let store = store();
+        assert_eq!(store.len().unwrap(), 4);
+
+        let a = store.by_irwin_id("{A}").unwrap().unwrap();
+        assert_eq!(a.attributes.incident_name.as_deref(), Some("Ridge Fire"));
+        // invalid records are only reachable by id
+        assert!(store.by_irwin_id("d").unwrap().is_some());
+
+        let by_ufi = store.by_unique_fire_identifier("2024-CA-b").unwrap();
+        assert_eq!(names(&by_ufi), ["100% Creek"]);
+        assert_eq!(
+            names(&store.by_name("ridge").unwrap()),
+            ["Ridge Fire", "Far Ridge"]
+        );
+        // LIKE wildcards in the search text are literal
+        assert_eq!(names(&store.by_name("100%").unwrap()), ["100% Creek"]);
+        assert!(store.by_name("_idge").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_spatial_queries_and_staleness() {
+        let mut store = store();
+        let boxed = store.in_bbox(38.0, -122.0, 39.0, -121.0).unwrap();
+        assert_eq!(names(&boxed), ["Ridge Fire", "100% Creek"]);
+
+        let near = store.near(38.6, -121.5, 25.0).unwrap();
+        let near_names: Vec<_> = near
+            .iter()
+            .map(|n| n.incident().incident_name.as_deref().unwrap())
+            .collect();
+        assert_eq!(near_names, ["Ridge Fire", "100% Creek"]);
+
+        assert_eq!(store.staleness_ms(1_000).unwrap(), None);
+        store.mark_synced(1_000).unwrap();
+        store.mark_synced(2_000).unwrap();
+        assert_eq!(store.last_synced_ms().unwrap(), Some(2_000));
+        assert_eq!(store.staleness_ms(62_000).unwrap(), Some(60_000));
+    }
+
+    #[test]
+    fn test_near_searches_across_the_antimeridian() {
+        let mut store = SqliteIncidentStore::open_in_memory().unwrap();
+        store
+            .upsert_page(vec![
+                ("e".into(), feature("e", "East", -17.0, 179.9, true)),
+                ("w".into(), feature("w", "West", -17.0, -179.9, true)),
+                ("x".into(), feature("x", "Elsewhere", -17.0, 170.0, true)),
+            ])
+            .unwrap();
+        assert_eq!(store.len().unwrap(), 3);
+
+        for lon in [179.95, -179.95] {
+            let near = store.near(-17.0, lon, 25.0).unwrap();
+            let mut near_names: Vec<_> = near
+                .iter()
+                .map(|n| n.incident().incident_name.as_deref().unwrap())
+                .collect();
+            near_names.sort();
+            assert_eq!(near_names, ["East", "West"], "from {lon}");
+        }
+    }
+}
@@ -0,0 +1,562 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::IrwinError;
+use crate::incident::{Feature, FeatureSet};
+use crate::types::IncidentQueryBuilder;
+use crate::{modified_since, IrwinClient};
+
+/// Sync pages oldest change first, so each page picks up where the last
+/// one ended and the cursor can advance page by page
+pub const SYNC_ORDER: &str = "ModifiedOnDateTime ASC, OBJECTID ASC";
+
+/// Where synced incidents are kept, keyed by normalized IrwinID
+///
+/// `HashMap<String, Feature>` works for a process-lifetime cache. Pair a
+/// saved cursor with a store that survives restarts as well, otherwise the
+/// changes before the cursor are never fetched again.
+pub trait IncidentStore {
+    fn get(&self, irwin_id: &str) -> Result<Option<Feature>, IrwinError>;
+
+    fn upsert(&mut self, irwin_id: &str, feature: Feature) -> Result<(), IrwinError>;
+
+    /// Store one sync page's records; stores that can should write them
+    /// all or none, so a failure never leaves half a page behind the cursor
+    fn upsert_page(&mut self, records: Vec<(String, Feature)>) -> Result<(), IrwinError> {
+        for (irwin_id, feature) in records {
+            self.upsert(&irwin_id, feature)?;
+        }
+        Ok(())
+    }
+
+    /// Called after a sync run completes; stores that track staleness
+    /// record `at_ms` here
+    fn mark_synced(&mut self, _at_ms: u64) -> Result<(), IrwinError> {
+        Ok(())
+    }
+}
+
+impl IncidentStore for HashMap<String, Feature> {
+    fn get(&self, irwin_id: &str) -> Result<Option<Feature>, IrwinError> {
+        Ok(HashMap::get(self, irwin_id).cloned())
+    }
+
+    fn upsert(&mut self, irwin_id: &str, feature: Feature) -> Result<(), IrwinError> {
+        self.insert(irwin_id.to_string(), feature);
+        Ok(())
+    }
+}
+
+/// A change seen by [`IncidentSync`]
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SyncEvent {
+    /// New to the store, or valid again after being invalidated
+    Created { irwin_id: String, incident: Feature },
+    Updated {
+        irwin_id: String,
+        previous: Box<Feature>,
+        current: Feature,
+    },
+    /// Marked `IsValid = 0` (deleted or merged into another record)
+    Invalidated { irwin_id: String, incident: Feature },
+}
+
+/// A sync that failed part way through
+///
+/// The pages before the failure are already in the store and behind the
+/// saved cursor, so their changes are never reported again; `events` is the
+/// only record of them.
+#[derive(Debug)]
+pub struct PartialSync {
+    pub events: Vec<SyncEvent>,
+    pub error: IrwinError,
+}
+
+impl fmt::Display for PartialSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (after {} change(s) were applied)",
+            self.error,
+            self.events.len()
+        )
+    }
+}
+
+impl std::error::Error for PartialSync {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PartialSync> for IrwinError {
+    fn from(partial: PartialSync) -> Self {
+        partial.error
+    }
+}
+
+/// `ModifiedOnDateTime` watermark persisted as a small JSON file
+#[derive(Debug)]
+pub struct SyncCursor {
+    path: PathBuf,
+    modified_since_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CursorFile {
+    modified_since_ms: u64,
+}
+
+impl SyncCursor {
+    /// Load the cursor at `path`; a missing file means no sync has run yet
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self, IrwinError> {
+        let path = path.into();
+        let modified_since_ms = match fs::read_to_string(&path) {
+            Ok(text) => Some(serde_json::from_str::<CursorFile>(&text)?.modified_since_ms),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            modified_since_ms,
+        })
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        self.modified_since_ms
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Persist `ms` via write-then-rename, so a crash never leaves a torn file
+    pub fn save(&mut self, ms: u64) -> Result<(), IrwinError> {
+        let tmp = self.path.with_extension("tmp");
+        fs::write(
+            &tmp,
+            serde_json::to_vec(&CursorFile {
+                modified_since_ms: ms,
+            })?,
+        )?;
+        fs::rename(&tmp, &self.path)?;
+        self.modified_since_ms = Some(ms);
+        Ok(())
+    }
+}
+
+/// Incremental sync: pages through everything modified since the saved
+/// cursor, upserts it into a store and reports what changed
+///
+/// The query uses `ModifiedOnDateTime >=`, so records at the cursor come back
+/// on the next run; they are recognized as unchanged and produce no event.
+pub struct IncidentSync<S> {
+    store: S,
+    cursor: SyncCursor,
+    start_ms: u64,
+}
+
+impl<S: IncidentStore> IncidentSync<S> {
+    /// Sync into `store`, keeping the cursor at `cursor_path`
+    pub fn new(store: S, cursor_path: impl Into<PathBuf>) -> Result<Self, IrwinError> {
+        Ok(Self {
+            store,
+            cursor: SyncCursor::open(cursor_path)?,
+            start_ms: 0,
+        })
+    }
+
+    /// Where the first sync starts when no cursor is saved (default: everything)
+    pub fn start_from(mut self, ms: u64) -> Self {
+        self.start_ms = ms;
+        self
+    }
+
+    /// The saved `ModifiedOnDateTime` watermark, epoch ms
+    pub fn cursor_ms(&self) -> Option<u64> {
+        self.cursor.get()
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Store a snapshot fetched outside the sync (e.g. every active fire)
+    /// and resume from it, so the first run needn't page through all of
+    /// IRWIN's history
+    ///
+    /// The cursor moves to the snapshot's `nextSyncDateTime` when IRWIN sent
+    /// one, else its newest record, else `queried_at_ms`; never backwards.
+    pub fn seed(
+        &mut self,
+        snapshot: FeatureSet,
+        queried_at_ms: u64,
+    ) -> Result<Vec<SyncEvent>, IrwinError> {
+        let next_sync = snapshot
+            .next_sync_date_time
+            .and_then(|t| u64::try_from(t).ok());
+        let mut events = Vec::new();
+        self.apply(snapshot, &mut events)?;
+        let resume = next_sync.or(self.cursor.get()).unwrap_or(queried_at_ms);
+        if self.cursor.get().is_none_or(|c| resume > c) {
+            self.cursor.save(resume)?;
+        }
+        Ok(events)
+    }
+
+    /// Fetch and apply every change since the cursor
+    ///
+    /// The cursor is saved after each page, so a failure part way keeps the
+    /// progress made; the error comes back as a [`PartialSync`] carrying the
+    /// events of the pages already applied.
+    pub async fn run(&mut self, client: &IrwinClient) -> Result<Vec<SyncEvent>, PartialSync> {
+        let mut events = Vec::new();
+        match self.pull(client, &mut events).await {
+            Ok(()) => Ok(events),
+            Err(error) => Err(PartialSync { events, error }),
+        }
+    }
+
+    /// Page by keyset rather than `resultOffset`: each query starts at the
+    /// newest `ModifiedOnDateTime` seen so far, so records changing while we
+    /// page can't shift the ones still to come out of reach
+    async fn pull(
+        &mut self,
+        client: &IrwinClient,
+        events: &mut Vec<SyncEvent>,
+    ) -> Result<(), IrwinError> {
+        let mut keyset = Keyset::new(self.cursor.get().unwrap_or(self.start_ms));
+        loop {
+            let query = IncidentQueryBuilder::new()
+                .filter(&modified_since(keyset.since_ms))
+                .out_fields("*")
+                .return_geometry("true")
+                .order_by_fields(SYNC_ORDER)
+                .result_offset(keyset.skip)
+                .build();
+            let page = client.query_incidents(&query).await?;
+            let more = page.exceeded_transfer_limit && !page.features.is_empty();
+            keyset.advance(&page);
+            self.apply(page, events)?;
+            if !more {
+                break;
+            }
+        }
+        self.store.mark_synced(IrwinClient::current_timestamp_ms())
+    }
+
+    /// Upsert one page, push its events and advance the cursor to the
+    /// newest `ModifiedOnDateTime` in it; nothing is pushed unless the
+    /// page was stored
+    fn apply(&mut self, page: FeatureSet, events: &mut Vec<SyncEvent>) -> Result<(), IrwinError> {
+        let mut newest: Option<u64> = None;
+        // this page's writes so far; a record may appear more than once
+        let mut pending: HashMap<String, Feature> = HashMap::new();
+        let mut page_events = Vec::new();
+        for feature in page.features {
+            if let Some(ms) = modified_ms(&feature) {
+                newest = Some(newest.map_or(ms, |n| n.max(ms)));
+            }
+            let Some(irwin_id) = feature.attributes.normalized_irwin_id() else {
+                continue;
+            };
+            let live = is_live(&feature);
+            let stored = match pending.get(&irwin_id) {
+                Some(f) => Some(f.clone()),
+                None => self.store.get(&irwin_id)?,
+            };
+            let event = match stored {
+                Some(previous) if is_live(&previous) => {
+                    if !live {
+                        Some(SyncEvent::Invalidated {
+                            irwin_id: irwin_id.clone(),
+                            incident: feature.clone(),
+                        })
+                    } else if previous != feature {
+                        Some(SyncEvent::Updated {
+                            irwin_id: irwin_id.clone(),
+                            previous: Box::new(previous),
+                            current: feature.clone(),
+                        })
+                    } else {
+                        None
+                    }
+                }
+                _ if live => Some(SyncEvent::Created {
+                    irwin_id: irwin_id.clone(),
+                    incident: feature.clone(),
+                }),
+                // an invalid record we never saw as valid stays out of the store
+                None => continue,
+                Some(_) => None,
+            };
+            pending.insert(irwin_id, feature);
+            page_events.extend(event);
+        }
+        self.store.upsert_page(pending.into_iter().collect())?;
+        events.extend(page_events);
+        if let Some(ms) = newest {
+            if self.cursor.get().is_none_or(|c| ms > c) {
+                self.cursor.save(ms)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where the next sync page starts: records modified at or after
+/// `since_ms`, less the first `skip` of them
+///
+/// The filter only has whole-second precision, so `skip` steps over the
+/// records already seen in `since_ms`'s second; it only grows past that when
+/// a whole page shares one second.
+#[derive(Debug, PartialEq)]
+struct Keyset {
+    since_ms: u64,
+    skip: u64,
+}
+
+impl Keyset {
+    fn new(since_ms: u64) -> Self {
+        Self { since_ms, skip: 0 }
+    }
+
+    fn advance(&mut self, page: &FeatureSet) {
+        let second = |f: &Feature| modified_ms(f).map(|ms| ms / 1000);
+        let last = page.features.last().and_then(modified_ms);
+        match last {
+            Some(last) if last / 1000 != self.since_ms / 1000 => {
+                let at_last = page
+                    .features
+                    .iter()
+                    .rev()
+                    .take_while(|f| second(f) == Some(last / 1000))
+                    .count();
+                self.since_ms = last;
+                self.skip = at_last as u64;
+            }
+            _ => self.skip += page.features.len() as u64,
+        }
+    }
+}
+
+fn modified_ms(feature: &Feature) -> Option<u64> {
+    u64::try_from(feature.attributes.modified_on_date_time?).ok()
+}
+
+/// Valid unless IRWIN says otherwise
+fn is_live(feature: &Feature) -> bool {
+    feature.attributes.is_valid != Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::incident::Incident;
+    use crate::test_server::{client, serve};
+
+    fn feature(id: &str, modified: i64, valid: bool, acres: f64) -> Feature {
+        Incident {
+            irwin_id: Some(format!("{{{}}}", id.to_uppercase())),
+            modified_on_date_time: Some(modified),
+            is_valid: Some(valid),
+            daily_acres: Some(acres),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn page(features: Vec<Feature>) -> FeatureSet {
+        FeatureSet {
+            features,
+            ..Default::default()
+        }
+    }
+
+    fn kinds(events: &[SyncEvent]) -> Vec<(&'static str, &str)> {
+        events
+            .iter()
+            .map(|e| match e {
+                SyncEvent::Created { irwin_id, .. } => ("created", irwin_id.as_str()),
+                SyncEvent::Updated { irwin_id, .. } => ("updated", irwin_id.as_str()),
+                SyncEvent::Invalidated { irwin_id, .. } => ("invalidated", irwin_id.as_str()),
+            })
+            .collect()
+    }
+
+    fn cursor_path(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("irwin-sync-{}-{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_events_and_cursor() {
+        let path = cursor_path("events");
+        let mut sync = IncidentSync::new(HashMap::new(), &path).unwrap();
+        assert_eq!(sync.cursor_ms(), None);
+
+        let mut events = Vec::new();
+        sync.apply(
+            page(vec![
+                feature("a", 100, true, 10.0),
+                feature("b", 110, true, 5.0),
+                feature("ghost", 120, false, 1.0),
+            ]),
+            &mut events,
+        )
+        .unwrap();
+        assert_eq!(kinds(&events), [("created", "a"), ("created", "b")]);
+        assert!(!sync.store().contains_key("ghost"));
+        assert_eq!(sync.cursor_ms(), Some(120));
+
+        // "b" comes back at the cursor boundary unchanged: no event
+        let mut events = Vec::new();
+        sync.apply(
+            page(vec![
+                feature("b", 110, true, 5.0),
+                feature("a", 130, true, 250.0),
+                feature("b", 140, false, 5.0),
+            ]),
+            &mut events,
+        )
+        .unwrap();
+        assert_eq!(kinds(&events), [("updated", "a"), ("invalidated", "b")]);
+        match &events[0] {
+            SyncEvent::Updated {
+                previous, current, ..
+            } => {
+                assert_eq!(previous.attributes.daily_acres, Some(10.0));
+                assert_eq!(current.attributes.daily_acres, Some(250.0));
+            }
+            other => panic!("expected update, got {:?}", other),
+        }
+
+        // the cursor survives a restart
+        let reopened = IncidentSync::new(HashMap::new(), &path).unwrap();
+        assert_eq!(reopened.cursor_ms(), Some(140));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_revalidated_incident_is_created_again() {
+        let path = cursor_path("revalidated");
+        let mut sync = IncidentSync::new(HashMap::new(), &path).unwrap();
+        let mut events = Vec::new();
+        sync.apply(page(vec![feature("a", 1, true, 1.0)]), &mut events)
+            .unwrap();
+        sync.apply(page(vec![feature("a", 2, false, 1.0)]), &mut events)
+            .unwrap();
+        sync.apply(page(vec![feature("a", 3, true, 1.0)]), &mut events)
+            .unwrap();
+        assert_eq!(
+            kinds(&events),
+            [("created", "a"), ("invalidated", "a"), ("created", "a")]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_seed_stores_the_snapshot_and_resumes_after_it() {
+        let path = cursor_path("seed");
+        let mut sync = IncidentSync::new(HashMap::new(), &path).unwrap();
+        let snapshot = page(vec![
+            feature("a", 100, true, 1.0),
+            feature("b", 200, true, 1.0),
+        ]);
+        let events = sync.seed(snapshot, 5_000).unwrap();
+        assert_eq!(kinds(&events), [("created", "a"), ("created", "b")]);
+        assert_eq!(sync.cursor_ms(), Some(200));
+
+        // IRWIN's suggestion wins over the newest record
+        let snapshot = FeatureSet {
+            next_sync_date_time: Some(900),
+            ..page(vec![feature("a", 100, true, 1.0)])
+        };
+        assert!(sync.seed(snapshot, 5_000).unwrap().is_empty());
+        assert_eq!(sync.cursor_ms(), Some(900));
+        fs::remove_file(&path).unwrap();
+
+        // nothing to go on: resume from when it was queried
+        let mut sync = IncidentSync::new(HashMap::new(), &path).unwrap();
+        sync.seed(page(vec![]), 5_000).unwrap();
+        assert_eq!(sync.cursor_ms(), Some(5_000));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_keyset_starts_each_page_after_the_last_one() {
+        let mut keyset = Keyset::new(1_000);
+        keyset.advance(&page(vec![
+            feature("a", 1_500, true, 1.0),
+            feature("b", 7_100, true, 1.0),
+            feature("c", 7_900, true, 1.0),
+        ]));
+        // the filter can't tell 7_100 from 7_900, so both are skipped
+        assert_eq!(
+            keyset,
+            Keyset {
+                since_ms: 7_900,
+                skip: 2
+            }
+        );
+
+        // a page entirely within that second only moves the skip
+        keyset.advance(&page(vec![
+            feature("d", 7_950, true, 1.0),
+            feature("e", 7_990, true, 1.0),
+        ]));
+        assert_eq!(
+            keyset,
+            Keyset {
+                since_ms: 7_900,
+                skip: 4
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_page_returns_the_events_already_applied() {
+        let path = cursor_path("partial");
+        let first = FeatureSet {
+            exceeded_transfer_limit: true,
+            ..page(vec![
+                feature("a", 1_000, true, 1.0),
+                feature("b", 5_000, true, 1.0),
+            ])
+        };
+        let (url, queries) = serve(vec![
+            serde_json::to_string(&first).unwrap(),
+            r#"{"error": {"code": 500, "message": "boom"}}"#.to_string(),
+        ])
+        .await;
+
+        let mut sync = IncidentSync::new(HashMap::new(), &path).unwrap();
+        let partial = sync.run(&client(&url)).await.unwrap_err();
+        assert_eq!(kinds(&partial.events), [("created", "a"), ("created", "b")]);
+        assert!(matches!(
+            partial.error,
+            IrwinError::ApiError { status: 500, .. }
+        ));
+        assert_eq!(sync.cursor_ms(), Some(5_000));
+
+        // the second page was asked for from the first one's newest record
+        let queries = queries.lock().unwrap();
+        assert!(!queries[0].contains("resultOffset=1"), "{}", queries[0]);
+        assert!(
+            queries[1].contains("1970-01-01+00%3A00%3A05") && queries[1].contains("resultOffset=1"),
+            "{}",
+            queries[1]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,60 @@
+//! A local stand-in for IRWIN, for tests that go through [`IrwinClient`]
+
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::types::IrwinConfig;
+use crate::IrwinClient;
+
+/// Hands out a token, then answers each query with the next of `bodies`
+/// (an empty feature set once they run out); returns its base URL and the
+/// query request targets seen
+pub async fn serve(bodies: Vec<String>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let queries = Arc::new(Mutex::new(Vec::new()));
+    let seen = queries.clone();
+    tokio::spawn(async move {
+        let mut bodies = bodies.into_iter();
+        loop {
+            let (mut sock, _) = listener.accept().await.unwrap();
+            let mut req = Vec::new();
+            let mut buf = [0u8; 4096];
+            let head = loop {
+                let n = sock.read(&mut buf).await.unwrap();
+                req.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&req).into_owned();
+                if n == 0 || text.contains("\r\n\r\n") {
+                    break text;
+                }
+            };
+            let target = head.split_whitespace().nth(1).unwrap_or("").to_string();
+            let body = if target.contains("generateToken") {
+                r#"{"token": "t", "expires": 9999999999999}"#.to_string()
+            } else {
+                seen.lock().unwrap().push(target);
+                bodies
+                    .next()
+                    .unwrap_or_else(|| r#"{"features": []}"#.to_string())
+            };
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\n\
+                 content-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            let _ = sock.write_all(resp.as_bytes()).await;
+        }
+    });
+    (base_url, queries)
+}
+
+pub fn client(base_url: &str) -> IrwinClient {
+    IrwinClient::new(IrwinConfig::new(
+        base_url.to_string(),
+        "user".to_string(),
+        "pass".to_string(),
+        "referer".to_string(),
+    ))
+    .unwrap()
+}
@@ -0,0 +1,132 @@
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use crate::error::IrwinError;
+use crate::incident::parse_response;
+
+/// Lifetime requested from `generateToken`, in minutes (the IRWIN maximum)
+pub const TOKEN_EXPIRATION_MINUTES: u64 = 60;
+
+/// Refresh this long before the server-reported expiry
+const REFRESH_MARGIN_MS: u64 = 2 * 60 * 1000;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CachedToken {
+    pub token: String,
+    /// Epoch milliseconds
+    pub expires_at_ms: u64,
+}
+
+impl CachedToken {
+    fn is_fresh(&self, now_ms: u64) -> bool {
+        now_ms + REFRESH_MARGIN_MS < self.expires_at_ms
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    /// Epoch milliseconds
+    expires: Option<u64>,
+}
+
+/// Parse a `generateToken` response; without `expires`, assume the lifetime
+/// we asked for
+pub(crate) fn parse_token_response(text: &str, now_ms: u64) -> Result<CachedToken, IrwinError> {
+    let resp: TokenResponse = parse_response(text)?;
+    let token = resp.token.ok_or_else(|| IrwinError::ApiError {
+        status: 0,
+        message: "No token in response".to_string(),
+    })?;
+    Ok(CachedToken {
+        token,
+        expires_at_ms: resp
+            .expires
+            .unwrap_or(now_ms + TOKEN_EXPIRATION_MINUTES * 60 * 1000),
+    })
+}
+
+/// The current token, shared by every request made through one client
+///
+/// The lock is held while a new token is fetched, so concurrent callers wait
+/// for one `generateToken` call instead of each making their own.
+#[derive(Default)]
+pub(crate) struct TokenCache {
+    slot: Mutex<Option<CachedToken>>,
+}
+
+impl TokenCache {
+    /// The cached token if it is still fresh, otherwise the one `fetch` returns
+    pub async fn get_or_refresh<F, Fut>(&self, now_ms: u64, fetch: F) -> Result<String, IrwinError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Result<CachedToken, IrwinError>>,
+    {
+        let mut slot = self.slot.lock().await;
+        if let Some(cached) = slot.as_ref().filter(|c| c.is_fresh(now_ms)) {
+            return Ok(cached.token.clone());
+        }
+        let fresh = fetch().await?;
+        let token = fresh.token.clone();
+        *slot = Some(fresh);
+        Ok(token)
+    }
+
+    /// Drop `token` if it is still the cached one (another task may already
+    /// have replaced it)
+    pub async fn invalidate(&self, token: &str) {
+        let mut slot = self.slot.lock().await;
+        if slot.as_ref().is_some_and(|c| c.token == token) {
+            *slot = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_token_is_reused_until_shortly_before_expiry() {
+        let cache = TokenCache::default();
+        let issue = |token: &'static str| {
+            move || async move {
+                Ok(CachedToken {
+                    token: token.to_string(),
+                    expires_at_ms: 60 * 60 * 1000,
+                })
+            }
+        };
+
+        assert_eq!(cache.get_or_refresh(0, issue("a")).await.unwrap(), "a");
+        // still fresh: the fetch isn't called
+        assert_eq!(cache.get_or_refresh(1000, issue("b")).await.unwrap(), "a");
+        // inside the refresh margin
+        let late = 60 * 60 * 1000 - 60 * 1000;
+        assert_eq!(cache.get_or_refresh(late, issue("c")).await.unwrap(), "c");
+
+        // only the token that failed is dropped
+        cache.invalidate("a").await;
+        assert_eq!(cache.get_or_refresh(0, issue("d")).await.unwrap(), "c");
+        cache.invalidate("c").await;
+        assert_eq!(cache.get_or_refresh(0, issue("e")).await.unwrap(), "e");
+    }
+
+    #[test]
+    fn test_parse_token_response() {
+        let t = parse_token_response(r#"{"token": "abc", "expires": 1700003600000}"#, 0).unwrap();
+        assert_eq!(t.expires_at_ms, 1_700_003_600_000);
+
+        let t = parse_token_response(r#"{"token": "abc"}"#, 1000).unwrap();
+        assert_eq!(t.expires_at_ms, 1000 + 60 * 60 * 1000);
+
+        assert!(parse_token_response(r#"{"expires": 1}"#, 0).is_err());
+        assert!(matches!(
+            parse_token_response(
+                r#"{"error": {"code": 400, "message": "Unable to generate token."}}"#,
+                0
+            ),
+            Err(IrwinError::ApiError { status: 400, .. })
+        ));
+    }
+}
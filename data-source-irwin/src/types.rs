@@ -1,5 +1,10 @@
 use std::time::Duration;
 
+use crate::incident::{Geometry, Ring};
+use crate::perimeter::PERIMETER_IRWIN_ID_FIELD;
+use crate::spatial::{DistanceUnit, SpatialRel, WGS84};
+use crate::where_clause::WhereClause;
+
 /// Configuration for the IRWIN client
 #[derive(Clone, Debug)]
 pub struct IrwinConfig {
@@ -17,6 +22,12 @@ pub struct IrwinConfig {
 
     /// Request timeout
     pub timeout: Duration,
+
+    /// Fire perimeter layer, relative to `base_url`
+    pub perimeter_layer: String,
+
+    /// Attribute of `perimeter_layer` holding the incident's IrwinID
+    pub perimeter_irwin_id_field: String,
 }
 
 impl IrwinConfig {
@@ -28,6 +39,8 @@ impl IrwinConfig {
             password,
             referer,
             timeout: Duration::from_secs(30),
+            perimeter_layer: layers::PERIMETERS.to_string(),
+            perimeter_irwin_id_field: PERIMETER_IRWIN_ID_FIELD.to_string(),
         }
     }
 
@@ -45,6 +58,8 @@ impl IrwinConfig {
             password,
             referer,
             timeout,
+            perimeter_layer: layers::PERIMETERS.to_string(),
+            perimeter_irwin_id_field: PERIMETER_IRWIN_ID_FIELD.to_string(),
         }
     }
 }
@@ -75,6 +90,119 @@ pub struct IncidentQuery {
 
     /// Include FFR (Fire Funding Request)
     pub include_ffr: bool,
+
+    /// Number of records to skip (paging)
+    pub result_offset: Option<u64>,
+
+    /// Page size; the server caps it at its `maxRecordCount`
+    pub result_record_count: Option<u64>,
+
+    /// Sort order, e.g. `ModifiedOnDateTime ASC`; needed for stable paging
+    pub order_by_fields: Option<String>,
+
+    /// Spatial filter geometry, in `in_sr` coordinates
+    pub geometry: Option<Geometry>,
+
+    /// ArcGIS geometry type of `geometry` (e.g. `esriGeometryPoint`)
+    pub geometry_type: Option<String>,
+
+    /// How `geometry` must relate to matching features
+    pub spatial_rel: Option<SpatialRel>,
+
+    /// Buffer distance around `geometry`, in `units`
+    pub distance: Option<f64>,
+
+    /// Units for `distance`
+    pub units: Option<DistanceUnit>,
+
+    /// Spatial reference WKID of `geometry`
+    pub in_sr: Option<u32>,
+
+    /// Spatial reference WKID for returned geometries
+    pub out_sr: Option<u32>,
+}
+
+impl IncidentQuery {
+    /// Query parameters for this query, excluding `f` and `token`
+    pub(crate) fn params(&self) -> Vec<(String, String)> {
+        let mut params = Vec::new();
+
+        if let Some(where_clause) = &self.where_clause {
+            params.push(("where".to_string(), where_clause.clone()));
+        }
+
+        if let Some(out_fields) = &self.out_fields {
+            params.push(("outFields".to_string(), out_fields.clone()));
+        }
+
+        if let Some(return_geometry) = &self.return_geometry {
+            params.push(("returnGeometry".to_string(), return_geometry.clone()));
+        }
+
+        if let Some(order_by_fields) = &self.order_by_fields {
+            params.push(("orderByFields".to_string(), order_by_fields.clone()));
+        }
+
+        if let Some(offset) = self.result_offset {
+            params.push(("resultOffset".to_string(), offset.to_string()));
+        }
+
+        if let Some(count) = self.result_record_count {
+            params.push(("resultRecordCount".to_string(), count.to_string()));
+        }
+
+        if let Some(geometry) = &self.geometry {
+            let json = serde_json::to_string(geometry).expect("geometry serializes to JSON");
+            params.push(("geometry".to_string(), json));
+        }
+
+        if let Some(geometry_type) = &self.geometry_type {
+            params.push(("geometryType".to_string(), geometry_type.clone()));
+        }
+
+        if let Some(spatial_rel) = self.spatial_rel {
+            params.push(("spatialRel".to_string(), spatial_rel.as_str().to_string()));
+        }
+
+        if let Some(distance) = self.distance {
+            params.push(("distance".to_string(), distance.to_string()));
+        }
+
+        if let Some(units) = self.units {
+            params.push(("units".to_string(), units.as_str().to_string()));
+        }
+
+        if let Some(in_sr) = self.in_sr {
+            params.push(("inSR".to_string(), in_sr.to_string()));
+        }
+
+        if let Some(out_sr) = self.out_sr {
+            params.push(("outSR".to_string(), out_sr.to_string()));
+        }
+
+        // Add IRWIN-specific extensions
+        if self.include_ads_status {
+            params.push(("includeADSStatus".to_string(), "true".to_string()));
+        }
+
+        if self.include_resources {
+            params.push(("includeResources".to_string(), "true".to_string()));
+        }
+
+        if self.include_relationships {
+            params.push(("includeRelationships".to_string(), "true".to_string()));
+        }
+
+        if self.include_last_sync_date_time {
+            params.push(("includeLastSyncDateTime".to_string(), "true".to_string()));
+        }
+
+        if self.include_ffr {
+            params.push(("includeFFR".to_string(), "true".to_string()));
+        }
+
+        params
+    }
 }
 
 /// Builder for IncidentQuery
@@ -87,6 +215,16 @@ pub struct IncidentQueryBuilder {
     pub include_relationships: bool,
     pub include_last_sync_date_time: bool,
     pub include_ffr: bool,
+    pub result_offset: Option<u64>,
+    pub result_record_count: Option<u64>,
+    pub order_by_fields: Option<String>,
+    pub geometry: Option<Geometry>,
+    pub geometry_type: Option<String>,
+    pub spatial_rel: Option<SpatialRel>,
+    pub distance: Option<f64>,
+    pub units: Option<DistanceUnit>,
+    pub in_sr: Option<u32>,
+    pub out_sr: Option<u32>,
 }
 
 impl IncidentQueryBuilder {
@@ -101,15 +239,32 @@ impl IncidentQueryBuilder {
             include_relationships: false,
             include_last_sync_date_time: false,
             include_ffr: false,
+            result_offset: None,
+            result_record_count: None,
+            order_by_fields: None,
+            geometry: None,
+            geometry_type: None,
+            spatial_rel: None,
+            distance: None,
+            units: None,
+            in_sr: None,
+            out_sr: None,
         }
     }
 
-    /// Set the WHERE clause
+    /// Set the WHERE clause as raw SQL; prefer [`filter`](Self::filter),
+    /// which escapes literals
     pub fn where_clause(mut self, where_clause: &str) -> Self {
         self.where_clause = Some(where_clause.to_string());
         self
     }
 
+    /// Set the WHERE clause from a [`WhereClause`]
+    pub fn filter(mut self, filter: &WhereClause) -> Self {
+        self.where_clause = Some(filter.to_string());
+        self
+    }
+
     /// Set the output fields
     pub fn out_fields(mut self, out_fields: &str) -> Self {
         self.out_fields = Some(out_fields.to_string());
@@ -152,6 +307,82 @@ impl IncidentQueryBuilder {
         self
     }
 
+    /// Set the number of records to skip
+    pub fn result_offset(mut self, offset: u64) -> Self {
+        self.result_offset = Some(offset);
+        self
+    }
+
+    /// Set the page size
+    pub fn result_record_count(mut self, count: u64) -> Self {
+        self.result_record_count = Some(count);
+        self
+    }
+
+    /// Set the sort order (e.g. `ModifiedOnDateTime ASC, OBJECTID ASC`)
+    pub fn order_by_fields(mut self, order_by_fields: &str) -> Self {
+        self.order_by_fields = Some(order_by_fields.to_string());
+        self
+    }
+
+    /// Set the spatial filter geometry; also sets the geometry type when it
+    /// can be told from the shape
+    pub fn geometry(mut self, geometry: Geometry) -> Self {
+        if let Some(geometry_type) = geometry.esri_type() {
+            self.geometry_type = Some(geometry_type.to_string());
+        }
+        self.geometry = Some(geometry);
+        self
+    }
+
+    /// Set the geometry type (e.g. `esriGeometryEnvelope`)
+    pub fn geometry_type(mut self, geometry_type: &str) -> Self {
+        self.geometry_type = Some(geometry_type.to_string());
+        self
+    }
+
+    /// Set the spatial relationship
+    pub fn spatial_rel(mut self, spatial_rel: SpatialRel) -> Self {
+        self.spatial_rel = Some(spatial_rel);
+        self
+    }
+
+    /// Buffer the query geometry by `distance` `units`
+    pub fn distance(mut self, distance: f64, units: DistanceUnit) -> Self {
+        self.distance = Some(distance);
+        self.units = Some(units);
+        self
+    }
+
+    /// Set the spatial reference of the query geometry
+    pub fn in_sr(mut self, wkid: u32) -> Self {
+        self.in_sr = Some(wkid);
+        self
+    }
+
+    /// Set the spatial reference of returned geometries
+    pub fn out_sr(mut self, wkid: u32) -> Self {
+        self.out_sr = Some(wkid);
+        self
+    }
+
+    /// Match features within `radius_mi` statute miles of a WGS84 point
+    pub fn near(self, lat: f64, lon: f64, radius_mi: f64) -> Self {
+        self.geometry(Geometry::Point { x: lon, y: lat })
+            .spatial_rel(SpatialRel::Intersects)
+            .distance(radius_mi, DistanceUnit::StatuteMile)
+            .in_sr(WGS84)
+            .out_sr(WGS84)
+    }
+
+    /// Match features inside a WGS84 polygon; rings are `[lon, lat]` pairs
+    pub fn within_polygon(self, rings: Vec<Ring>) -> Self {
+        self.geometry(Geometry::Polygon { rings })
+            .spatial_rel(SpatialRel::Intersects)
+            .in_sr(WGS84)
+            .out_sr(WGS84)
+    }
+
     /// Build the IncidentQuery
     pub fn build(self) -> IncidentQuery {
         IncidentQuery {
@@ -163,6 +394,16 @@ impl IncidentQueryBuilder {
             include_relationships: self.include_relationships,
             include_last_sync_date_time: self.include_last_sync_date_time,
             include_ffr: self.include_ffr,
+            result_offset: self.result_offset,
+            result_record_count: self.result_record_count,
+            order_by_fields: self.order_by_fields,
+            geometry: self.geometry,
+            geometry_type: self.geometry_type,
+            spatial_rel: self.spatial_rel,
+            distance: self.distance,
+            units: self.units,
+            in_sr: self.in_sr,
+            out_sr: self.out_sr,
         }
     }
 }
@@ -173,6 +414,16 @@ impl Default for IncidentQueryBuilder {
     }
 }
 
+/// Feature layer paths, relative to the environment URL
+pub mod layers {
+    /// Incident points
+    pub const INCIDENTS: &str = "/Irwin/Incidents/FeatureServer/0";
+
+    /// Fire perimeter polygons; deployments differ, so this can be
+    /// overridden with `IrwinConfig::perimeter_layer`
+    pub const PERIMETERS: &str = "/Irwin/Perimeters/FeatureServer/0";
+}
+
 /// IRWIN environment URLs
 pub mod environments {
     /// Test environment URL
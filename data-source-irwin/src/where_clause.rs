@@ -0,0 +1,323 @@
+use std::fmt;
+
+use crate::error::IrwinError;
+
+/// A literal on the right-hand side of a comparison
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal {
+    /// Quoted, with embedded quotes doubled
+    Str(String),
+    Int(i64),
+    /// Built with `Literal::try_from`, which rejects NaN and infinities
+    Num(Finite),
+    /// Epoch milliseconds, rendered as a UTC `timestamp '...'` literal
+    DateTime(i64),
+}
+
+/// An `f64` known to be finite, so it always renders as a SQL number
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Finite(f64);
+
+impl Finite {
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+impl Literal {
+    /// A date field value from epoch milliseconds
+    pub fn datetime_ms(ms: i64) -> Self {
+        Literal::DateTime(ms)
+    }
+}
+
+impl From<&str> for Literal {
+    fn from(v: &str) -> Self {
+        Literal::Str(v.to_string())
+    }
+}
+
+impl From<String> for Literal {
+    fn from(v: String) -> Self {
+        Literal::Str(v)
+    }
+}
+
+impl From<&String> for Literal {
+    fn from(v: &String) -> Self {
+        Literal::Str(v.clone())
+    }
+}
+
+impl From<i64> for Literal {
+    fn from(v: i64) -> Self {
+        Literal::Int(v)
+    }
+}
+
+impl From<i32> for Literal {
+    fn from(v: i32) -> Self {
+        Literal::Int(v.into())
+    }
+}
+
+impl TryFrom<f64> for Literal {
+    type Error = IrwinError;
+
+    fn try_from(v: f64) -> Result<Self, Self::Error> {
+        if v.is_finite() {
+            Ok(Literal::Num(Finite(v)))
+        } else {
+            Err(IrwinError::RequestError(format!(
+                "non-finite number {} in where clause",
+                v
+            )))
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Str(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Num(n) => write!(f, "{}", n.get()),
+            Literal::DateTime(ms) => write!(f, "timestamp '{}'", format_utc(*ms)),
+        }
+    }
+}
+
+/// A WHERE clause built from typed parts, so callers never hand-write SQL
+///
+/// Field names must be plain identifiers (letters, digits, `_`); anything
+/// else panics, since field names come from code rather than user input.
+/// A name from configuration goes through a `try_` constructor instead.
+/// String literals are always quoted and escaped.
+///
+/// ```
+/// use data_source_irwin::{Literal, WhereClause};
+///
+/// let w = WhereClause::eq("IsValid", 1)
+///     .and(WhereClause::eq("IncidentTypeKind", "FI"))
+///     .and(WhereClause::gte("ModifiedOnDateTime", Literal::datetime_ms(0)));
+/// assert_eq!(
+///     w.to_string(),
+///     "((IsValid = 1 AND IncidentTypeKind = 'FI') AND ModifiedOnDateTime >= timestamp '1970-01-01 00:00:00')"
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct WhereClause(String);
+
+impl WhereClause {
+    fn compare(field: &str, op: &str, value: Literal) -> Self {
+        WhereClause(format!("{} {} {}", ident(field), op, value))
+    }
+
+    pub fn eq(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, "=", value.into())
+    }
+
+    pub fn ne(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, "<>", value.into())
+    }
+
+    pub fn gt(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, ">", value.into())
+    }
+
+    pub fn gte(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, ">=", value.into())
+    }
+
+    pub fn lt(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, "<", value.into())
+    }
+
+    pub fn lte(field: &str, value: impl Into<Literal>) -> Self {
+        Self::compare(field, "<=", value.into())
+    }
+
+    /// Inclusive range
+    pub fn between(field: &str, low: impl Into<Literal>, high: impl Into<Literal>) -> Self {
+        WhereClause(format!(
+            "{} BETWEEN {} AND {}",
+            ident(field),
+            low.into(),
+            high.into()
+        ))
+    }
+
+    /// `field IN (...)`; an empty list matches nothing
+    pub fn in_list<V: Into<Literal>>(field: &str, values: impl IntoIterator<Item = V>) -> Self {
+        let values: Vec<String> = values.into_iter().map(|v| v.into().to_string()).collect();
+        if values.is_empty() {
+            return WhereClause("1 = 0".to_string());
+        }
+        WhereClause(format!("{} IN ({})", ident(field), values.join(", ")))
+    }
+
+    /// [`in_list`](Self::in_list) for a field name that isn't fixed in code;
+    /// an invalid one is a `RequestError` rather than a panic
+    pub fn try_in_list<V: Into<Literal>>(
+        field: &str,
+        values: impl IntoIterator<Item = V>,
+    ) -> Result<Self, IrwinError> {
+        if !is_ident(field) {
+            return Err(IrwinError::RequestError(format!(
+                "invalid field name in where clause: {:?}",
+                field
+            )));
+        }
+        Ok(Self::in_list(field, values))
+    }
+
+    pub fn is_null(field: &str) -> Self {
+        WhereClause(format!("{} IS NULL", ident(field)))
+    }
+
+    pub fn is_not_null(field: &str) -> Self {
+        WhereClause(format!("{} IS NOT NULL", ident(field)))
+    }
+
+    pub fn and(self, other: WhereClause) -> Self {
+        WhereClause(format!("({} AND {})", self.0, other.0))
+    }
+
+    pub fn or(self, other: WhereClause) -> Self {
+        WhereClause(format!("({} OR {})", self.0, other.0))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for WhereClause {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+fn ident(field: &str) -> &str {
+    assert!(
+        is_ident(field),
+        "invalid field name in where clause: {:?}",
+        field
+    );
+    field
+}
+
+fn is_ident(field: &str) -> bool {
+    let mut chars = field.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC for epoch milliseconds
+fn format_utc(ms: i64) -> String {
+    let secs = ms.div_euclid(1000);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (y, m, d) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        y,
+        m,
+        d,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Days since 1970-01-01 to a proleptic Gregorian (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_literals_are_escaped() {
+        let w = WhereClause::eq("IncidentName", "O'Brien Fire' OR 1=1 --");
+        assert_eq!(w.as_str(), "IncidentName = 'O''Brien Fire'' OR 1=1 --'");
+    }
+
+    #[test]
+    fn test_in_list_quotes_guids_and_handles_empty() {
+        let w = WhereClause::in_list("IrwinID", ["{A-1}", "{B-2}"]);
+        assert_eq!(w.as_str(), "IrwinID IN ('{A-1}', '{B-2}')");
+        let none: [&str; 0] = [];
+        assert_eq!(WhereClause::in_list("IrwinID", none).as_str(), "1 = 0");
+    }
+
+    #[test]
+    fn test_try_in_list_rejects_bad_field_names() {
+        assert_eq!(
+            WhereClause::try_in_list("IRWINID", ["a"]).unwrap().as_str(),
+            "IRWINID IN ('a')"
+        );
+        for field in ["", "Irwin ID", "1=1 OR IrwinID"] {
+            assert!(matches!(
+                WhereClause::try_in_list(field, ["a"]),
+                Err(IrwinError::RequestError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ranges_and_boolean_composition() {
+        let w = WhereClause::between("DailyAcres", 100, Literal::try_from(500.5).unwrap())
+            .or(WhereClause::is_null("DailyAcres"))
+            .and(WhereClause::ne("IsValid", 0));
+        assert_eq!(
+            w.as_str(),
+            "((DailyAcres BETWEEN 100 AND 500.5 OR DailyAcres IS NULL) AND IsValid <> 0)"
+        );
+    }
+
+    #[test]
+    fn test_non_finite_numbers_are_rejected() {
+        for v in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                Literal::try_from(v),
+                Err(IrwinError::RequestError(_))
+            ));
+        }
+        assert_eq!(Literal::try_from(-0.25).unwrap().to_string(), "-0.25");
+    }
+
+    #[test]
+    fn test_datetime_literal() {
+        assert_eq!(
+            Literal::datetime_ms(1_700_000_000_123).to_string(),
+            "timestamp '2023-11-14 22:13:20'"
+        );
+        assert_eq!(
+            Literal::datetime_ms(951_782_400_000).to_string(),
+            "timestamp '2000-02-29 00:00:00'"
+        );
+        assert_eq!(
+            Literal::datetime_ms(-1000).to_string(),
+            "timestamp '1969-12-31 23:59:59'"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid field name")]
+    fn test_field_names_are_validated() {
+        WhereClause::eq("IsValid=1 OR 1", 1);
+    }
+}
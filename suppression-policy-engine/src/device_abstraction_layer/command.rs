@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Command {
     Monitor,
@@ -18,4 +18,10 @@ pub enum Command {
 pub struct CommandResult {
     pub ok: bool,
     pub message: String,
+    /// Driver-specific detail, surfaced on the run's step list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+    /// Transient failures the driver retried past while running the command.
+    #[serde(default)]
+    pub retries: u32,
 }
@@ -1,20 +1,27 @@
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use reqwest::{Client, Url};
+use reqwest::{StatusCode, Url};
 use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tracing::{debug, error};
 
 use super::{
-    config::ControlByWebConfig, device_access::DeviceAccessTokenManager,
-    relay_plan::parse_relay_state_map, token::TokenManager,
+    config::ControlByWebConfig,
+    device_access::{DatRejected, DeviceAccessTokenManager},
+    device_state::parse_device_status,
+    http::{CloudError, CloudHttp, CountRetries},
+    relay_plan::parse_relay_state_map,
+    token::TokenManager,
 };
 use crate::device_abstraction_layer::drivers::control_by_web::account::InstallationAccountResolver;
+use crate::device_abstraction_layer::drivers::control_by_web::relay_map::{RelayMap, RelayMapBook};
 use crate::device_abstraction_layer::drivers::control_by_web::relay_plan::{
-    expected_from_plan, mismatches, plan_relays, RelayPlan,
+    expected_from_plan, mismatches, RelayPlan,
+};
+use crate::device_abstraction_layer::{
+    Command, CommandResult, DeviceDriver, DeviceStatus, DriverError, RelayMismatch,
 };
-use crate::device_abstraction_layer::{Command, CommandResult, DeviceDriver};
 
 /// Base host for direct-to-device DAT calls.
 /// If you want this configurable, move to config and pass through `ControlByWebConfig`.
@@ -22,11 +29,11 @@ const DAT_BASE: &str = "https://productionblue.api.controlbyweb.cloud/";
 
 #[derive(Clone)]
 pub struct ControlByWebDriver {
-    client: Client,
-    base_url: Url,
-    tm: Arc<Mutex<TokenManager>>,
+    http: CloudHttp,
     dats: DeviceAccessTokenManager,
     resolver: Arc<dyn InstallationAccountResolver>,
+    relay_maps: Arc<RelayMapBook>,
+    dat_minutes_valid: u32,
 }
 
 impl ControlByWebDriver {
@@ -34,29 +41,80 @@ impl ControlByWebDriver {
         cfg: ControlByWebConfig,
         resolver: Arc<dyn InstallationAccountResolver>,
     ) -> Result<Self> {
-        let client = cfg.build_client()?;
+        let http = CloudHttp::new(cfg.build_client()?, cfg.retry);
         let token_url = cfg.token_url()?;
-        let base_url = cfg.base_url.clone();
         let tm = Arc::new(Mutex::new(TokenManager::new(
-            client.clone(),
+            http.clone(),
             token_url,
             cfg.username,
             cfg.password,
         )));
-        let dats = DeviceAccessTokenManager::new(client.clone(), cfg.base_url.clone(), tm.clone());
+        let dats = DeviceAccessTokenManager::new(http.clone(), cfg.base_url.clone(), tm);
 
         Ok(Self {
-            client,
-            base_url,
-            tm,
+            http,
             dats,
             resolver,
+            relay_maps: Arc::new(cfg.relay_maps),
+            dat_minutes_valid: cfg.dat_minutes_valid,
         })
     }
 
-    #[inline]
-    fn _endpoint(&self, rel: &str) -> Result<Url> {
-        Ok(self.base_url.join(rel)?)
+    fn relay_map(&self, installation_id: &str) -> Result<RelayMap, DriverError> {
+        self.relay_maps
+            .for_installation(installation_id)
+            .cloned()
+            .ok_or_else(|| {
+                DriverError::NotConfigured(format!(
+                    "no relay map for installation {installation_id}"
+                ))
+            })
+    }
+}
+
+/// Device-side failure: a rejected DAT stays [`DatRejected`] so
+/// `with_device_access` can retry it, and gateway errors mean the cloud
+/// couldn't reach the device.
+fn device_error(e: CloudError) -> anyhow::Error {
+    match e.status() {
+        Some(
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT,
+        ) => DriverError::DeviceOffline(e.to_string()).into(),
+        _ => DatRejected::from_cloud(e),
+    }
+}
+
+/// Map whatever came out of a DAT call onto the driver error taxonomy.
+fn classify(e: anyhow::Error) -> DriverError {
+    let e = match e.downcast::<DriverError>() {
+        Ok(d) => return d,
+        Err(e) => e,
+    };
+    if e.downcast_ref::<DatRejected>().is_some() {
+        return DriverError::AuthFailed(format!("{e:#}"));
+    }
+    match e.downcast_ref::<CloudError>() {
+        Some(CloudError::Status {
+            status,
+            retry_after,
+            ..
+        }) => match *status {
+            StatusCode::TOO_MANY_REQUESTS => DriverError::RateLimited {
+                message: format!("{e:#}"),
+                retry_after: *retry_after,
+            },
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+                DriverError::AuthFailed(format!("{e:#}"))
+            }
+            _ => DriverError::Other(e),
+        },
+        Some(CloudError::Transport { err, .. }) if err.is_timeout() => {
+            DriverError::Timeout(format!("{e:#}"))
+        }
+        Some(CloudError::Transport { err, .. }) if err.is_connect() => {
+            DriverError::DeviceOffline(format!("{e:#}"))
+        }
+        _ => DriverError::Other(e),
     }
 }
 
@@ -78,28 +136,48 @@ fn build_dat_custom_state_url(dat: &str, plan: &RelayPlan) -> Result<Url> {
 
 #[async_trait]
 impl DeviceDriver for ControlByWebDriver {
-    async fn apply(&self, installation_id: &str, cmd: Command) -> Result<CommandResult> {
+    async fn apply(
+        &self,
+        installation_id: &str,
+        cmd: Command,
+    ) -> Result<CommandResult, DriverError> {
         // 1) Resolve (account_id, device_id)
-        let binding = self.resolver.resolve(installation_id).ok_or_else(|| {
-            anyhow::anyhow!("no account/device binding for installation {installation_id}")
-        })?;
+        let binding = self
+            .resolver
+            .resolve(installation_id)
+            .ok_or_else(|| DriverError::UnresolvedInstallation(installation_id.to_string()))?;
         let account_id = binding.account_id;
         let device_id = binding.device_id; // String (owned)
         debug!("apply(): installation_id={installation_id} account_id={account_id} device_id={device_id:?} cmd={:?}", cmd);
 
-        // 2) Plan relays once, return ON and OFF lists
-        let plan = plan_relays(installation_id, cmd);
+        if matches!(cmd, Command::Noop) {
+            return Ok(CommandResult {
+                ok: true,
+                message: "noop".into(),
+                response: None,
+                retries: 0,
+            });
+        }
+
+        // 2) Plan relays once from the installation's relay map
+        let relay_map = self.relay_map(installation_id)?;
+        let plan = relay_map.plan(cmd).map_err(|e| {
+            DriverError::NotConfigured(format!("installation {installation_id}: {e}"))
+        })?;
         debug!("relay plan: ON={:?} OFF={:?}", plan.on, plan.off);
 
-        // 3) Acquire a short-lived DAT; call device; delete if created
-        let minutes_valid: u32 = 5;
-        let client = self.client.clone();
+        // 3) Call the device through the cached DAT for this device
+        let minutes_valid = self.dat_minutes_valid;
+        let http = self.http.clone();
 
-        self.dats
+        // Retries anywhere in the call (auth, DATs, device) are reported on the result
+        let (res, retries) = self
+            .dats
             .with_device_access(account_id, &device_id, minutes_valid, move |dat_ref| {
                 let dat = dat_ref.to_string();
                 let plan = plan.clone();
-                let client = client.clone();
+                let http = http.clone();
+                let relay_map = relay_map.clone();
 
                 async move {
                     // 1) Batch attempt
@@ -107,24 +185,16 @@ impl DeviceDriver for ControlByWebDriver {
                         .context("building DAT customState url")?;
                     debug!("DAT customState url={url}");
 
-                    let resp = client
-                        .get(url.clone())
-                        .send()
+                    let resp = http
+                        .send("DAT customState", http.get(url.clone()))
                         .await
-                        .context("sending DAT customState request")?;
-
-                    if !resp.status().is_success() {
-                        let code = resp.status();
-                        let text = resp.text().await.unwrap_or_default();
-                        error!("device DAT not ok: status={} body={}", code, text);
-                        anyhow::bail!("device error via DAT: status={} body={}", code, text);
-                    }
+                        .map_err(device_error)?;
 
                     let body = resp.text().await.unwrap_or_default();
                     debug!("Batch response: {}", &body[..body.len().min(400)]);
 
                     // 2) Compare plan vs actual
-                    let actual = parse_relay_state_map(&body).unwrap_or_default();
+                    let actual = parse_relay_state_map(&body, &relay_map).unwrap_or_default();
                     let expected = expected_from_plan(&plan);
                     let diffs = mismatches(&expected, &actual);
 
@@ -133,6 +203,8 @@ impl DeviceDriver for ControlByWebDriver {
                         return Ok::<CommandResult, anyhow::Error>(CommandResult {
                             ok: true,
                             message: "relays updated (batch)".into(),
+                            response: Some(json!({ "relays": actual })),
+                            retries: 0,
                         });
                     }
 
@@ -154,29 +226,35 @@ impl DeviceDriver for ControlByWebDriver {
                                 )
                             })?;
 
-                        let r = client
-                            .get(single_url.clone())
-                            .send()
+                        let r = match http
+                            .send("DAT single relay", http.get(single_url.clone()))
                             .await
-                            .with_context(|| {
-                                format!(
+                        {
+                            Ok(r) => r,
+                            Err(CloudError::Status { status, body, .. })
+                                if DatRejected::from_status(status).is_none() =>
+                            {
+                                error!(
+                                    "single relay not ok: {} wanted={} got={:?} status={} body={}",
+                                    relay, want_on, got, status, body
+                                );
+                                failures.push(RelayMismatch {
+                                    relay,
+                                    want_on,
+                                    got,
+                                    detail: Some(format!("status={status} body={body}")),
+                                });
+                                // If you want to stop on first failure, break here.
+                                continue;
+                            }
+                            Err(e) => {
+                                return Err(device_error(e).context(format!(
                                     "send single relay {}={}",
                                     relay,
                                     if want_on { 1 } else { 0 }
-                                )
-                            })?;
-
-                        if !r.status().is_success() {
-                            let code = r.status();
-                            let text = r.text().await.unwrap_or_default();
-                            error!(
-                                "single relay not ok: {} wanted={} got={:?} status={} body={}",
-                                relay, want_on, got, code, text
-                            );
-                            failures.push((relay, want_on, code.as_u16(), text));
-                            // If you want to stop on first failure, break here.
-                            continue;
-                        }
+                                )));
+                            }
+                        };
 
                         // (Optional) read body for logging
                         let b = r.text().await.unwrap_or_default();
@@ -191,25 +269,60 @@ impl DeviceDriver for ControlByWebDriver {
 
                     // 4) Finalize
                     if failures.is_empty() {
-                        return Ok::<CommandResult, anyhow::Error>(CommandResult {
+                        Ok(CommandResult {
                             ok: true,
                             message: format!(
                                 "relays updated (fallback ok, {} fixed)",
                                 successes.len()
                             ),
-                        });
+                            response: Some(json!({ "relays": actual, "fixed": successes })),
+                            retries: 0,
+                        })
                     } else {
-                        let summary = serde_json::to_string(&failures).unwrap_or_default();
-                        anyhow::bail!("fallback failed for some relays: {}", summary);
+                        Err(DriverError::PartialRelayFailure(failures).into())
                     }
                 }
             })
+            .count_retries()
+            .await;
+        res.map(|r| CommandResult { retries, ..r })
+            .map_err(|e| classify(e).with_retries(retries))
+    }
+
+    async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError> {
+        let binding = self
+            .resolver
+            .resolve(installation_id)
+            .ok_or_else(|| DriverError::UnresolvedInstallation(installation_id.to_string()))?;
+        let relay_map = self.relay_map(installation_id)?;
+        let (account_id, device_id) = (binding.account_id, binding.device_id);
+        let minutes_valid = self.dat_minutes_valid;
+        let http = self.http.clone();
+        let installation_id = installation_id.to_string();
+
+        self.dats
+            .with_device_access(account_id, &device_id, minutes_valid, move |dat_ref| {
+                let dat = dat_ref.to_string();
+                let http = http.clone();
+                let installation_id = installation_id.clone();
+                let relay_map = relay_map.clone();
+                async move {
+                    let url = Url::parse(DAT_BASE)?.join(&format!("DAT/{dat}/state.json"))?;
+                    debug!("DAT state url={url}");
+                    let resp = http
+                        .send("DAT state", http.get(url))
+                        .await
+                        .map_err(device_error)?;
+                    let body = resp.text().await.context("reading DAT state body")?;
+                    parse_device_status(&body, &installation_id, &relay_map)
+                }
+            })
             .await
+            .map_err(classify)
     }
 
-    async fn status(&self, installation_id: &str) -> Result<serde_json::Value> {
-        // Stub for now
-        Ok(json!({ "ok": true, "installation_id": installation_id }))
+    async fn shutdown(&self) {
+        self.dats.shutdown().await;
     }
 }
 
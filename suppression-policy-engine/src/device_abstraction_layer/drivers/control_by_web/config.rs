@@ -2,6 +2,9 @@ use reqwest::Client;
 use reqwest::Url;
 use std::time::Duration;
 
+use super::http::RetryPolicy;
+use super::relay_map::RelayMapBook;
+
 #[derive(Clone, Debug)]
 pub struct ControlByWebConfig {
     pub base_url: Url,
@@ -9,10 +12,18 @@ pub struct ControlByWebConfig {
     pub password: String,
     pub connect_timeout: Duration,
     pub request_timeout: Duration,
+    pub relay_maps: RelayMapBook,
+    /// Lifetime requested for Device Access Tokens; they are cached and
+    /// reused until shortly before this runs out.
+    pub dat_minutes_valid: u32,
+    pub retry: RetryPolicy,
 }
 
 impl ControlByWebConfig {
-    pub fn from_env() -> anyhow::Result<Self> {
+    /// Credentials and tuning from the environment. `relay_maps` are loaded
+    /// and validated separately, at startup, so a bad map is never mistaken
+    /// for an unconfigured driver.
+    pub fn from_env(relay_maps: RelayMapBook) -> anyhow::Result<Self> {
         let base =
             std::env::var("CBW_BASE_URL").map_err(|_| anyhow::anyhow!("CBW_BASE_URL not set"))?;
         let username =
@@ -20,12 +31,28 @@ impl ControlByWebConfig {
         let password =
             std::env::var("CBW_PASSWORD").map_err(|_| anyhow::anyhow!("CBW_PASSWORD not set"))?;
 
+        let dat_minutes_valid = match std::env::var("CBW_DAT_MINUTES_VALID") {
+            Ok(v) => v.parse().ok().filter(|m| *m > 0).ok_or_else(|| {
+                anyhow::anyhow!("CBW_DAT_MINUTES_VALID must be a positive integer")
+            })?,
+            Err(_) => 15,
+        };
+        let mut retry = RetryPolicy::default();
+        if let Ok(v) = std::env::var("CBW_RETRY_MAX_ATTEMPTS") {
+            retry.max_attempts = v.parse().ok().filter(|n| *n > 0).ok_or_else(|| {
+                anyhow::anyhow!("CBW_RETRY_MAX_ATTEMPTS must be a positive integer")
+            })?;
+        }
+
         Ok(Self {
             base_url: Url::parse(&base)?,
             username,
             password,
             connect_timeout: Duration::from_secs(5),
             request_timeout: Duration::from_secs(60),
+            relay_maps,
+            dat_minutes_valid,
+            retry,
         })
     }
 
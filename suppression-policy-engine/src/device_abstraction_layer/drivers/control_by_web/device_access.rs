@@ -1,11 +1,56 @@
+use super::http::{CloudError, CloudHttp};
 use super::token::TokenManager;
 use anyhow::{anyhow, Context, Result};
-use reqwest::{Client, Url};
+use reqwest::{StatusCode, Url};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tracing::{debug, warn};
 
+/// Refresh a cached DAT once less than this much validity is left
+/// (capped at a quarter of its lifetime for very short-lived tokens).
+const DAT_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// Returned by device calls when the device rejects the DAT (401/403).
+/// `with_device_access` drops the cached token and retries once with a new one.
+#[derive(Debug, thiserror::Error)]
+#[error("device rejected DAT: status={0}")]
+pub struct DatRejected(pub StatusCode);
+
+impl DatRejected {
+    /// `Some` when `status` means the DAT is no longer accepted.
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN).then_some(Self(status))
+    }
+    /// Turn a failed device call into [`DatRejected`] when the status says so,
+    /// otherwise keep the original error.
+    pub fn from_cloud(e: CloudError) -> anyhow::Error {
+        match e.status().and_then(Self::from_status) {
+            Some(rejected) => rejected.into(),
+            None => e.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedDat {
+    token: String,
+    created_at: Instant,
+    expires_at: Instant,
+}
+
+impl CachedDat {
+    /// Usable at `now` with enough validity left that it won't expire mid-command.
+    fn is_fresh(&self, now: Instant) -> bool {
+        let margin = DAT_REFRESH_MARGIN.min((self.expires_at - self.created_at) / 4);
+        now + margin < self.expires_at
+    }
+}
+
+type DatSlot = Arc<Mutex<Option<CachedDat>>>;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct DeviceAccessTokenItem {
     pub token: String,
@@ -13,17 +58,21 @@ pub struct DeviceAccessTokenItem {
 
 #[derive(Clone)]
 pub struct DeviceAccessTokenManager {
-    client: Client,
+    http: CloudHttp,
     base_url: Url,
     tm: Arc<Mutex<TokenManager>>,
+    /// One slot per (account, device); the slot lock keeps concurrent callers
+    /// from creating duplicate DATs for the same device.
+    cache: Arc<Mutex<HashMap<(u64, String), DatSlot>>>,
 }
 
 impl DeviceAccessTokenManager {
-    pub fn new(client: Client, base_url: Url, tm: Arc<Mutex<TokenManager>>) -> Self {
+    pub fn new(http: CloudHttp, base_url: Url, tm: Arc<Mutex<TokenManager>>) -> Self {
         Self {
-            client,
+            http,
             base_url,
             tm,
+            cache: Arc::new(Mutex::new(HashMap::new())),
         }
     }
 
@@ -57,17 +106,12 @@ impl DeviceAccessTokenManager {
     ) -> Result<Vec<DeviceAccessTokenItem>> {
         let url = self.list_url(account_id, device_id)?;
         debug!("List DATs url={url}");
-        let mut req = self.client.get(url.clone());
+        let mut req = self.http.get(url.clone());
         {
             let mut tm = self.tm.lock().await;
             req = tm.attach_auth(req).await?;
         }
-        let resp = req
-            .send()
-            .await
-            .context("DAT list send failed")?
-            .error_for_status()
-            .context("DAT list non-2xx")?;
+        let resp = self.http.send("DAT list", req).await?;
         let body = resp.bytes().await.context("reading DAT list body")?;
         let items: Vec<DeviceAccessTokenItem> = serde_json::from_slice(&body).map_err(|e| {
             anyhow!(
@@ -88,19 +132,14 @@ impl DeviceAccessTokenManager {
         let url = self.create_url(account_id, device_id)?;
         debug!("Create DAT url={url} minutes_valid={minutes_valid}");
         let mut req = self
-            .client
+            .http
             .post(url.clone())
             .form(&[("minutesValid", minutes_valid.to_string())]);
         {
             let mut tm = self.tm.lock().await;
             req = tm.attach_auth(req).await?;
         }
-        let resp = req
-            .send()
-            .await
-            .context("DAT create send failed")?
-            .error_for_status()
-            .context("DAT create non-2xx")?;
+        let resp = self.http.send("DAT create", req).await?;
         let body = resp.bytes().await.unwrap_or_default();
         debug!(
             "DAT create response len={} body={}",
@@ -114,17 +153,12 @@ impl DeviceAccessTokenManager {
     pub async fn delete_token(&self, account_id: u64, device_id: &str, dat: &str) -> Result<()> {
         let url = self.delete_url(account_id, device_id, dat)?;
         debug!("Delete DAT url={url}");
-        let mut req = self.client.delete(url.clone());
+        let mut req = self.http.delete(url.clone());
         {
             let mut tm = self.tm.lock().await;
             req = tm.attach_auth(req).await?;
         }
-        let resp = req
-            .send()
-            .await
-            .context("DAT delete send failed")?
-            .error_for_status()
-            .context("DAT delete non-2xx")?;
+        let resp = self.http.send("DAT delete", req).await?;
         let body = resp.bytes().await.unwrap_or_default();
         debug!(
             "DAT delete response len={} body={}",
@@ -134,41 +168,93 @@ impl DeviceAccessTokenManager {
         Ok(())
     }
 
-    /// Ensure a usable token for (account, device) without caching:
-    /// - If any tokens exist, just return one (prefers the last element).
-    /// - Otherwise, create one (minutes_valid) and list again; return the newest token.
-    ///
-    /// Returns (token, created_new).
-    pub async fn ensure_device_access_token(
+    /// Create a DAT valid for `minutes_valid` and return its token.
+    /// The create call doesn't return the token, so the list is diffed
+    /// before/after to find it (falling back to the newest entry).
+    async fn create_and_fetch(
         &self,
         account_id: u64,
         device_id: &str,
         minutes_valid: u32,
-    ) -> Result<(String, bool)> {
+    ) -> Result<String> {
         let before = self.list_tokens(account_id, device_id).await?;
-        if let Some(tok) = before.last().map(|i| i.token.clone()) {
-            return Ok((tok, false));
-        }
-
-        // none exist — create and list again
         self.create_token(account_id, device_id, minutes_valid)
             .await?;
         let after = self.list_tokens(account_id, device_id).await?;
-        if let Some(tok) = after.last().map(|i| i.token.clone()) {
-            return Ok((tok, true));
+        after
+            .iter()
+            .rev()
+            .find(|i| !before.iter().any(|b| b.token == i.token))
+            .or_else(|| after.last())
+            .map(|i| i.token.clone())
+            .ok_or_else(|| anyhow!("DAT create succeeded but no token found after listing"))
+    }
+
+    async fn slot(&self, account_id: u64, device_id: &str) -> DatSlot {
+        self.cache
+            .lock()
+            .await
+            .entry((account_id, device_id.to_string()))
+            .or_default()
+            .clone()
+    }
+
+    /// Return a cached DAT for (account, device), creating one when there is
+    /// none or the cached one is close to expiry. Expired tokens are simply
+    /// dropped; the cloud discards them on its own. DATs listed for the device
+    /// but not created by this process (other instances, technicians,
+    /// integrations, or a previous run of this one) are never touched.
+    pub async fn ensure_device_access_token(
+        &self,
+        account_id: u64,
+        device_id: &str,
+        minutes_valid: u32,
+    ) -> Result<String> {
+        let slot = self.slot(account_id, device_id).await;
+        let mut cached = slot.lock().await;
+        if let Some(c) = cached.as_ref().filter(|c| c.is_fresh(Instant::now())) {
+            return Ok(c.token.clone());
         }
+        // measure validity from before the create so we never overestimate it
+        let created_at = Instant::now();
+        let token = self
+            .create_and_fetch(account_id, device_id, minutes_valid)
+            .await?;
+        debug!("cached new DAT for account={account_id} device={device_id}");
+        *cached = Some(CachedDat {
+            token: token.clone(),
+            created_at,
+            expires_at: created_at + Duration::from_secs(u64::from(minutes_valid) * 60),
+        });
+        Ok(token)
+    }
 
-        Err(anyhow!(
-            "DAT create succeeded but no token found after listing"
-        ))
+    /// Forget `dat` for (account, device) if it is still the cached token,
+    /// and delete it in the cloud so it stops counting against the device's
+    /// DAT limit.
+    async fn invalidate(&self, account_id: u64, device_id: &str, dat: &str) {
+        let slot = self.slot(account_id, device_id).await;
+        let mut cached = slot.lock().await;
+        if cached.as_ref().is_some_and(|c| c.token == dat) {
+            *cached = None;
+        }
+        // a rejected token may already be gone on the cloud side
+        if let Err(e) = self.delete_token(account_id, device_id, dat).await {
+            debug!("failed to delete rejected DAT for device {device_id}: {e:#}");
+        }
     }
 
-    /// Context-manager style helper: create or pick a DAT, run `f(dat)`, then delete it if we created it.
+    /// Context-manager style helper: run `f(dat)` with a cached DAT for the device.
+    /// If `f` fails with [`DatRejected`] the token is invalidated and `f` runs
+    /// once more with a fresh DAT.
     ///
     /// Usage:
-    /// device_tokens.with_device_access(aid, did, 5, |dat| async move {
-    ///     // use `dat` here (e.g., build /DAT/{dat}/state.json URL)
-    ///     Ok(())
+    /// device_tokens.with_device_access(aid, did, 15, |dat| {
+    ///     let dat = dat.to_string();
+    ///     async move {
+    ///         // use `dat` here (e.g., build /DAT/{dat}/state.json URL)
+    ///         Ok(())
+    ///     }
     /// }).await?;
     pub async fn with_device_access<F, Fut, T>(
         &self,
@@ -178,21 +264,78 @@ impl DeviceAccessTokenManager {
         f: F,
     ) -> Result<T>
     where
-        F: FnOnce(&str) -> Fut,
+        F: Fn(&str) -> Fut,
         Fut: std::future::Future<Output = Result<T>>,
     {
-        let (dat, created) = self
+        let dat = self
             .ensure_device_access_token(account_id, device_id, minutes_valid)
             .await?;
-        let res = f(&dat).await;
-        if created {
-            if let Err(e) = self.delete_token(account_id, device_id, &dat).await {
+        match f(&dat).await {
+            Err(e) if e.downcast_ref::<DatRejected>().is_some() => {
+                warn!("DAT rejected for device {device_id} ({e}); retrying with a new one");
+                self.invalidate(account_id, device_id, &dat).await;
+                let dat = self
+                    .ensure_device_access_token(account_id, device_id, minutes_valid)
+                    .await?;
+                f(&dat).await
+            }
+            res => res,
+        }
+    }
+
+    /// Delete every cached DAT that is still valid. Called on shutdown.
+    pub async fn shutdown(&self) {
+        let slots: Vec<_> = self.cache.lock().await.drain().collect();
+        let now = Instant::now();
+        for ((account_id, device_id), slot) in slots {
+            let Some(c) = slot.lock().await.take() else {
+                continue;
+            };
+            if c.expires_at <= now {
+                continue;
+            }
+            if let Err(e) = self.delete_token(account_id, &device_id, &c.token).await {
                 warn!(
                     "cleanup: failed to delete DAT {} for device {}: {:?}",
-                    dat, device_id, e
+                    c.token, device_id, e
                 );
             }
         }
-        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dat(created_at: Instant, valid: Duration) -> CachedDat {
+        CachedDat {
+            token: "t".into(),
+            created_at,
+            expires_at: created_at + valid,
+        }
+    }
+
+    #[test]
+    fn refreshes_before_expiry() {
+        let t0 = Instant::now();
+        let d = dat(t0, Duration::from_secs(15 * 60));
+        assert!(d.is_fresh(t0));
+        assert!(d.is_fresh(t0 + Duration::from_secs(13 * 60)));
+        // inside the 60s margin
+        assert!(!d.is_fresh(t0 + Duration::from_secs(14 * 60 + 30)));
+
+        // one-minute tokens refresh in their last 15s rather than immediately
+        let short = dat(t0, Duration::from_secs(60));
+        assert!(short.is_fresh(t0 + Duration::from_secs(40)));
+        assert!(!short.is_fresh(t0 + Duration::from_secs(50)));
+    }
+
+    #[test]
+    fn only_auth_statuses_reject_the_dat() {
+        assert!(DatRejected::from_status(StatusCode::UNAUTHORIZED).is_some());
+        assert!(DatRejected::from_status(StatusCode::FORBIDDEN).is_some());
+        assert!(DatRejected::from_status(StatusCode::NOT_FOUND).is_none());
+        assert!(DatRejected::from_status(StatusCode::BAD_GATEWAY).is_none());
     }
 }
@@ -0,0 +1,124 @@
+use anyhow::Context;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::relay_map::RelayMap;
+use super::relay_plan::parse_on;
+use crate::device_abstraction_layer::status::{DeviceInputs, RelayStatus};
+use crate::device_abstraction_layer::DeviceStatus;
+
+/// Parse a device `state.json` body into a typed [`DeviceStatus`].
+///
+/// Relays come from `map` (keyed by friendly name, which the map keeps unique). Inputs are recognised by
+/// key: `…digitalInputN` → digital, `…analogInputN` and `vin` → analog.
+/// `utcTime` and `firmwareVersion` fill the device clock and firmware.
+pub fn parse_device_status(
+    body: &str,
+    installation_id: &str,
+    map: &RelayMap,
+) -> anyhow::Result<DeviceStatus> {
+    let v: Value = serde_json::from_str(body).with_context(|| {
+        format!(
+            "parsing device state.json: {}",
+            &body[..body.len().min(400)]
+        )
+    })?;
+    let obj = v.as_object().context("state.json was not an object")?;
+
+    let mut relays = BTreeMap::new();
+    let mut inputs = DeviceInputs::default();
+    let mut device_time_s = None;
+    let mut firmware = None;
+
+    for (k, val) in obj {
+        if let Some(def) = map.relay(k) {
+            if let Some(on) = parse_on(val) {
+                let status = RelayStatus {
+                    key: k.clone(),
+                    on,
+                    role: def.role,
+                    zone: def.zone.clone(),
+                };
+                if relays.insert(def.name.clone(), status).is_some() {
+                    anyhow::bail!("relay name `{}` maps to more than one relay", def.name);
+                }
+            }
+            continue;
+        }
+
+        let lower = k.to_ascii_lowercase();
+        if lower.contains("digitalinput") {
+            if let Some(on) = parse_on(val) {
+                inputs.digital.insert(k.clone(), on);
+            }
+        } else if lower.contains("analoginput") || lower == "vin" {
+            if let Some(x) = parse_number(val) {
+                inputs.analog.insert(k.clone(), x);
+            }
+        } else if lower == "utctime" {
+            device_time_s = parse_number(val).map(|t| t as u64);
+        } else if lower == "firmwareversion" {
+            firmware = val
+                .as_str()
+                .map(str::to_string)
+                .or_else(|| val.as_f64().map(|n| n.to_string()));
+        }
+    }
+
+    Ok(DeviceStatus {
+        installation_id: installation_id.to_string(),
+        relays,
+        inputs,
+        device_time_s,
+        firmware,
+    })
+}
+
+/// Numbers arrive as JSON numbers or strings like "12.4 @1.3" / "3.30 V".
+fn parse_number(val: &Value) -> Option<f64> {
+    if let Some(n) = val.as_f64() {
+        return Some(n);
+    }
+    val.as_str()?.split([' ', '@']).next()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::drivers::control_by_web::relay_map::RelayMapBook;
+    use crate::device_abstraction_layer::status::RelayRole;
+
+    #[test]
+    fn parses_relays_inputs_clock_and_firmware() {
+        let book = RelayMapBook::parse(
+            r#"
+            [default]
+            relays = [
+                { key = "x19Relay4", role = "pump", zone = "house", name = "Booster pump 1" },
+                { key = "x19Relay11", role = "valve", name = "Priority valve 1" },
+            ]
+            commands = { monitor = [] }
+            "#,
+        )
+        .unwrap();
+        let map = book.for_installation("h").unwrap();
+        let body = r#"{
+            "x19Relay4": "1 @1.3", "x19Relay11": 0, "x19Relay99": "1",
+            "digitalInput1": "1", "x21DigitalInput2": false,
+            "analogInput1": "3.31 V", "vin": 24.1,
+            "utcTime": "1760000000", "firmwareVersion": "1.07"
+        }"#;
+
+        let st = parse_device_status(body, "h", map).unwrap();
+        assert_eq!(st.relays.len(), 2);
+        assert!(st.relays["Booster pump 1"].on);
+        assert_eq!(st.relays["Booster pump 1"].role, RelayRole::Pump);
+        assert!(!st.relays["Priority valve 1"].on);
+        assert!(st.inputs.digital["digitalInput1"]);
+        assert!(!st.inputs.digital["x21DigitalInput2"]);
+        assert_eq!(st.inputs.analog["analogInput1"], 3.31);
+        assert_eq!(st.inputs.analog["vin"], 24.1);
+        assert_eq!(st.device_time_s, Some(1_760_000_000));
+        assert_eq!(st.firmware.as_deref(), Some("1.07"));
+    }
+}
@@ -0,0 +1,370 @@
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, IntoUrl, RequestBuilder, Response, StatusCode};
+use std::cell::Cell;
+use std::future::Future;
+use std::time::Duration;
+use tracing::{error, warn};
+
+/// Longest `Retry-After` we are willing to sleep for; anything above is clamped.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+tokio::task_local! {
+    static RETRIES: Cell<u32>;
+}
+
+pub trait CountRetries: Future + Sized {
+    /// Run the future, returning its output plus the number of retries any
+    /// [`CloudHttp::send`] inside it performed.
+    fn count_retries(self) -> impl Future<Output = (Self::Output, u32)> {
+        RETRIES.scope(Cell::new(0), async move {
+            let out = self.await;
+            (out, RETRIES.with(Cell::get))
+        })
+    }
+}
+
+impl<F: Future> CountRetries for F {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Worth retrying: timeouts, connection failures, 408/429/5xx.
+    Transient,
+    /// Retrying won't help: other 4xx, bad requests, decode errors.
+    Permanent,
+}
+
+pub fn classify_status(status: StatusCode) -> ErrorClass {
+    if status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+    {
+        ErrorClass::Transient
+    } else {
+        ErrorClass::Permanent
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CloudError {
+    #[error("{what} returned status={status} body={body}")]
+    Status {
+        what: String,
+        status: StatusCode,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("{what} failed to send: {err}")]
+    Transport { what: String, err: reqwest::Error },
+}
+
+impl CloudError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            CloudError::Status { status, .. } => classify_status(*status),
+            // timeouts, refused/reset connections and truncated bodies
+            CloudError::Transport { err, .. }
+                if err.is_timeout() || err.is_connect() || err.is_request() || err.is_body() =>
+            {
+                ErrorClass::Transient
+            }
+            CloudError::Transport { .. } => ErrorClass::Permanent,
+        }
+    }
+
+    /// Whether sending the request again is safe and may help. A
+    /// non-idempotent request (the DAT create POST) may already have been
+    /// acted on unless the connection itself failed, so that is the only
+    /// failure it is retried on.
+    pub fn is_retryable(&self, idempotent: bool) -> bool {
+        match self {
+            CloudError::Transport { err, .. } if err.is_connect() => true,
+            _ => idempotent && self.class() == ErrorClass::Transient,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            CloudError::Status { status, .. } => Some(*status),
+            CloudError::Transport { .. } => None,
+        }
+    }
+}
+
+/// Bounded exponential backoff with jitter.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total tries including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(250),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (1-based): `base * 2^(retry-1)`,
+    /// capped at `max_delay`, with the lower half randomized.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(1u32 << retry.saturating_sub(1).min(16))
+            .min(self.max_delay);
+        exp / 2 + exp.mul_f64(fastrand::f64() / 2.0)
+    }
+}
+
+/// reqwest client plus the retry policy shared by every ControlByWeb cloud call.
+#[derive(Clone)]
+pub struct CloudHttp {
+    client: Client,
+    policy: RetryPolicy,
+}
+
+impl CloudHttp {
+    pub fn new(client: Client, policy: RetryPolicy) -> Self {
+        Self { client, policy }
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn delete(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    /// Send `req`, retrying transient failures per the policy (for
+    /// non-idempotent methods, only failures to connect). Returns the
+    /// 2xx response, or the last error once retries are exhausted or the
+    /// failure is permanent. `what` labels the call in logs and errors.
+    pub async fn send(&self, what: &str, req: RequestBuilder) -> Result<Response, CloudError> {
+        let idempotent = req
+            .try_clone()
+            .and_then(|r| r.build().ok())
+            .is_some_and(|r| r.method().is_idempotent());
+        let mut attempt = 1;
+        let mut req = Some(req);
+        loop {
+            // form/query bodies always clone; a streaming body just gets one try
+            let Some(cur) = req.take() else {
+                unreachable!("request kept for every retry")
+            };
+            let this = match cur.try_clone() {
+                Some(r) => {
+                    req = Some(cur);
+                    r
+                }
+                None => cur,
+            };
+            let err = match this.send().await {
+                Ok(resp) if resp.status().is_success() => return Ok(resp),
+                Ok(resp) => {
+                    let status = resp.status();
+                    let retry_after = parse_retry_after(&resp);
+                    let body = resp.text().await.unwrap_or_default();
+                    CloudError::Status {
+                        what: what.to_string(),
+                        status,
+                        body,
+                        retry_after,
+                    }
+                }
+                Err(err) => CloudError::Transport {
+                    what: what.to_string(),
+                    err,
+                },
+            };
+
+            if req.is_none() || attempt >= self.policy.max_attempts || !err.is_retryable(idempotent)
+            {
+                error!("{err} (attempt {attempt}, giving up)");
+                return Err(err);
+            }
+
+            let mut delay = self.policy.backoff(attempt);
+            if let CloudError::Status {
+                retry_after: Some(ra),
+                ..
+            } = &err
+            {
+                delay = delay.max((*ra).min(MAX_RETRY_AFTER));
+            }
+            warn!("{err} (attempt {attempt}); retrying in {delay:?}");
+            let _ = RETRIES.try_with(|c| c.set(c.get() + 1));
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// `Retry-After` in delta-seconds form; HTTP-date values are ignored.
+fn parse_retry_after(resp: &Response) -> Option<Duration> {
+    resp.headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn classifies_statuses() {
+        for s in [500, 502, 503, 504, 429, 408] {
+            let s = StatusCode::from_u16(s).unwrap();
+            assert_eq!(classify_status(s), ErrorClass::Transient, "{s}");
+        }
+        for s in [400, 401, 403, 404, 422] {
+            let s = StatusCode::from_u16(s).unwrap();
+            assert_eq!(classify_status(s), ErrorClass::Permanent, "{s}");
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let p = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+        };
+        for _ in 0..50 {
+            let d1 = p.backoff(1);
+            assert!(d1 >= Duration::from_millis(50) && d1 <= Duration::from_millis(100));
+            let d3 = p.backoff(3);
+            assert!(d3 >= Duration::from_millis(200) && d3 <= Duration::from_millis(400));
+            let d9 = p.backoff(9);
+            assert!(d9 >= Duration::from_millis(500) && d9 <= Duration::from_secs(1));
+        }
+    }
+
+    /// Local endpoint answering every request with `status`; returns its
+    /// URL and a count of the requests it has seen.
+    async fn failing_endpoint(status: u16) -> (String, Arc<AtomicU32>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicU32::new(0));
+        let seen = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                seen.fetch_add(1, Ordering::SeqCst);
+                // read the whole request so closing never resets it
+                let mut req = Vec::new();
+                let mut buf = [0u8; 1024];
+                loop {
+                    let n = sock.read(&mut buf).await.unwrap_or(0);
+                    req.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&req);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let len = text[..end]
+                            .lines()
+                            .find_map(|l| {
+                                l.to_ascii_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap_or(0))
+                            })
+                            .unwrap_or(0);
+                        if n == 0 || req.len() >= end + 4 + len {
+                            break;
+                        }
+                    } else if n == 0 {
+                        break;
+                    }
+                }
+                let resp = format!(
+                    "HTTP/1.1 {status} Nope\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                );
+                let _ = sock.write_all(resp.as_bytes()).await;
+            }
+        });
+        (url, hits)
+    }
+
+    fn cloud(max_attempts: u32) -> CloudHttp {
+        CloudHttp::new(
+            Client::new(),
+            RetryPolicy {
+                max_attempts,
+                base_delay: Duration::from_millis(1),
+                max_delay: Duration::from_millis(5),
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn send_retries_transient_failures_until_attempts_run_out() {
+        let http = cloud(3);
+        let (url, hits) = failing_endpoint(503).await;
+        let (res, retries) = http.send("test", http.get(&url)).count_retries().await;
+        let err = res.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(err.class(), ErrorClass::Transient);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert_eq!(retries, 2);
+
+        // permanent failures get one try
+        let (url, hits) = failing_endpoint(404).await;
+        let err = http.send("test", http.get(&url)).await.unwrap_err();
+        assert_eq!(err.class(), ErrorClass::Permanent);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn post_is_only_retried_when_it_never_connected() {
+        let http = cloud(3);
+        let form = [("minutesValid", "15")];
+
+        // the server saw it, so a 503 may have created the DAT anyway
+        let (url, hits) = failing_endpoint(503).await;
+        let err = http
+            .send("test", http.post(&url).form(&form))
+            .await
+            .unwrap_err();
+        assert!(!err.is_retryable(false));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // nothing listening: safe to try again
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", closed.local_addr().unwrap());
+        drop(closed);
+        let (res, retries) = http
+            .send("test", http.post(&url).form(&form))
+            .count_retries()
+            .await;
+        assert!(res.unwrap_err().is_retryable(false));
+        assert_eq!(retries, 2);
+    }
+
+    #[tokio::test]
+    async fn retries_are_counted_per_scope() {
+        let ((), n) = async {
+            let _ = RETRIES.try_with(|c| c.set(c.get() + 2));
+        }
+        .count_retries()
+        .await;
+        assert_eq!(n, 2);
+        // outside a scope counting is a no-op
+        assert!(RETRIES.try_with(|c| c.get()).is_err());
+    }
+}
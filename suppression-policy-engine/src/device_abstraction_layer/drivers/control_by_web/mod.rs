@@ -2,9 +2,13 @@ mod account;
 mod client;
 pub mod config;
 mod device_access;
+mod device_state;
+mod http;
+pub mod relay_map;
 mod relay_plan;
 mod token;
 
 pub use account::{InMemoryResolver, InstallationAccountResolver};
 pub use client::ControlByWebDriver;
 pub use config::ControlByWebConfig;
+pub use relay_map::RelayMapBook;
@@ -0,0 +1,268 @@
+use anyhow::{bail, Context, Result};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::relay_plan::RelayPlan;
+use crate::device_abstraction_layer::status::RelayRole;
+use crate::device_abstraction_layer::Command;
+use crate::enactor::PlanBook;
+
+/// Built-in map, used when `CBW_RELAY_MAP_PATH` is not set.
+const DEFAULT_RELAY_MAP: &str = include_str!("../../../../config/relays.toml");
+
+/// One physical relay on a device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RelayDef {
+    /// Key in the device's state.json / customState.json (e.g. `x19Relay4`).
+    pub key: String,
+    pub role: RelayRole,
+    #[serde(default)]
+    pub zone: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRelayMap {
+    relays: Vec<RelayDef>,
+    #[serde(default)]
+    commands: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRelayMapFile {
+    #[serde(default)]
+    default: Option<RawRelayMap>,
+    #[serde(default)]
+    installations: HashMap<String, RawRelayMap>,
+}
+
+/// Validated wiring for one device: its relays and which are ON per command.
+#[derive(Debug, Clone)]
+pub struct RelayMap {
+    relays: Vec<RelayDef>,
+    commands: HashMap<Command, Vec<String>>,
+}
+
+impl RelayMap {
+    fn from_raw(raw: RawRelayMap, table: &str) -> Result<Self> {
+        let mut keys = HashSet::new();
+        let mut names = HashSet::new();
+        for r in &raw.relays {
+            if !keys.insert(r.key.as_str()) {
+                bail!("[{table}] relay `{}` is listed twice", r.key);
+            }
+            // device status is keyed by friendly name
+            if !names.insert(r.name.as_str()) {
+                bail!("[{table}] relay name `{}` is used twice", r.name);
+            }
+        }
+
+        let mut commands = HashMap::new();
+        for (name, on) in raw.commands {
+            let cmd = Command::deserialize(StrDeserializer::<ValueError>::new(&name))
+                .map_err(|_| anyhow::anyhow!("[{table}.commands] unknown command `{name}`"))?;
+            let on = if on == ["*"] {
+                raw.relays.iter().map(|r| r.key.clone()).collect()
+            } else {
+                for k in &on {
+                    if !keys.contains(k.as_str()) {
+                        bail!("[{table}.commands] `{name}` uses undefined relay `{k}`");
+                    }
+                }
+                on
+            };
+            commands.insert(cmd, on);
+        }
+
+        Ok(Self {
+            relays: raw.relays,
+            commands,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn relays(&self) -> &[RelayDef] {
+        &self.relays
+    }
+
+    pub fn relay(&self, key: &str) -> Option<&RelayDef> {
+        self.relays.iter().find(|r| r.key == key)
+    }
+
+    /// ON/OFF plan for `cmd`: mapped relays ON, every other relay OFF.
+    /// Commands without a mapping are an error.
+    pub fn plan(&self, cmd: Command) -> Result<RelayPlan> {
+        let on = self
+            .commands
+            .get(&cmd)
+            .with_context(|| format!("command {cmd:?} has no relay mapping"))?;
+        let off = self
+            .relays
+            .iter()
+            .filter(|r| !on.contains(&r.key))
+            .map(|r| r.key.clone())
+            .collect();
+        Ok(RelayPlan {
+            on: on.clone(),
+            off,
+        })
+    }
+}
+
+/// All relay maps: an optional default plus per-installation maps.
+#[derive(Debug, Clone)]
+pub struct RelayMapBook {
+    default: Option<RelayMap>,
+    installations: HashMap<String, RelayMap>,
+}
+
+impl RelayMapBook {
+    /// Load from `CBW_RELAY_MAP_PATH`, or the built-in `config/relays.toml`.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("CBW_RELAY_MAP_PATH") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading relay map {path}"))?;
+                Self::parse(&raw).with_context(|| format!("invalid relay map {path}"))
+            }
+            Err(_) => Self::parse(DEFAULT_RELAY_MAP).context("invalid built-in relay map"),
+        }
+    }
+
+    pub fn parse(raw: &str) -> Result<Self> {
+        let file: RawRelayMapFile = toml::from_str(raw)?;
+        let default = file
+            .default
+            .map(|m| RelayMap::from_raw(m, "default"))
+            .transpose()?;
+        let mut installations = HashMap::new();
+        for (id, m) in file.installations {
+            let map = RelayMap::from_raw(m, &format!("installations.{id}"))?;
+            installations.insert(id, map);
+        }
+        Ok(Self {
+            default,
+            installations,
+        })
+    }
+
+    /// Fail if any plan sends a command the relay map it lands on cannot
+    /// switch. Checked for the default plans against the default map and for
+    /// every installation that has its own plans or its own map.
+    pub fn check_plans(&self, plans: &PlanBook) -> Result<()> {
+        let mut ids: BTreeSet<&str> = plans.installation_ids().collect();
+        ids.extend(self.installations.keys().map(String::as_str));
+
+        let mut targets = vec![(
+            "default".to_string(),
+            self.default.as_ref(),
+            plans.default_commands().collect::<Vec<_>>(),
+        )];
+        for id in ids {
+            let commands = plans
+                .resolve_all(id)
+                .into_iter()
+                .flat_map(|p| p.commands)
+                .collect();
+            targets.push((
+                format!("installations.{id}"),
+                self.for_installation(id),
+                commands,
+            ));
+        }
+
+        for (table, map, commands) in targets {
+            // no map at all is reported per run by the driver
+            let Some(map) = map else { continue };
+            for cmd in commands {
+                if cmd != Command::Noop && !map.commands.contains_key(&cmd) {
+                    bail!("plans for [{table}] use {cmd:?}, which its relay map does not map");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The installation's own map, else the default.
+    pub fn for_installation(&self, installation_id: &str) -> Option<&RelayMap> {
+        self.installations
+            .get(installation_id)
+            .or(self.default.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_map_keeps_previous_wiring() {
+        let book = RelayMapBook::parse(DEFAULT_RELAY_MAP).unwrap();
+        let map = book.for_installation("sebastians-house").unwrap();
+
+        let low = map.plan(Command::EnablePumpsLow).unwrap();
+        assert_eq!(low.on, vec!["x21Relay3", "x19Relay4"]);
+        assert_eq!(low.off.len(), map.relays().len() - 2);
+
+        let all = map.plan(Command::Lockdown).unwrap();
+        assert_eq!(all.on.len(), map.relays().len());
+        assert!(all.off.is_empty());
+    }
+
+    #[test]
+    fn unmapped_command_is_rejected() {
+        let book = RelayMapBook::parse(DEFAULT_RELAY_MAP).unwrap();
+        let map = book.for_installation("anywhere").unwrap();
+        assert!(map.plan(Command::StagePumps).is_err());
+    }
+
+    #[test]
+    fn installation_map_overrides_default_and_validates_keys() {
+        let raw = r#"
+            [installations.cabin]
+            relays = [{ key = "r1", role = "valve", zone = "north", name = "North valve" }]
+            commands = { monitor = [], open_valves_all = ["r1"] }
+        "#;
+        let book = RelayMapBook::parse(raw).unwrap();
+        assert!(book.for_installation("other").is_none());
+        let plan = book
+            .for_installation("cabin")
+            .unwrap()
+            .plan(Command::OpenValvesAll)
+            .unwrap();
+        assert_eq!(plan.on, vec!["r1"]);
+
+        let bad = raw.replace(r#"["r1"]"#, r#"["r2"]"#);
+        assert!(RelayMapBook::parse(&bad).is_err());
+
+        let same_name = raw.replace(
+            r#"relays = [{ key = "r1", role = "valve", zone = "north", name = "North valve" }]"#,
+            r#"relays = [
+                { key = "r1", role = "valve", name = "North valve" },
+                { key = "r2", role = "valve", name = "North valve" },
+            ]"#,
+        );
+        let err = RelayMapBook::parse(&same_name).unwrap_err().to_string();
+        assert!(err.contains("North valve"), "{err}");
+    }
+
+    #[test]
+    fn builtin_plans_only_use_mapped_commands() {
+        let book = RelayMapBook::parse(DEFAULT_RELAY_MAP).unwrap();
+        let plans = PlanBook::parse(include_str!("../../../../config/plans.toml")).unwrap();
+        book.check_plans(&plans).unwrap();
+
+        let staging = PlanBook::parse(
+            "[defaults]\nobserve = [\"monitor\"]\nprepare = [\"noop\"]\n\
+             defend = [\"noop\"]\ncontain = [\"noop\"]\nsuppress = [\"noop\"]\n\
+             [installations.cabin]\nprepare = [\"stage_pumps\"]\n",
+        )
+        .unwrap();
+        let err = book.check_plans(&staging).unwrap_err().to_string();
+        assert!(err.contains("installations.cabin"), "{err}");
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Context;
-use reqwest::{Client, Url};
+use reqwest::Url;
 use serde::Deserialize;
 use std::time::{Duration, Instant};
 #[derive(Debug, Deserialize)]
@@ -11,8 +11,11 @@ pub struct TokenResp {
 }
 use tracing::debug;
 
+use super::http::{CloudError, CloudHttp, ErrorClass};
+use crate::device_abstraction_layer::DriverError;
+
 pub struct TokenManager {
-    client: Client,
+    http: CloudHttp,
     auth_token_url: Url,
     username: String,
     password: String,
@@ -23,9 +26,9 @@ pub struct TokenManager {
 }
 
 impl TokenManager {
-    pub fn new(client: Client, auth_token_url: Url, username: String, password: String) -> Self {
+    pub fn new(http: CloudHttp, auth_token_url: Url, username: String, password: String) -> Self {
         Self {
-            client,
+            http,
             auth_token_url,
             username,
             password,
@@ -52,19 +55,16 @@ impl TokenManager {
 
     async fn login_password(&mut self) -> anyhow::Result<String> {
         debug!("Logging password");
+        let req = self.http.post(self.auth_token_url.clone()).form(&[
+            ("grant_type", "password"),
+            ("username", self.username.as_str()),
+            ("password", self.password.as_str()),
+        ]);
         let resp = self
-            .client
-            .post(self.auth_token_url.clone())
-            .form(&[
-                ("grant_type", "password"),
-                ("username", self.username.as_str()),
-                ("password", self.password.as_str()),
-            ])
-            .send()
+            .http
+            .send("token request (password grant)", req)
             .await
-            .context("token request (password grant) failed to send")?
-            .error_for_status()
-            .context("token request (password grant) returned non-2xx")?;
+            .map_err(auth_error)?;
 
         // Read once, then you can log & parse
         let body = resp.bytes().await.context("reading token body")?;
@@ -86,18 +86,15 @@ impl TokenManager {
     }
 
     async fn refresh(&mut self, refresh_token: String) -> anyhow::Result<String> {
+        let req = self.http.post(self.auth_token_url.clone()).form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+        ]);
         let resp = self
-            .client
-            .post(self.auth_token_url.clone())
-            .form(&[
-                ("grant_type", "refresh_token"),
-                ("refresh_token", refresh_token.as_str()),
-            ])
-            .send()
+            .http
+            .send("token request (refresh grant)", req)
             .await
-            .context("token request (refresh grant) failed to send")?
-            .error_for_status()
-            .context("token request (refresh grant) returned non-2xx")?;
+            .map_err(auth_error)?;
 
         let body = resp.bytes().await.context("reading refresh body")?;
         debug!(
@@ -133,3 +130,14 @@ impl TokenManager {
         Ok(req.bearer_auth(tok))
     }
 }
+
+/// A token endpoint that answers with a permanent error (bad credentials,
+/// revoked refresh token) is an auth failure; transient ones stay as they are.
+fn auth_error(e: CloudError) -> anyhow::Error {
+    match e.class() {
+        ErrorClass::Permanent if e.status().is_some() => {
+            DriverError::AuthFailed(e.to_string()).into()
+        }
+        _ => e.into(),
+    }
+}
@@ -1,17 +1,73 @@
 use async_trait::async_trait;
 use tracing::info;
 
-use crate::device_abstraction_layer::{Command, CommandResult, DeviceDriver};
+use crate::device_abstraction_layer::status::DeviceInputs;
+use crate::device_abstraction_layer::{
+    Command, CommandResult, DeviceDriver, DeviceStatus, DriverError,
+};
 
 pub struct MockDriver;
 
 #[async_trait]
 impl DeviceDriver for MockDriver {
-    async fn apply(&self, installation_id: &str, cmd: Command) -> anyhow::Result<CommandResult> {
+    async fn apply(
+        &self,
+        installation_id: &str,
+        cmd: Command,
+    ) -> Result<CommandResult, DriverError> {
         info!(%installation_id, ?cmd, "MockDriver.apply");
         Ok(CommandResult {
             ok: true,
             message: format!("applied {:?}", cmd),
+            response: None,
+            retries: 0,
         })
     }
+
+    async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError> {
+        Ok(DeviceStatus {
+            installation_id: installation_id.to_string(),
+            relays: Default::default(),
+            inputs: DeviceInputs::default(),
+            device_time_s: None,
+            firmware: Some("mock".into()),
+        })
+    }
+}
+
+/// Accepts commands but never finishes one, so a run stays `Running`.
+#[cfg(test)]
+pub struct Stalled;
+
+#[cfg(test)]
+#[async_trait]
+impl DeviceDriver for Stalled {
+    async fn apply(&self, _: &str, _: Command) -> Result<CommandResult, DriverError> {
+        std::future::pending().await
+    }
+
+    async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError> {
+        MockDriver.status(installation_id).await
+    }
+}
+
+/// Reports every command as refused, so a run fails with `step_failed`.
+#[cfg(test)]
+pub struct Refusing;
+
+#[cfg(test)]
+#[async_trait]
+impl DeviceDriver for Refusing {
+    async fn apply(&self, _: &str, _: Command) -> Result<CommandResult, DriverError> {
+        Ok(CommandResult {
+            ok: false,
+            message: "device refused".into(),
+            response: None,
+            retries: 0,
+        })
+    }
+
+    async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError> {
+        MockDriver.status(installation_id).await
+    }
 }
@@ -4,3 +4,5 @@ pub mod mock;
 pub use control_by_web::ControlByWebConfig;
 pub use control_by_web::ControlByWebDriver;
 pub use mock::MockDriver;
+#[cfg(test)]
+pub use mock::{Refusing, Stalled};
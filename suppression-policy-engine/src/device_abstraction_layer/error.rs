@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A relay that did not end up in its planned state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayMismatch {
+    pub relay: String,
+    pub want_on: bool,
+    /// State read back from the device, if it reported one.
+    pub got: Option<bool>,
+    /// Why the corrective write failed (status and body from the device).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Stable, serializable class of a [`DriverError`]; stored on steps and runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriverErrorKind {
+    UnresolvedInstallation,
+    NotConfigured,
+    AuthFailed,
+    DeviceOffline,
+    PartialRelayFailure,
+    Timeout,
+    RateLimited,
+    Other,
+}
+
+impl DriverErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DriverErrorKind::UnresolvedInstallation => "unresolved_installation",
+            DriverErrorKind::NotConfigured => "not_configured",
+            DriverErrorKind::AuthFailed => "auth_failed",
+            DriverErrorKind::DeviceOffline => "device_offline",
+            DriverErrorKind::PartialRelayFailure => "partial_relay_failure",
+            DriverErrorKind::Timeout => "timeout",
+            DriverErrorKind::RateLimited => "rate_limited",
+            DriverErrorKind::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DriverError {
+    #[error("no account/device binding for installation {0}")]
+    UnresolvedInstallation(String),
+    /// Relay map missing or the command has no mapping.
+    #[error("{0}")]
+    NotConfigured(String),
+    #[error("authentication failed: {0}")]
+    AuthFailed(String),
+    /// The device (or the cloud's path to it) is unreachable.
+    #[error("device offline: {0}")]
+    DeviceOffline(String),
+    #[error("{} relay(s) did not reach the planned state", .0.len())]
+    PartialRelayFailure(Vec<RelayMismatch>),
+    #[error("timed out: {0}")]
+    Timeout(String),
+    #[error("rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+    /// `source` is what was left after the driver retried past `retries`
+    /// transient failures; see [`DriverError::with_retries`].
+    #[error("{source} (after {retries} retries)")]
+    Retried {
+        retries: u32,
+        source: Box<DriverError>,
+    },
+}
+
+impl DriverError {
+    pub fn kind(&self) -> DriverErrorKind {
+        match self {
+            DriverError::UnresolvedInstallation(_) => DriverErrorKind::UnresolvedInstallation,
+            DriverError::NotConfigured(_) => DriverErrorKind::NotConfigured,
+            DriverError::AuthFailed(_) => DriverErrorKind::AuthFailed,
+            DriverError::DeviceOffline(_) => DriverErrorKind::DeviceOffline,
+            DriverError::PartialRelayFailure(_) => DriverErrorKind::PartialRelayFailure,
+            DriverError::Timeout(_) => DriverErrorKind::Timeout,
+            DriverError::RateLimited { .. } => DriverErrorKind::RateLimited,
+            DriverError::Other(_) => DriverErrorKind::Other,
+            DriverError::Retried { source, .. } => source.kind(),
+        }
+    }
+
+    /// Record that the driver retried `retries` times before failing; a no-op
+    /// for 0.
+    pub fn with_retries(self, retries: u32) -> Self {
+        if retries == 0 {
+            return self;
+        }
+        DriverError::Retried {
+            retries,
+            source: Box::new(self),
+        }
+    }
+
+    /// Driver-level retries behind this failure.
+    pub fn retries(&self) -> u32 {
+        match self {
+            DriverError::Retried { retries, .. } => *retries,
+            _ => 0,
+        }
+    }
+
+    /// The failure itself, without the retry count.
+    pub fn root(&self) -> &DriverError {
+        match self {
+            DriverError::Retried { source, .. } => source.root(),
+            e => e,
+        }
+    }
+
+    /// Worth trying the same command again shortly; everything else needs a
+    /// human (config, credentials, wiring) or the device to come back.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.root(),
+            DriverError::Timeout(_) | DriverError::RateLimited { .. }
+        )
+    }
+
+    /// How long the remote side asked us to back off, if it said.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self.root() {
+            DriverError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
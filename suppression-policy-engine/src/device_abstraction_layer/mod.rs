@@ -1,6 +1,10 @@
 pub mod command;
 pub mod drivers;
+pub mod error;
+pub mod status;
 pub mod traits;
 
 pub use command::{Command, CommandResult};
+pub use error::{DriverError, DriverErrorKind, RelayMismatch};
+pub use status::DeviceStatus;
 pub use traits::DeviceDriver;
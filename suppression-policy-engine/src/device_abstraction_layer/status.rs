@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Live snapshot of an installation's device, as read from the hardware.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    pub installation_id: String,
+    /// Relays keyed by friendly name.
+    pub relays: BTreeMap<String, RelayStatus>,
+    pub inputs: DeviceInputs,
+    /// Device clock, seconds since the Unix epoch (UTC), if reported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_time_s: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayStatus {
+    /// Device-side key (e.g. `x19Relay4`).
+    pub key: String,
+    pub on: bool,
+    pub role: RelayRole,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+}
+
+/// What a relay drives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayRole {
+    Pump,
+    Valve,
+    Sensor,
+    Other,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceInputs {
+    /// Analog readings by device key (volts, or the unit the device is set to).
+    pub analog: BTreeMap<String, f64>,
+    pub digital: BTreeMap<String, bool>,
+}
@@ -1,13 +1,18 @@
-use anyhow::Result;
 use async_trait::async_trait;
 
-use super::{Command, CommandResult};
+use super::{Command, CommandResult, DeviceStatus, DriverError};
 
 #[async_trait]
 pub trait DeviceDriver: Send + Sync {
-    async fn apply(&self, installation_id: &str, cmd: Command) -> Result<CommandResult>;
+    async fn apply(
+        &self,
+        installation_id: &str,
+        cmd: Command,
+    ) -> Result<CommandResult, DriverError>;
 
-    async fn status(&self, installation_id: &str) -> Result<serde_json::Value> {
-        Ok(serde_json::json!({"ok": true, "installation_id": installation_id}))
-    }
+    /// Read live relay and input state from the installation's device.
+    async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError>;
+
+    /// Release any held device credentials before the process exits.
+    async fn shutdown(&self) {}
 }
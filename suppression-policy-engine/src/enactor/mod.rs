@@ -1,60 +1,79 @@
 // src/enactor/mod.rs
+pub mod plan;
+
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
 
-use crate::device_abstraction_layer::{Command, CommandResult, DeviceDriver};
+use crate::device_abstraction_layer::{Command, DeviceDriver, DriverError, DriverErrorKind};
 use crate::policy::Policy;
+use crate::time::now_ms;
 
-#[derive(Debug, Clone)]
+pub use plan::PlanBook;
+
+/// One executed (or dry-run) command and what the driver said about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnactStep {
-    pub name: String,
+    pub command: Command,
     pub ok: bool,
     pub message: String,
+    pub started_at_ms: u128,
+    pub finished_at_ms: u128,
+    /// Raw driver payload (e.g. relay state read back from the device).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<serde_json::Value>,
+    /// Driver-level retries behind this step (0 when it went through first time).
+    #[serde(default)]
+    pub retries: u32,
+    /// Class of the driver error when the step failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<DriverErrorKind>,
 }
 
+/// Pause before re-trying a step that failed with a retryable driver error
+/// and no `Retry-After` hint.
+const STEP_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Longest `Retry-After` the enactor will wait out before its one re-try.
+const MAX_STEP_RETRY_DELAY: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Clone)]
 pub struct EnactReport {
-    pub policy: Policy,
     pub steps: Vec<EnactStep>,
     pub ok: bool,
+    /// True when the cancel token fired before every step ran.
+    pub canceled: bool,
+    /// Driver error class of the step that failed the run, if any.
+    pub failure: Option<DriverErrorKind>,
 }
 
 #[async_trait]
 pub trait InstallationEnactor: Send + Sync {
-    /// Translate policy → device operations and execute them (unless dry_run)
+    /// Translate policy → device operations and execute them (unless dry_run).
+    /// `cancel` is checked before each step; once it fires no further steps run.
+    /// Every finished step is also sent on `progress` as it completes.
     async fn enact(
         &self,
         installation_id: &str,
         policy: Policy,
         dry_run: bool,
+        cancel: CancellationToken,
+        progress: UnboundedSender<EnactStep>,
     ) -> Result<EnactReport>;
 }
 
 /// Simple mapper that uses your existing DeviceDriver::apply(Command)
 pub struct SimpleEnactor {
     driver: Arc<dyn DeviceDriver>,
+    plans: Arc<PlanBook>,
 }
 
 impl SimpleEnactor {
-    pub fn new(driver: Arc<dyn DeviceDriver>) -> Self {
-        Self { driver }
-    }
-
-    /// Hide the mapping here: policy → commands. Not exposed to engine/policy.
-    fn plan(policy: Policy) -> Vec<Command> {
-        match policy {
-            Policy::Observe => vec![Command::Monitor],
-            Policy::Prepare => vec![Command::ArmSensors, Command::StagePumps],
-            Policy::Defend => vec![Command::ArmSensors, Command::EnablePumpsLow],
-            Policy::Contain => vec![
-                Command::ArmSensors,
-                Command::EnablePumpsHigh,
-                Command::OpenValvesPriority,
-            ],
-            Policy::Suppress => vec![Command::Lockdown],
-            Policy::Unknown => vec![Command::Noop],
-        }
+    pub fn new(driver: Arc<dyn DeviceDriver>, plans: Arc<PlanBook>) -> Self {
+        Self { driver, plans }
     }
 }
 
@@ -65,53 +84,218 @@ impl InstallationEnactor for SimpleEnactor {
         installation_id: &str,
         policy: Policy,
         dry_run: bool,
+        cancel: CancellationToken,
+        progress: UnboundedSender<EnactStep>,
     ) -> Result<EnactReport> {
         let mut steps = Vec::new();
         let mut all_ok = true;
+        let mut canceled = false;
+        let mut failure = None;
 
-        for cmd in Self::plan(policy) {
-            if dry_run {
-                steps.push(EnactStep {
-                    name: format!("{cmd:?}"),
-                    ok: true,
-                    message: "dry_run".into(),
-                });
-                continue;
+        for cmd in self.plans.resolve(installation_id, policy).commands {
+            if cancel.is_cancelled() {
+                canceled = true;
+                break;
             }
 
-            // Execute via DAL
-            let res: Result<CommandResult> = self.driver.apply(installation_id, cmd.clone()).await;
-            match res {
-                Ok(r) if r.ok => steps.push(EnactStep {
-                    name: format!("{cmd:?}"),
+            let started_at_ms = now_ms();
+            let step = if dry_run {
+                EnactStep {
+                    command: cmd,
                     ok: true,
-                    message: r.message,
-                }),
-                Ok(r) => {
-                    all_ok = false;
-                    steps.push(EnactStep {
-                        name: format!("{cmd:?}"),
-                        ok: false,
-                        message: r.message,
-                    });
-                    break;
+                    message: "dry_run".into(),
+                    started_at_ms,
+                    finished_at_ms: now_ms(),
+                    response: None,
+                    retries: 0,
+                    error_kind: None,
                 }
-                Err(e) => {
-                    all_ok = false;
-                    steps.push(EnactStep {
-                        name: format!("{cmd:?}"),
+            } else {
+                // Execute via DAL
+                let mut res = self.driver.apply(installation_id, cmd).await;
+                // Timeouts and rate limits get one more try; other classes stop the run
+                let retry_in = match &res {
+                    Err(e) if e.is_retryable() => Some(
+                        e.retry_after()
+                            .unwrap_or(STEP_RETRY_DELAY)
+                            .min(MAX_STEP_RETRY_DELAY),
+                    ),
+                    _ => None,
+                };
+                if let Some(delay) = retry_in {
+                    tokio::select! {
+                        // the failed attempt is still recorded, but the run
+                        // ends canceled rather than failed
+                        _ = cancel.cancelled() => canceled = true,
+                        _ = tokio::time::sleep(delay) => {
+                            res = self.driver.apply(installation_id, cmd).await;
+                        }
+                    }
+                }
+                match res {
+                    Ok(r) => EnactStep {
+                        command: cmd,
+                        ok: r.ok,
+                        message: r.message,
+                        started_at_ms,
+                        finished_at_ms: now_ms(),
+                        response: r.response,
+                        retries: r.retries,
+                        error_kind: None,
+                    },
+                    Err(e) => EnactStep {
+                        command: cmd,
                         ok: false,
                         message: e.to_string(),
-                    });
-                    break;
+                        started_at_ms,
+                        finished_at_ms: now_ms(),
+                        response: match e.root() {
+                            DriverError::PartialRelayFailure(m) => {
+                                Some(serde_json::json!({ "mismatches": m }))
+                            }
+                            _ => None,
+                        },
+                        retries: e.retries(),
+                        error_kind: Some(e.kind()),
+                    },
                 }
+            };
+
+            let failed = !step.ok;
+            // the receiver going away only means nobody is watching
+            let _ = progress.send(step.clone());
+            steps.push(step);
+            if failed {
+                all_ok = false;
+                failure = steps.last().and_then(|s| s.error_kind);
+                break;
             }
         }
 
         Ok(EnactReport {
-            policy,
             steps,
-            ok: all_ok,
+            ok: all_ok && !canceled,
+            canceled,
+            failure,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::{CommandResult, DeviceStatus, RelayMismatch};
+    use std::sync::Mutex;
+
+    const ONE_STEP_PLANS: &str = r#"
+        [defaults]
+        observe = ["monitor"]
+        prepare = ["noop"]
+        defend = ["noop"]
+        contain = ["noop"]
+        suppress = ["noop"]
+    "#;
+
+    /// Returns the queued errors in order, then succeeds.
+    struct ScriptedDriver(Mutex<Vec<DriverError>>);
+
+    #[async_trait]
+    impl DeviceDriver for ScriptedDriver {
+        async fn apply(&self, _: &str, _: Command) -> Result<CommandResult, DriverError> {
+            let mut errs = self.0.lock().unwrap();
+            if errs.is_empty() {
+                return Ok(CommandResult {
+                    ok: true,
+                    message: "ok".into(),
+                    response: None,
+                    retries: 0,
+                });
+            }
+            Err(errs.remove(0))
+        }
+
+        async fn status(&self, installation_id: &str) -> Result<DeviceStatus, DriverError> {
+            Ok(DeviceStatus {
+                installation_id: installation_id.to_string(),
+                relays: Default::default(),
+                inputs: Default::default(),
+                device_time_s: None,
+                firmware: Some("scripted".into()),
+            })
+        }
+    }
+
+    async fn enact(errs: Vec<DriverError>) -> EnactReport {
+        enact_until(errs, CancellationToken::new()).await
+    }
+
+    async fn enact_until(errs: Vec<DriverError>, cancel: CancellationToken) -> EnactReport {
+        let enactor = SimpleEnactor::new(
+            Arc::new(ScriptedDriver(Mutex::new(errs))),
+            Arc::new(PlanBook::parse(ONE_STEP_PLANS).unwrap()),
+        );
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        enactor
+            .enact("h", Policy::Observe, false, cancel, tx)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn cancel_during_retry_backoff_cancels_the_run() {
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            trigger.cancel();
+        });
+        // no Retry-After, so the enactor would wait STEP_RETRY_DELAY
+        let timeout = DriverError::Timeout("slow device".into());
+        let report =
+            tokio::time::timeout(Duration::from_secs(1), enact_until(vec![timeout], cancel))
+                .await
+                .expect("cancel did not cut the back-off short");
+        assert!(report.canceled);
+        assert!(!report.ok);
+        assert_eq!(report.steps.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limited_step_is_retried_once() {
+        let limited = || DriverError::RateLimited {
+            message: "slow down".into(),
+            retry_after: Some(Duration::ZERO),
+        };
+        let report = enact(vec![limited()]).await;
+        assert!(report.ok);
+        assert!(report.failure.is_none());
+
+        let report = enact(vec![limited(), limited()]).await;
+        assert!(!report.ok);
+        assert_eq!(report.failure, Some(DriverErrorKind::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn partial_relay_failure_stops_the_run_with_mismatches() {
+        let report = enact(vec![DriverError::PartialRelayFailure(vec![
+            RelayMismatch {
+                relay: "x19Relay4".into(),
+                want_on: true,
+                got: Some(false),
+                detail: None,
+            },
+        ])
+        .with_retries(2)])
+        .await;
+        assert!(!report.ok);
+        assert_eq!(report.steps.len(), 1);
+        assert_eq!(report.failure, Some(DriverErrorKind::PartialRelayFailure));
+        let step = &report.steps[0];
+        assert_eq!(step.error_kind, Some(DriverErrorKind::PartialRelayFailure));
+        assert_eq!(step.retries, 2);
+        assert_eq!(
+            step.response.as_ref().unwrap()["mismatches"][0]["relay"],
+            "x19Relay4"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::device_abstraction_layer::Command;
+use crate::policy::Policy;
+
+/// Built-in plans, used when `PLANS_PATH` is not set.
+const DEFAULT_PLANS: &str = include_str!("../../config/plans.toml");
+
+/// Policies every `[defaults]` table must cover.
+const KNOWN_POLICIES: [Policy; 5] = [
+    Policy::Observe,
+    Policy::Prepare,
+    Policy::Defend,
+    Policy::Contain,
+    Policy::Suppress,
+];
+
+/// On-disk shape. Keys stay strings so typos are reported instead of
+/// collapsing into `Policy::Unknown`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlanFile {
+    defaults: HashMap<String, Vec<Command>>,
+    #[serde(default)]
+    installations: HashMap<String, HashMap<String, Vec<Command>>>,
+}
+
+/// Validated policy → command plans: defaults plus per-installation overrides.
+#[derive(Debug, Clone)]
+pub struct PlanBook {
+    defaults: HashMap<Policy, Vec<Command>>,
+    installations: HashMap<String, HashMap<Policy, Vec<Command>>>,
+}
+
+/// Where a resolved plan came from.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanSource {
+    Installation,
+    Default,
+    /// `Policy::Unknown` always resolves to a single `Noop`.
+    Fallback,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedPlan {
+    pub installation_id: String,
+    pub policy: Policy,
+    pub source: PlanSource,
+    pub commands: Vec<Command>,
+}
+
+impl PlanBook {
+    /// Load from `PLANS_PATH`, or the built-in `config/plans.toml` when unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("PLANS_PATH") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading plans file {path}"))?;
+                Self::parse(&raw).with_context(|| format!("invalid plans file {path}"))
+            }
+            Err(_) => Self::parse(DEFAULT_PLANS).context("invalid built-in plans"),
+        }
+    }
+
+    /// Parse and validate a plans TOML document.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let file: PlanFile = toml::from_str(raw)?;
+
+        let defaults = policy_table(file.defaults, "defaults")?;
+        for p in KNOWN_POLICIES {
+            if !defaults.contains_key(&p) {
+                bail!("[defaults] is missing a plan for `{p}`");
+            }
+        }
+
+        let mut installations = HashMap::new();
+        for (id, table) in file.installations {
+            let table = policy_table(table, &format!("installations.{id}"))?;
+            installations.insert(id, table);
+        }
+
+        Ok(Self {
+            defaults,
+            installations,
+        })
+    }
+
+    /// Commands to run for `policy` at `installation_id`.
+    pub fn resolve(&self, installation_id: &str, policy: Policy) -> ResolvedPlan {
+        let (source, commands) = if policy == Policy::Unknown {
+            (PlanSource::Fallback, vec![Command::Noop])
+        } else if let Some(cmds) = self
+            .installations
+            .get(installation_id)
+            .and_then(|t| t.get(&policy))
+        {
+            (PlanSource::Installation, cmds.clone())
+        } else {
+            (
+                PlanSource::Default,
+                self.defaults.get(&policy).cloned().unwrap_or_default(),
+            )
+        };
+        ResolvedPlan {
+            installation_id: installation_id.to_string(),
+            policy,
+            source,
+            commands,
+        }
+    }
+
+    /// Installations with plans of their own.
+    pub fn installation_ids(&self) -> impl Iterator<Item = &str> {
+        self.installations.keys().map(String::as_str)
+    }
+
+    /// Every command the default plans send.
+    pub fn default_commands(&self) -> impl Iterator<Item = Command> + '_ {
+        self.defaults.values().flatten().copied()
+    }
+
+    /// Resolved plans for every known policy at `installation_id`.
+    pub fn resolve_all(&self, installation_id: &str) -> Vec<ResolvedPlan> {
+        KNOWN_POLICIES
+            .iter()
+            .map(|p| self.resolve(installation_id, *p))
+            .collect()
+    }
+}
+
+fn policy_table(
+    raw: HashMap<String, Vec<Command>>,
+    table: &str,
+) -> Result<HashMap<Policy, Vec<Command>>> {
+    let mut out = HashMap::new();
+    for (key, cmds) in raw {
+        let policy: Policy = key
+            .parse()
+            .map_err(|_| anyhow::anyhow!("[{table}] has unknown policy `{key}`"))?;
+        if cmds.is_empty() {
+            bail!("[{table}] plan for `{key}` is empty (use [\"noop\"] for no action)");
+        }
+        out.insert(policy, cmds);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_plans_match_previous_hardcoded_mapping() {
+        let book = PlanBook::parse(DEFAULT_PLANS).unwrap();
+        let contain = book.resolve("anywhere", Policy::Contain);
+        assert!(matches!(contain.source, PlanSource::Default));
+        assert!(matches!(
+            contain.commands.as_slice(),
+            [
+                Command::ArmSensors,
+                Command::EnablePumpsHigh,
+                Command::OpenValvesPriority
+            ]
+        ));
+        assert!(matches!(
+            book.resolve("anywhere", Policy::Unknown)
+                .commands
+                .as_slice(),
+            [Command::Noop]
+        ));
+    }
+
+    #[test]
+    fn installation_overrides_single_policy() {
+        let book = PlanBook::parse(&format!(
+            "{DEFAULT_PLANS}\n[installations.cabin]\ndefend = [\"open_valves_all\"]\n"
+        ))
+        .unwrap();
+        let defend = book.resolve("cabin", Policy::Defend);
+        assert!(matches!(defend.source, PlanSource::Installation));
+        assert!(matches!(
+            defend.commands.as_slice(),
+            [Command::OpenValvesAll]
+        ));
+        assert!(matches!(
+            book.resolve("cabin", Policy::Observe).source,
+            PlanSource::Default
+        ));
+    }
+
+    #[test]
+    fn rejects_typos_missing_defaults_and_unknown_commands() {
+        let missing = "[defaults]\nobserve = [\"monitor\"]\n";
+        assert!(PlanBook::parse(missing).is_err());
+
+        let typo = format!("{DEFAULT_PLANS}\n[installations.x]\ndefnd = [\"monitor\"]\n");
+        assert!(PlanBook::parse(&typo).is_err());
+
+        let bad_cmd = DEFAULT_PLANS.replace("\"lockdown\"", "\"flood\"");
+        assert!(PlanBook::parse(&bad_cmd).is_err());
+    }
+}
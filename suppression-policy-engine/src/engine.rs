@@ -1,5 +1,9 @@
-use crate::policy::Policy;
+use crate::policy::rules::Decision;
+use crate::policy::{DecisionInputs, DecisionRules, Policy};
+use crate::time::now_ms;
 use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
 
 /// A pure, side-effect-free evaluation of policy intent for an installation.
 /// The engine does not prescribe device actions; it summarizes intent/level.
@@ -12,14 +16,59 @@ pub struct Evaluation {
     pub level: u8,
     pub summary: &'static str,
     pub dry_run: bool,
+    /// Set when the engine chose the policy itself (see [`Engine::decide`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decision: Option<Decision>,
+    /// How the decided policy relates to the one already in force.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition: Option<Transition>,
 }
 
-#[derive(Debug, Default)]
-pub struct Engine;
+/// The policy an installation's last succeeded non-dry-run run applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CurrentPolicy {
+    pub policy: Policy,
+    /// When that policy was first applied; re-applying it doesn't reset this.
+    pub since_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    /// `None` until a run has applied a policy since startup.
+    pub current: Option<CurrentPolicy>,
+    /// What the rules alone recommend; differs from the evaluation's policy
+    /// when the move was blocked.
+    pub recommended: Policy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocked: Option<Blocked>,
+}
+
+/// Why the decider kept (part of) the current policy instead of the
+/// recommended one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum Blocked {
+    /// The current policy hasn't been in force for the minimum dwell time.
+    MinDwell { remaining_s: u64 },
+    /// A rule's `hold` conditions still cover the inputs, so stepping down
+    /// stops at its policy.
+    Hold { rule: String, policy: Policy },
+    /// This installation only steps down on a manual run.
+    ManualDeescalation,
+}
+
+#[derive(Debug)]
+pub struct Engine {
+    rules: DecisionRules,
+    current: Mutex<HashMap<String, CurrentPolicy>>,
+}
 
 impl Engine {
-    pub fn new() -> Self {
-        Self
+    pub fn new(rules: DecisionRules) -> Self {
+        Self {
+            rules,
+            current: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Plan at the intent level (no device actions here).
@@ -32,6 +81,220 @@ impl Engine {
             level: policy.level(),
             summary: policy.summary(),
             dry_run,
+            decision: None,
+            transition: None,
         }
     }
+
+    /// Choose the policy from fire and weather inputs using the threshold
+    /// rules, damped by the installation's transition limits, and explain
+    /// the choice.
+    pub fn decide(
+        &self,
+        installation_id: &str,
+        inputs: DecisionInputs,
+        dry_run: bool,
+    ) -> Evaluation {
+        self.decide_at(installation_id, inputs, dry_run, now_ms())
+    }
+
+    fn decide_at(
+        &self,
+        installation_id: &str,
+        inputs: DecisionInputs,
+        dry_run: bool,
+        now: u128,
+    ) -> Evaluation {
+        let decision = self.rules.decide(installation_id, inputs);
+        let current = self.current(installation_id);
+        let (policy, blocked) = match current {
+            Some(cur) => self.gate(installation_id, cur, &decision, now),
+            None => (decision.policy, None),
+        };
+        Evaluation {
+            transition: Some(Transition {
+                current,
+                recommended: decision.policy,
+                blocked,
+            }),
+            decision: Some(decision),
+            ..self.evaluate(installation_id, policy, dry_run)
+        }
+    }
+
+    /// Where the decider may actually go from `cur` given the recommendation.
+    fn gate(
+        &self,
+        installation_id: &str,
+        cur: CurrentPolicy,
+        decision: &Decision,
+        now: u128,
+    ) -> (Policy, Option<Blocked>) {
+        let limits = self.rules.transitions(installation_id);
+        let held_s = (now.saturating_sub(cur.since_ms) / 1000) as u64;
+        let dwell = |min_s: u64| {
+            (held_s < min_s).then(|| Blocked::MinDwell {
+                remaining_s: min_s - held_s,
+            })
+        };
+
+        let to = decision.policy;
+        if to.level() == cur.policy.level() {
+            return (to, None);
+        }
+        if to.level() > cur.policy.level() {
+            return match dwell(limits.min_dwell_escalate_s) {
+                Some(b) => (cur.policy, Some(b)),
+                None => (to, None),
+            };
+        }
+
+        // stepping down: go no lower than whatever still holds
+        let (to, held) = match self
+            .rules
+            .holding(installation_id, &decision.inputs, cur.policy)
+        {
+            Some(r) if r.policy.level() > to.level() => (
+                r.policy,
+                Some(Blocked::Hold {
+                    rule: r.name.clone(),
+                    policy: r.policy,
+                }),
+            ),
+            _ => (to, None),
+        };
+        if to.level() == cur.policy.level() {
+            return (cur.policy, held);
+        }
+        if !limits.auto_deescalate {
+            return (cur.policy, Some(Blocked::ManualDeescalation));
+        }
+        match dwell(limits.min_dwell_deescalate_s) {
+            Some(b) => (cur.policy, Some(b)),
+            None => (to, held),
+        }
+    }
+
+    /// The policy currently in force at `installation_id`, if known.
+    pub fn current(&self, installation_id: &str) -> Option<CurrentPolicy> {
+        self.current.lock().unwrap().get(installation_id).copied()
+    }
+
+    /// Note that a run applied `policy` at `at_ms`. Call for succeeded
+    /// non-dry-run runs only; manual and automatic runs alike reset the dwell clock when they
+    /// change the policy.
+    pub fn record(&self, installation_id: &str, policy: Policy, at_ms: u128) {
+        let mut current = self.current.lock().unwrap();
+        match current.get(installation_id) {
+            Some(cur) if cur.policy == policy => {}
+            _ => {
+                current.insert(
+                    installation_id.to_string(),
+                    CurrentPolicy {
+                        policy,
+                        since_ms: at_ms,
+                    },
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: u128 = 60_000;
+
+    fn engine(transitions: &str) -> Engine {
+        let rules = DecisionRules::parse(&format!(
+            "[defaults]\n{transitions}\n\
+             [[defaults.rules]]\nname = \"close\"\npolicy = \"contain\"\n\
+             when = {{ distance_mi = {{ max = 3.0 }} }}\nhold = {{ distance_mi = {{ max = 4.0 }} }}\n\
+             [[defaults.rules]]\nname = \"near\"\npolicy = \"defend\"\n\
+             when = {{ distance_mi = {{ max = 8.0 }} }}\n"
+        ))
+        .unwrap();
+        Engine::new(rules)
+    }
+
+    fn at(distance_mi: f64) -> DecisionInputs {
+        DecisionInputs {
+            distance_mi: Some(distance_mi),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn hold_band_keeps_policy_until_inputs_clear_it() {
+        let e = engine("");
+        e.record("a", Policy::Contain, 0);
+
+        // left the `when` band but still inside `hold`
+        let ev = e.decide_at("a", at(3.5), false, MIN);
+        assert_eq!(ev.policy, Policy::Contain);
+        let t = ev.transition.unwrap();
+        assert_eq!(t.recommended, Policy::Defend);
+        assert_eq!(
+            t.blocked,
+            Some(Blocked::Hold {
+                rule: "close".into(),
+                policy: Policy::Contain
+            })
+        );
+
+        let ev = e.decide_at("a", at(5.0), false, MIN);
+        assert_eq!(ev.policy, Policy::Defend);
+        assert_eq!(ev.transition.unwrap().blocked, None);
+    }
+
+    #[test]
+    fn dwell_times_apply_per_direction() {
+        let e = engine(
+            "[defaults.transitions]\nmin_dwell_escalate_s = 60\nmin_dwell_deescalate_s = 600",
+        );
+        e.record("a", Policy::Defend, 0);
+
+        let ev = e.decide_at("a", at(1.0), false, 30_000);
+        assert_eq!(ev.policy, Policy::Defend);
+        assert_eq!(
+            ev.transition.unwrap().blocked,
+            Some(Blocked::MinDwell { remaining_s: 30 })
+        );
+        assert_eq!(
+            e.decide_at("a", at(1.0), false, MIN).policy,
+            Policy::Contain
+        );
+
+        let ev = e.decide_at("a", at(20.0), false, 5 * MIN);
+        assert_eq!(ev.policy, Policy::Defend);
+        assert_eq!(
+            ev.transition.unwrap().blocked,
+            Some(Blocked::MinDwell { remaining_s: 300 })
+        );
+        assert_eq!(
+            e.decide_at("a", at(20.0), false, 10 * MIN).policy,
+            Policy::Observe
+        );
+    }
+
+    #[test]
+    fn escalate_only_installations_step_down_manually() {
+        let e = engine("[defaults.transitions]\nauto_deescalate = false");
+        assert_eq!(e.decide_at("a", at(20.0), false, 0).policy, Policy::Observe);
+
+        e.record("a", Policy::Contain, 0);
+        let ev = e.decide_at("a", at(20.0), false, 60 * MIN);
+        assert_eq!(ev.policy, Policy::Contain);
+        assert_eq!(
+            ev.transition.unwrap().blocked,
+            Some(Blocked::ManualDeescalation)
+        );
+
+        // re-recording the same policy keeps the original since_ms
+        e.record("a", Policy::Contain, 5);
+        assert_eq!(e.current("a").unwrap().since_ms, 0);
+        e.record("a", Policy::Observe, 5);
+        assert_eq!(e.decide_at("a", at(20.0), false, 6).policy, Policy::Observe);
+    }
 }
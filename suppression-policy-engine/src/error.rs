@@ -1,16 +1,20 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
 
+use crate::device_abstraction_layer::{DriverError, DriverErrorKind, RelayMismatch};
+
 #[derive(Debug)]
 pub enum ApiError {
     BadRequest(&'static str),
     Conflict(&'static str),
     NotFound(&'static str),
     Internal(String),
+    /// A device call failed; the status code follows the error class.
+    Driver(DriverError),
 }
 
 #[derive(Serialize)]
@@ -18,6 +22,43 @@ struct ErrBody {
     error: String,
 }
 
+#[derive(Serialize)]
+struct DriverErrBody {
+    error: String,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mismatches: Option<Vec<RelayMismatch>>,
+}
+
+fn driver_error_response(e: DriverError) -> Response {
+    let code = match e.kind() {
+        DriverErrorKind::UnresolvedInstallation => StatusCode::NOT_FOUND,
+        DriverErrorKind::NotConfigured => StatusCode::CONFLICT,
+        DriverErrorKind::AuthFailed | DriverErrorKind::PartialRelayFailure => {
+            StatusCode::BAD_GATEWAY
+        }
+        DriverErrorKind::DeviceOffline => StatusCode::SERVICE_UNAVAILABLE,
+        DriverErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        DriverErrorKind::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        DriverErrorKind::Other => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let retry_after = e.retry_after();
+    let body = DriverErrBody {
+        error: format!("{e:#}"),
+        kind: e.kind().as_str(),
+        mismatches: match e.root() {
+            DriverError::PartialRelayFailure(m) => Some(m.clone()),
+            _ => None,
+        },
+    };
+    let mut resp = (code, Json(body)).into_response();
+    if let Some(d) = retry_after {
+        resp.headers_mut()
+            .insert(header::RETRY_AFTER, d.as_secs().max(1).into());
+    }
+    resp
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         match self {
@@ -47,6 +88,7 @@ impl IntoResponse for ApiError {
                 Json(ErrBody { error: msg }),
             )
                 .into_response(),
+            ApiError::Driver(e) => driver_error_response(e),
         }
     }
 }
@@ -0,0 +1,100 @@
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::enactor::EnactStep;
+use crate::models::{RunRecord, RunStatus};
+use crate::time::now_ms;
+
+/// Progress notification for a single run, fanned out to SSE subscribers.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunEvent {
+    Status {
+        run_id: String,
+        installation_id: String,
+        status: RunStatus,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        failure_reason: Option<String>,
+        at_ms: u128,
+    },
+    Step {
+        run_id: String,
+        installation_id: String,
+        step: EnactStep,
+    },
+}
+
+impl RunEvent {
+    pub fn status(r: &RunRecord) -> Self {
+        RunEvent::Status {
+            run_id: r.run_id.clone(),
+            installation_id: r.installation_id.clone(),
+            status: r.status,
+            failure_reason: r.failure_reason.clone(),
+            at_ms: now_ms(),
+        }
+    }
+
+    /// SSE `event:` name.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunEvent::Status { .. } => "status",
+            RunEvent::Step { .. } => "step",
+        }
+    }
+
+    pub fn run_id(&self) -> &str {
+        match self {
+            RunEvent::Status { run_id, .. } | RunEvent::Step { run_id, .. } => run_id,
+        }
+    }
+
+    pub fn installation_id(&self) -> &str {
+        match self {
+            RunEvent::Status {
+                installation_id, ..
+            }
+            | RunEvent::Step {
+                installation_id, ..
+            } => installation_id,
+        }
+    }
+
+    /// True for the last event a run will ever emit.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, RunEvent::Status { status, .. } if status.is_terminal())
+    }
+}
+
+/// In-process broadcast bus for [`RunEvent`]s. Slow subscribers lag rather than
+/// block the runner.
+#[derive(Clone)]
+pub struct RunEvents {
+    tx: broadcast::Sender<RunEvent>,
+}
+
+impl RunEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RunEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Announce the run's current status.
+    pub fn status(&self, r: &RunRecord) {
+        // no subscribers is fine
+        let _ = self.tx.send(RunEvent::status(r));
+    }
+
+    /// Announce a completed enactor step.
+    pub fn step(&self, r: &RunRecord, step: EnactStep) {
+        let _ = self.tx.send(RunEvent::Step {
+            run_id: r.run_id.clone(),
+            installation_id: r.installation_id.clone(),
+            step,
+        });
+    }
+}
@@ -1,12 +1,12 @@
-use serde::Serialize;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+
+use crate::storage::IdempotencyBackend;
 
 /// A stored result for an idempotent create call.
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct IdemRecord {
     pub run_id: String,
     pub body_fingerprint: u64,
@@ -14,16 +14,15 @@ pub struct IdemRecord {
     pub created_at_ms: u128,
 }
 
-/// In-memory idempotency store.
-/// You can swap this out later for Redis/Postgres with the same API.
-#[derive(Clone, Default)]
+/// Idempotency store over a pluggable backend (see `crate::storage`).
+#[derive(Clone)]
 pub struct IdempotencyStore {
-    inner: Arc<RwLock<HashMap<String, IdemRecord>>>,
+    inner: Arc<dyn IdempotencyBackend>,
 }
 
 impl IdempotencyStore {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(inner: Arc<dyn IdempotencyBackend>) -> Self {
+        Self { inner }
     }
 
     /// Scope the key (optional): keeps keys unique per installation.
@@ -31,24 +30,19 @@ impl IdempotencyStore {
         format!("{}::{}", installation_id, key)
     }
 
-    /// Canonical-ish fingerprint for a JSON body (good enough for in-memory).
+    /// Canonical-ish fingerprint for a JSON body.
+    /// FNV-1a so the value is stable across restarts and toolchain upgrades
+    /// (fingerprints are persisted alongside the key).
     pub fn fingerprint_json(value: &Value) -> u64 {
         let bytes = serde_json::to_vec(value).unwrap_or_default();
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        bytes.hash(&mut hasher);
-        hasher.finish()
-    }
-
-    /// Get a stored record (clone) if present.
-    pub async fn get(&self, scoped_key: &str) -> Option<IdemRecord> {
-        let map = self.inner.read().await;
-        map.get(scoped_key).cloned()
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+            (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+        })
     }
 
-    /// Insert/overwrite a record.
-    pub async fn put(&self, scoped_key: String, rec: IdemRecord) {
-        let mut map = self.inner.write().await;
-        map.insert(scoped_key, rec);
+    /// Get a stored record if present.
+    pub async fn get(&self, scoped_key: &str) -> Result<Option<IdemRecord>> {
+        self.inner.get(scoped_key).await
     }
 
     /// Optional: simple TTL sweeping hook (no-op for now).
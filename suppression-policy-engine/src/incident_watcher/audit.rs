@@ -0,0 +1,83 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use tracing::{error, info};
+
+use super::incidents::InRange;
+use super::WatchMode;
+use crate::engine::Evaluation;
+use crate::policy::Policy;
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum AuditAction {
+    /// Policy unchanged (or the change was blocked); nothing started.
+    Unchanged,
+    StartedRun {
+        run_id: String,
+    },
+    RunFailed {
+        error: String,
+    },
+}
+
+/// One line of the audit log: a single watcher decision for one installation.
+#[derive(Debug, Serialize)]
+pub struct AuditRecord<'a> {
+    pub at_ms: u128,
+    pub installation_id: &'a str,
+    pub mode: WatchMode,
+    /// Incidents within range considered for this decision.
+    pub incidents_considered: usize,
+    /// The incident that drove the decision, if any was in range.
+    pub incident: Option<InRange<'a>>,
+    /// Policy the watcher compared against.
+    pub previous: Policy,
+    pub evaluation: &'a Evaluation,
+    #[serde(flatten)]
+    pub action: AuditAction,
+}
+
+/// Append-only JSON-lines log of watcher decisions.
+pub struct AuditLog {
+    path: String,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("opening watcher audit log {path}"))?;
+        Ok(Self {
+            path: path.to_string(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Write `rec`; a failed write is logged, never fatal.
+    pub fn record(&self, rec: &AuditRecord<'_>) {
+        info!(
+            installation_id = rec.installation_id,
+            policy = %rec.evaluation.policy,
+            previous = %rec.previous,
+            action = ?rec.action,
+            "irwin watcher decision"
+        );
+        let line = match serde_json::to_string(rec) {
+            Ok(l) => l,
+            Err(e) => {
+                error!(error = %e, "failed to serialize watcher audit record");
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = writeln!(file, "{line}") {
+            error!(path = %self.path, error = %e, "failed to write watcher audit record");
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use data_source_irwin::spatial::great_circle_mi;
+use data_source_irwin::{Feature, WhereClause};
+use serde::Serialize;
+
+use super::sites::Site;
+
+/// Valid fires not yet declared out: what the first sync seeds the store
+/// with, whenever they were last modified.
+pub fn active_fires() -> WhereClause {
+    WhereClause::eq("IsValid", 1)
+        .and(WhereClause::eq("IncidentTypeKind", "FI"))
+        .and(WhereClause::is_null("FireOutDateTime"))
+}
+
+/// The slice of an IRWIN incident record the watcher cares about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Incident {
+    pub irwin_id: String,
+    pub name: Option<String>,
+    pub lat: f64,
+    pub lon: f64,
+    pub acres: Option<f64>,
+    pub containment_pct: Option<f64>,
+    /// Valid, a fire, and not declared out.
+    pub active: bool,
+    pub modified_ms: Option<u64>,
+}
+
+impl Incident {
+    /// The watcher's view of an IRWIN record; `None` without a usable id or
+    /// location.
+    pub fn from_feature(f: &Feature) -> Option<Self> {
+        let a = &f.attributes;
+        let irwin_id = a.normalized_irwin_id()?;
+        let (lat, lon) = f.location()?;
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return None;
+        }
+
+        let extra = |key: &str| a.extra.get(key).filter(|v| !v.is_null());
+        let valid = a.is_valid != Some(false);
+        let fire = a.incident_type_kind.is_none() || a.is_fire();
+        let out = extra("FireOutDateTime").is_some();
+
+        Some(Self {
+            irwin_id,
+            name: a.incident_name.clone(),
+            lat,
+            lon,
+            acres: a
+                .daily_acres
+                .or_else(|| extra("CalculatedAcres")?.as_f64())
+                .or_else(|| extra("DiscoveryAcres")?.as_f64()),
+            containment_pct: a.percent_contained,
+            active: valid && fire && !out,
+            modified_ms: a.modified_on_date_time.and_then(|t| u64::try_from(t).ok()),
+        })
+    }
+
+    /// Great-circle distance in miles and initial bearing in degrees true
+    /// from `site` to the incident.
+    pub fn distance_and_bearing(&self, site: Site) -> (f64, f64) {
+        let distance = great_circle_mi((site.lat, site.lon), (self.lat, self.lon));
+
+        let (lat1, lat2) = (site.lat.to_radians(), self.lat.to_radians());
+        let dlon = (self.lon - site.lon).to_radians();
+        let y = dlon.sin() * lat2.cos();
+        let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+        let bearing = y.atan2(x).to_degrees().rem_euclid(360.0);
+        (distance, bearing)
+    }
+}
+
+/// An incident with its distance and bearing from one installation.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct InRange<'a> {
+    #[serde(flatten)]
+    pub incident: &'a Incident,
+    pub distance_mi: f64,
+    pub bearing_deg: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_source_irwin::FeatureSet;
+
+    #[test]
+    fn from_feature_reads_records_and_skips_unlocated_ones() {
+        let body = r#"{
+            "features": [
+                {"attributes": {"IrwinID": "{ABC-1}", "IncidentName": "Ridge",
+                    "IncidentTypeKind": "FI", "IsValid": 1,
+                    "InitialLatitude": 38.6, "InitialLongitude": -121.5,
+                    "DailyAcres": 1200.5, "PercentContained": 10,
+                    "FireOutDateTime": null, "ModifiedOnDateTime": 1700000000000}},
+                {"attributes": {"IrwinID": "def-2", "FireOutDateTime": 1700000000000},
+                 "geometry": {"x": -120.0, "y": 39.0}},
+                {"attributes": {"IrwinID": "ghi-3"}}
+            ]
+        }"#;
+        let fs: FeatureSet = serde_json::from_str(body).unwrap();
+        let parsed: Vec<_> = fs.features.iter().map(Incident::from_feature).collect();
+        assert!(parsed[2].is_none());
+
+        let ridge = parsed[0].as_ref().unwrap();
+        assert_eq!(ridge.irwin_id, "abc-1");
+        assert_eq!(ridge.acres, Some(1200.5));
+        assert_eq!(ridge.containment_pct, Some(10.0));
+        assert!(ridge.active);
+
+        let out = parsed[1].as_ref().unwrap();
+        assert_eq!((out.lat, out.lon), (39.0, -120.0));
+        assert!(!out.active);
+    }
+
+    #[test]
+    fn first_sync_filter_selects_active_fires() {
+        assert_eq!(
+            active_fires().to_string(),
+            "((IsValid = 1 AND IncidentTypeKind = 'FI') AND FireOutDateTime IS NULL)"
+        );
+    }
+
+    #[test]
+    fn distance_and_bearing() {
+        let site = Site {
+            lat: 38.0,
+            lon: -121.0,
+        };
+        let north = Incident {
+            irwin_id: "n".into(),
+            name: None,
+            lat: 38.1,
+            lon: -121.0,
+            acres: None,
+            containment_pct: None,
+            active: true,
+            modified_ms: None,
+        };
+        let (d, b) = north.distance_and_bearing(site);
+        assert!((d - 6.91).abs() < 0.01, "{d}");
+        assert!(b.abs() < 1e-6, "{b}");
+
+        let west = Incident {
+            lat: 38.0,
+            lon: -121.1,
+            ..north
+        };
+        let (_, b) = west.distance_and_bearing(site);
+        assert!((b - 270.0).abs() < 0.1, "{b}");
+    }
+}
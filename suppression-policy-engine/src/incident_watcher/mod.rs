@@ -0,0 +1,623 @@
+//! Background watcher that polls IRWIN for wildfire incidents and starts
+//! runs when the decided policy for an installation changes.
+
+pub mod audit;
+pub mod incidents;
+pub mod sites;
+
+use anyhow::{bail, Context, Result};
+use data_source_irwin::{
+    environments, Feature, IncidentQueryBuilder, IncidentSync, IrwinClient, IrwinConfig,
+    SqliteIncidentStore, SyncEvent,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::engine::Evaluation;
+use crate::models::{RunStatus, StartRunRequest};
+use crate::policy::{DecisionInputs, Policy};
+use crate::routes::v1::runs::create_run_and_response;
+use crate::state::AppState;
+use crate::time::now_ms;
+use audit::{AuditAction, AuditLog, AuditRecord};
+use incidents::{active_fires, InRange, Incident};
+use sites::{InstallationSites, Site};
+
+pub const REQUESTED_BY: &str = "irwin-watcher";
+
+/// Longest the watcher waits before retrying a policy whose live runs keep
+/// failing.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchMode {
+    /// Decide and start runs, but only as dry runs; for rollout.
+    DryRun,
+    Live,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatcherConfig {
+    pub mode: WatchMode,
+    pub irwin: IrwinConfig,
+    pub interval: Duration,
+    /// Incidents farther than this from a site are ignored.
+    pub radius_mi: f64,
+    pub audit_path: String,
+    /// SQLite file of synced incidents; with the cursor it lets a restart
+    /// pick up where the last sync ended.
+    pub store_path: String,
+    /// `ModifiedOnDateTime` cursor of the last sync, as JSON.
+    pub cursor_path: String,
+}
+
+impl WatcherConfig {
+    /// `IRWIN_WATCHER=off|dry-run|live` (default `off`); `None` when off.
+    pub fn from_env() -> Result<Option<Self>> {
+        let mode = match std::env::var("IRWIN_WATCHER").as_deref() {
+            Err(_) | Ok("off") => return Ok(None),
+            Ok("dry-run") => WatchMode::DryRun,
+            Ok("live") => WatchMode::Live,
+            Ok(other) => bail!("unknown IRWIN_WATCHER {other:?} (expected off, dry-run or live)"),
+        };
+        let var = |name: &str| std::env::var(name).with_context(|| format!("{name} not set"));
+        let number = |name: &str, default: u64| match std::env::var(name) {
+            Ok(v) => v
+                .parse::<u64>()
+                .ok()
+                .filter(|n| *n > 0)
+                .with_context(|| format!("{name} must be a positive integer")),
+            Err(_) => Ok(default),
+        };
+
+        let base_url =
+            std::env::var("IRWIN_BASE_URL").unwrap_or_else(|_| environments::PROD.to_string());
+        Ok(Some(Self {
+            mode,
+            irwin: IrwinConfig::new(
+                base_url,
+                var("IRWIN_USERNAME")?,
+                var("IRWIN_PASSWORD")?,
+                var("IRWIN_REFERER")?,
+            ),
+            interval: Duration::from_secs(number("IRWIN_WATCHER_INTERVAL_S", 300)?),
+            radius_mi: number("IRWIN_WATCHER_RADIUS_MI", 50)? as f64,
+            audit_path: std::env::var("IRWIN_WATCHER_AUDIT_PATH")
+                .unwrap_or_else(|_| "irwin-watcher-audit.jsonl".to_string()),
+            store_path: std::env::var("IRWIN_WATCHER_STORE_PATH")
+                .unwrap_or_else(|_| "irwin-incidents.sqlite".to_string()),
+            cursor_path: std::env::var("IRWIN_WATCHER_CURSOR_PATH")
+                .unwrap_or_else(|_| "irwin-watcher-cursor.json".to_string()),
+        }))
+    }
+}
+
+pub struct IncidentWatcher {
+    state: AppState,
+    client: IrwinClient,
+    sites: InstallationSites,
+    cfg: WatcherConfig,
+    audit: AuditLog,
+    /// Incremental sync into the on-disk store, from the saved cursor.
+    sync: IncidentSync<SqliteIncidentStore>,
+    /// Active incidents by IRWIN id: loaded from the store at start, then
+    /// kept current from the sync's change events.
+    incidents: HashMap<String, Incident>,
+    /// Last run the watcher started per installation. Dry runs never reach
+    /// the engine's current policy and live ones only once they succeed, so
+    /// this is what keeps the watcher from re-starting the same run every
+    /// poll, or retrying a failed one before its backoff is up.
+    acted: HashMap<String, Acted>,
+}
+
+/// A run the watcher started and the policy it asked for.
+struct Acted {
+    policy: Policy,
+    run_id: String,
+    /// Live runs for `policy` that failed in a row before this one.
+    failures: u32,
+}
+
+impl IncidentWatcher {
+    pub fn new(state: AppState, sites: InstallationSites, cfg: WatcherConfig) -> Result<Self> {
+        let client = IrwinClient::new(cfg.irwin.clone()).context("building IRWIN client")?;
+        let audit = AuditLog::open(&cfg.audit_path)?;
+        let store = SqliteIncidentStore::open(&cfg.store_path)
+            .with_context(|| format!("opening IRWIN store {}", cfg.store_path))?;
+        // every valid record with a location
+        let stored = store.in_bbox(-90.0, -180.0, 90.0, 180.0)?;
+        let sync = IncidentSync::new(store, &cfg.cursor_path)
+            .with_context(|| format!("opening IRWIN sync cursor {}", cfg.cursor_path))?;
+        let mut watcher = Self {
+            state,
+            client,
+            sites,
+            cfg,
+            audit,
+            sync,
+            incidents: HashMap::new(),
+            acted: HashMap::new(),
+        };
+        for f in &stored {
+            watcher.track(f);
+        }
+        Ok(watcher)
+    }
+
+    /// Poll forever on the configured interval. A failed sync is logged and
+    /// retried next tick; decisions still run on the incidents already known.
+    pub fn spawn(mut self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            info!(
+                mode = ?self.cfg.mode,
+                sites = self.sites.len(),
+                interval_s = self.cfg.interval.as_secs(),
+                "irwin watcher started"
+            );
+            if self.sites.is_empty() {
+                warn!("irwin watcher has no installation sites configured");
+            }
+            let mut tick = tokio::time::interval(self.cfg.interval);
+            tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                if let Err(e) = self.sync().await {
+                    error!(error = %e, "irwin sync failed");
+                }
+                self.decide_all().await;
+            }
+        })
+    }
+
+    /// Pull incidents modified since the saved cursor and fold the changes
+    /// in. With no cursor yet, the store is seeded with every active fire
+    /// instead, however long ago it changed.
+    async fn sync(&mut self) -> Result<()> {
+        let events = match self.sync.cursor_ms() {
+            Some(_) => match self.sync.run(&self.client).await {
+                Ok(events) => events,
+                Err(partial) => {
+                    // the pages before the failure are stored and won't be
+                    // reported again
+                    self.apply(&partial.events);
+                    return Err(partial.error.into());
+                }
+            },
+            None => {
+                let queried_at_ms = IrwinClient::current_timestamp_ms();
+                let query = IncidentQueryBuilder::new()
+                    .filter(&active_fires())
+                    .out_fields("*")
+                    .return_geometry("true")
+                    .include_last_sync_date_time(true)
+                    .build();
+                let snapshot = self.client.query_all_incidents(&query).await?;
+                self.sync.seed(snapshot, queried_at_ms)?
+            }
+        };
+        self.apply(&events);
+        Ok(())
+    }
+
+    /// Fold sync changes into the known incidents.
+    fn apply(&mut self, events: &[SyncEvent]) {
+        let mut skipped = 0;
+        for event in events {
+            match event {
+                SyncEvent::Created { incident: f, .. } | SyncEvent::Updated { current: f, .. } => {
+                    if !self.track(f) {
+                        skipped += 1;
+                    }
+                }
+                SyncEvent::Invalidated { irwin_id, .. } => {
+                    self.incidents.remove(irwin_id);
+                }
+            }
+        }
+        if skipped > 0 {
+            warn!(skipped, "irwin records without an id or location");
+        }
+    }
+
+    /// Keep `f` if it is an active fire, drop it otherwise; `false` when it
+    /// has no usable id or location.
+    fn track(&mut self, f: &Feature) -> bool {
+        match Incident::from_feature(f) {
+            Some(i) if i.active => {
+                self.incidents.insert(i.irwin_id.clone(), i);
+            }
+            Some(i) => {
+                self.incidents.remove(&i.irwin_id);
+            }
+            None => {
+                if let Some(id) = f.attributes.normalized_irwin_id() {
+                    self.incidents.remove(&id);
+                }
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn decide_all(&mut self) {
+        let sites: Vec<(String, Site)> = self
+            .sites
+            .iter()
+            .map(|(id, s)| (id.to_string(), s))
+            .collect();
+        for (id, site) in sites {
+            self.decide_site(&id, site).await;
+        }
+    }
+
+    /// Decide from every incident in range and keep the most severe
+    /// outcome (nearest on ties); start a run if it differs from what the
+    /// installation is running.
+    async fn decide_site(&mut self, installation_id: &str, site: Site) {
+        let dry_run = self.cfg.mode == WatchMode::DryRun;
+        let in_range: Vec<InRange> = self
+            .incidents
+            .values()
+            .map(|incident| {
+                let (distance_mi, bearing_deg) = incident.distance_and_bearing(site);
+                InRange {
+                    incident,
+                    distance_mi,
+                    bearing_deg,
+                }
+            })
+            .filter(|r| r.distance_mi <= self.cfg.radius_mi)
+            .collect();
+
+        let mut best: Option<(Evaluation, Option<InRange>)> = None;
+        for &r in &in_range {
+            let inputs = DecisionInputs {
+                distance_mi: Some(r.distance_mi),
+                fire_acres: r.incident.acres,
+                containment_pct: r.incident.containment_pct,
+                fire_bearing_deg: Some(r.bearing_deg),
+                ..Default::default()
+            };
+            let eval = self.state.engine.decide(installation_id, inputs, dry_run);
+            let better = best.as_ref().is_none_or(|(b, br)| {
+                let b_dist = br.map_or(f64::INFINITY, |br| br.distance_mi);
+                (eval.policy.level(), -r.distance_mi) > (b.policy.level(), -b_dist)
+            });
+            if better {
+                best = Some((eval, Some(r)));
+            }
+        }
+        let (eval, nearest) = best.unwrap_or_else(|| {
+            let eval =
+                self.state
+                    .engine
+                    .decide(installation_id, DecisionInputs::default(), dry_run);
+            (eval, None)
+        });
+
+        // failed attempts at the policy the last live run asked for
+        let mut failed: Option<(Policy, u32)> = None;
+        let acted = match self.acted.get(installation_id) {
+            Some(a) if dry_run => Some(a.policy),
+            Some(a) => match self.state.runs.get(&a.run_id).await {
+                Ok(Some(r)) if !r.status.is_terminal() => Some(a.policy),
+                Ok(Some(r)) if r.status == RunStatus::Failed => {
+                    failed = Some((a.policy, a.failures + 1));
+                    let retry_at_ms = r.updated_at_ms + self.retry_backoff(a.failures + 1);
+                    (now_ms() < retry_at_ms).then_some(a.policy)
+                }
+                // a live run that ended otherwise left the engine's current
+                // policy right
+                _ => None,
+            },
+            None => None,
+        };
+        let previous = acted
+            .or_else(|| self.state.engine.current(installation_id).map(|c| c.policy))
+            .unwrap_or(Policy::Observe);
+
+        let action = if eval.policy == previous {
+            AuditAction::Unchanged
+        } else {
+            let mut metadata = HashMap::new();
+            if let Some(r) = nearest {
+                metadata.insert("irwin_id".to_string(), r.incident.irwin_id.clone());
+                metadata.insert("distance_mi".to_string(), format!("{:.2}", r.distance_mi));
+            }
+            let start = StartRunRequest {
+                policy: eval.policy,
+                dry_run,
+                metadata: Some(metadata),
+                requested_by: Some(REQUESTED_BY.to_string()),
+                // escalations shouldn't wait behind a slower run
+                supersede: eval.policy.level() > previous.level(),
+            };
+            match create_run_and_response(self.state.clone(), installation_id.to_string(), start)
+                .await
+            {
+                Ok(created) => {
+                    let acted = Acted {
+                        policy: eval.policy,
+                        run_id: created.run_id.clone(),
+                        failures: failed
+                            .filter(|(policy, _)| *policy == eval.policy)
+                            .map_or(0, |(_, n)| n),
+                    };
+                    self.acted.insert(installation_id.to_string(), acted);
+                    AuditAction::StartedRun {
+                        run_id: created.run_id,
+                    }
+                }
+                Err(e) => AuditAction::RunFailed {
+                    error: format!("{e:?}"),
+                },
+            }
+        };
+
+        self.audit.record(&AuditRecord {
+            at_ms: now_ms(),
+            installation_id,
+            mode: self.cfg.mode,
+            incidents_considered: in_range.len(),
+            incident: nearest,
+            previous,
+            evaluation: &eval,
+            action,
+        });
+    }
+
+    /// How long after the `failures`-th failed live run in a row the watcher
+    /// tries the same policy again: two poll intervals, doubling each time,
+    /// up to [`MAX_RETRY_BACKOFF`].
+    fn retry_backoff(&self, failures: u32) -> u128 {
+        let backoff = self.cfg.interval.saturating_mul(1 << failures.min(16));
+        backoff.min(MAX_RETRY_BACKOFF).as_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::drivers::{MockDriver, Refusing, Stalled};
+    use crate::device_abstraction_layer::DeviceDriver;
+    use crate::models::RunRecord;
+    use crate::storage::RunFilter;
+    use data_source_irwin::FeatureSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const SITE: Site = Site {
+        lat: 38.0,
+        lon: -121.0,
+    };
+
+    /// A path under the temp dir no other test in this process uses.
+    fn temp_path(ext: &str) -> String {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "spe-watcher-{}-{}.{ext}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        std::env::temp_dir()
+            .join(name)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Watcher settings with an in-memory store and a cursor file that
+    /// doesn't exist yet.
+    fn config(mode: WatchMode) -> WatcherConfig {
+        WatcherConfig {
+            mode,
+            irwin: IrwinConfig::new(
+                "http://127.0.0.1:9".into(),
+                "user".into(),
+                "pass".into(),
+                "referer".into(),
+            ),
+            interval: Duration::from_secs(300),
+            radius_mi: 50.0,
+            audit_path: temp_path("jsonl"),
+            store_path: ":memory:".into(),
+            cursor_path: temp_path("json"),
+        }
+    }
+
+    async fn watcher(driver: Arc<dyn DeviceDriver>, mode: WatchMode) -> IncidentWatcher {
+        watcher_with(driver, config(mode)).await
+    }
+
+    async fn watcher_with(driver: Arc<dyn DeviceDriver>, cfg: WatcherConfig) -> IncidentWatcher {
+        let sites =
+            InstallationSites::parse("[installations.cabin]\nlat = 38.0\nlon = -121.0\n").unwrap();
+        IncidentWatcher::new(AppState::for_tests(driver).await, sites, cfg).unwrap()
+    }
+
+    /// An active fire `lat_offset` degrees due north of [`SITE`].
+    fn fire(id: &str, lat_offset: f64, modified_ms: u64) -> Incident {
+        Incident {
+            irwin_id: id.into(),
+            name: None,
+            lat: SITE.lat + lat_offset,
+            lon: SITE.lon,
+            acres: None,
+            containment_pct: None,
+            active: true,
+            modified_ms: Some(modified_ms),
+        }
+    }
+
+    /// IRWIN's record of [`fire`].
+    fn record(id: &str, lat_offset: f64, modified_ms: i64) -> Feature {
+        data_source_irwin::Incident {
+            irwin_id: Some(format!("{{{}}}", id.to_uppercase())),
+            poo_latitude: Some(SITE.lat + lat_offset),
+            poo_longitude: Some(SITE.lon),
+            incident_type_kind: Some("FI".into()),
+            is_valid: Some(true),
+            modified_on_date_time: Some(modified_ms),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    async fn runs(w: &IncidentWatcher) -> Vec<RunRecord> {
+        let filter = RunFilter::default();
+        let mut runs = w.state.runs.list(&filter, None, 100).await.unwrap().runs;
+        runs.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+        runs
+    }
+
+    #[tokio::test]
+    async fn apply_folds_sync_events_into_the_active_incidents() {
+        let mut w = watcher(Arc::new(MockDriver), WatchMode::DryRun).await;
+        let created = |f: Feature| SyncEvent::Created {
+            irwin_id: f.attributes.normalized_irwin_id().unwrap(),
+            incident: f,
+        };
+        let mut declared_out = record("b", 0.5, 300);
+        declared_out
+            .attributes
+            .extra
+            .insert("FireOutDateTime".into(), 300.into());
+        let unlocated = Feature::from(data_source_irwin::Incident {
+            irwin_id: Some("c".into()),
+            ..Default::default()
+        });
+
+        w.apply(&[
+            created(record("a", 0.1, 100)),
+            created(record("b", 0.5, 200)),
+            created(unlocated),
+        ]);
+        assert_eq!(w.incidents["a"], fire("a", 0.1, 100));
+        assert!(w.incidents.contains_key("b") && !w.incidents.contains_key("c"));
+
+        w.apply(&[
+            SyncEvent::Updated {
+                irwin_id: "b".into(),
+                previous: Box::new(record("b", 0.5, 200)),
+                current: declared_out,
+            },
+            SyncEvent::Invalidated {
+                irwin_id: "a".into(),
+                incident: record("a", 0.1, 400),
+            },
+        ]);
+        assert!(w.incidents.is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_restart_resumes_from_the_stored_incidents_and_cursor() {
+        let cfg = WatcherConfig {
+            store_path: temp_path("sqlite"),
+            ..config(WatchMode::DryRun)
+        };
+        let mut w = watcher_with(Arc::new(MockDriver), cfg.clone()).await;
+        let snapshot = FeatureSet {
+            features: vec![record("a", 0.1, 100), record("b", 0.5, 200)],
+            ..Default::default()
+        };
+        let events = w.sync.seed(snapshot, 5_000).unwrap();
+        w.apply(&events);
+        drop(w);
+
+        let w = watcher_with(Arc::new(MockDriver), cfg.clone()).await;
+        assert_eq!(w.sync.cursor_ms(), Some(200));
+        assert_eq!(w.incidents.len(), 2);
+        assert_eq!(w.incidents["b"], fire("b", 0.5, 200));
+
+        drop(w);
+        for path in [&cfg.store_path, &cfg.cursor_path] {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{path}{suffix}"));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_mode_starts_one_dry_run_per_policy_change() {
+        let mut w = watcher(Arc::new(MockDriver), WatchMode::DryRun).await;
+        // ~7 mi out: "near"
+        w.incidents.insert("a".into(), fire("a", 0.1, 0));
+
+        w.decide_site("cabin", SITE).await;
+        w.decide_site("cabin", SITE).await;
+        let started = runs(&w).await;
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].policy, Policy::Defend);
+        assert!(started[0].dry_run);
+        assert_eq!(started[0].requested_by.as_deref(), Some(REQUESTED_BY));
+        assert!(w.state.engine.current("cabin").is_none());
+    }
+
+    #[tokio::test]
+    async fn live_mode_waits_on_its_run_and_escalations_supersede_it() {
+        let mut w = watcher(Arc::new(Stalled), WatchMode::Live).await;
+        // ~21 mi out: "regional"
+        w.incidents.insert("a".into(), fire("a", 0.3, 0));
+
+        w.decide_site("cabin", SITE).await;
+        // still in flight, so not started again
+        w.decide_site("cabin", SITE).await;
+        let started = runs(&w).await;
+        assert_eq!(started.len(), 1);
+        assert_eq!(started[0].policy, Policy::Prepare);
+        assert!(!started[0].dry_run);
+
+        // under a mile: "fire-at-perimeter"
+        w.incidents.insert("a".into(), fire("a", 0.01, 1));
+        w.decide_site("cabin", SITE).await;
+        let started = runs(&w).await;
+        assert_eq!(started.len(), 2);
+        assert_eq!(started[1].policy, Policy::Suppress);
+        assert_eq!(started[1].supersedes, [started[0].run_id.clone()]);
+    }
+
+    #[tokio::test]
+    async fn live_mode_leaves_a_policy_alone_once_its_run_succeeded() {
+        let mut w = watcher(Arc::new(MockDriver), WatchMode::Live).await;
+        let mut events = w.state.events.subscribe();
+        w.incidents.insert("a".into(), fire("a", 0.1, 0));
+
+        w.decide_site("cabin", SITE).await;
+        while !events.recv().await.unwrap().is_terminal() {}
+        assert_eq!(runs(&w).await[0].status, RunStatus::Succeeded);
+        assert_eq!(
+            w.state.engine.current("cabin").map(|c| c.policy),
+            Some(Policy::Defend)
+        );
+
+        w.decide_site("cabin", SITE).await;
+        assert_eq!(runs(&w).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn live_mode_backs_off_after_a_failed_run() {
+        let mut w = watcher(Arc::new(Refusing), WatchMode::Live).await;
+        let mut events = w.state.events.subscribe();
+        w.incidents.insert("a".into(), fire("a", 0.1, 0));
+
+        w.decide_site("cabin", SITE).await;
+        while !events.recv().await.unwrap().is_terminal() {}
+        assert_eq!(runs(&w).await[0].status, RunStatus::Failed);
+        assert!(w.state.engine.current("cabin").is_none());
+
+        // the next poll doesn't retry straight away
+        w.decide_site("cabin", SITE).await;
+        assert_eq!(runs(&w).await.len(), 1);
+
+        // once the backoff is up it does, and the next wait is longer
+        w.cfg.interval = Duration::ZERO;
+        w.decide_site("cabin", SITE).await;
+        let started = runs(&w).await;
+        assert_eq!(started.len(), 2);
+        assert_eq!(started[1].policy, Policy::Defend);
+        assert_eq!(w.acted["cabin"].failures, 1);
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Built-in sites, used when `INSTALLATION_SITES_PATH` is not set.
+const DEFAULT_SITES: &str = include_str!("../../config/installation_sites.toml");
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Site {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSitesFile {
+    #[serde(default)]
+    installations: BTreeMap<String, Site>,
+}
+
+/// Validated installation locations, keyed by installation id.
+#[derive(Debug, Clone, Default)]
+pub struct InstallationSites {
+    sites: BTreeMap<String, Site>,
+}
+
+impl InstallationSites {
+    /// Load from `INSTALLATION_SITES_PATH`, or the built-in
+    /// `config/installation_sites.toml` when unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("INSTALLATION_SITES_PATH") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading installation sites file {path}"))?;
+                Self::parse(&raw).with_context(|| format!("invalid installation sites file {path}"))
+            }
+            Err(_) => Self::parse(DEFAULT_SITES).context("invalid built-in installation sites"),
+        }
+    }
+
+    /// Parse and validate an installation sites TOML document.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let file: RawSitesFile = toml::from_str(raw)?;
+        for (id, site) in &file.installations {
+            if !(-90.0..=90.0).contains(&site.lat) {
+                bail!("[installations.{id}] lat {} is out of range", site.lat);
+            }
+            if !(-180.0..=180.0).contains(&site.lon) {
+                bail!("[installations.{id}] lon {} is out of range", site.lon);
+            }
+        }
+        Ok(Self {
+            sites: file.installations,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Site)> {
+        self.sites.iter().map(|(id, s)| (id.as_str(), *s))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sites.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_is_empty_and_coordinates_are_range_checked() {
+        assert!(InstallationSites::parse(DEFAULT_SITES).unwrap().is_empty());
+
+        let site =
+            |lat: f64, lon: f64| format!("[installations.cabin]\nlat = {lat}\nlon = {lon}\n");
+        let sites = InstallationSites::parse(&site(38.5, -121.5)).unwrap();
+        assert_eq!(
+            sites.iter().collect::<Vec<_>>(),
+            vec![(
+                "cabin",
+                Site {
+                    lat: 38.5,
+                    lon: -121.5
+                }
+            )]
+        );
+        assert!(InstallationSites::parse(&site(95.0, -121.5)).is_err());
+        assert!(InstallationSites::parse(&site(38.5, 190.0)).is_err());
+    }
+}
@@ -2,11 +2,14 @@ mod device_abstraction_layer;
 mod enactor;
 mod engine;
 mod error;
+mod events;
 mod idempotency;
+mod incident_watcher;
 mod models;
 mod policy;
 mod routes;
 mod state;
+mod storage;
 mod suppression_policy_runner;
 mod telemetry;
 mod time;
@@ -17,7 +20,7 @@ use tracing_subscriber::{fmt, EnvFilter};
 
 // ✅ correct imports for the resolver + drivers
 use crate::device_abstraction_layer::drivers::control_by_web::{
-    InMemoryResolver, InstallationAccountResolver,
+    InMemoryResolver, InstallationAccountResolver, RelayMapBook,
 };
 use crate::device_abstraction_layer::drivers::{
     ControlByWebConfig, ControlByWebDriver, MockDriver,
@@ -30,7 +33,25 @@ async fn main() {
     let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
     fmt().with_env_filter(filter).init();
 
-    let engine = Arc::new(engine::Engine::new());
+    // Decision rules are validated up front; a bad file stops startup
+    let rules = policy::DecisionRules::from_env().unwrap_or_else(|e| {
+        eprintln!("decision rules config error: {e:#}");
+        std::process::exit(1);
+    });
+    let engine = Arc::new(engine::Engine::new(rules));
+
+    // Policy → command plans are validated up front; a bad file stops startup
+    let plans = Arc::new(enactor::PlanBook::from_env().unwrap_or_else(|e| {
+        eprintln!("plans config error: {e:#}");
+        std::process::exit(1);
+    }));
+    // Relay maps too, and they must map every command the plans send
+    let relay_maps = RelayMapBook::from_env()
+        .and_then(|maps| maps.check_plans(&plans).map(|()| maps))
+        .unwrap_or_else(|e| {
+            eprintln!("relay map config error: {e:#}");
+            std::process::exit(1);
+        });
 
     // ── Build installation → account resolver
     let mut map = HashMap::new();
@@ -51,20 +72,60 @@ async fn main() {
     let resolver: Arc<dyn InstallationAccountResolver> =
         Arc::new(InMemoryResolver::new(map, default_account, default_device));
     // ── Build DAL (prefer ControlByWeb; fall back to Mock on any error)
-    let device_abstraction_layer: Arc<dyn DeviceDriver> = match ControlByWebConfig::from_env() {
-        Ok(cfg) => match ControlByWebDriver::new(cfg, resolver.clone()) {
-            Ok(driver) => Arc::new(driver),
+    let device_abstraction_layer: Arc<dyn DeviceDriver> =
+        match ControlByWebConfig::from_env(relay_maps) {
+            Ok(cfg) => match ControlByWebDriver::new(cfg, resolver.clone()) {
+                Ok(driver) => Arc::new(driver),
+                Err(e) => {
+                    eprintln!("CBW driver init failed: {e}; falling back to MockDriver");
+                    Arc::new(MockDriver)
+                }
+            },
             Err(e) => {
-                eprintln!("CBW driver init failed: {e}; falling back to MockDriver");
+                eprintln!("CBW driver not configured ({e:#}); using MockDriver");
                 Arc::new(MockDriver)
             }
-        },
-        Err(_) => Arc::new(MockDriver),
-    };
+        };
 
     let telemetry = Arc::new(telemetry::NoopSink);
 
-    let app_state = state::AppState::new(engine, device_abstraction_layer, telemetry);
+    let storage_cfg = storage::StorageConfig::from_env().expect("invalid storage config");
+    let storage = storage::Storage::open(&storage_cfg).expect("failed to open run storage");
+    let app_state =
+        state::AppState::new(engine, device_abstraction_layer, telemetry, storage, plans)
+            .await
+            .expect("failed to initialize app state");
+
+    // Runs left Starting/Running/Canceling by a previous process
+    let recovery = suppression_policy_runner::RecoveryPolicy::from_env();
+    if let Err(e) = suppression_policy_runner::reconcile(&app_state, &recovery).await {
+        eprintln!("run reconciliation failed: {e}");
+    }
+    // Transition limits need to know what each site is already running
+    if let Err(e) = suppression_policy_runner::restore_current_policies(&app_state).await {
+        eprintln!("restoring current policies failed: {e}");
+    }
+
+    // IRWIN incident watcher; off unless IRWIN_WATCHER says otherwise
+    let watcher_cfg = incident_watcher::WatcherConfig::from_env().unwrap_or_else(|e| {
+        eprintln!("irwin watcher config error: {e:#}");
+        std::process::exit(1);
+    });
+    if let Some(cfg) = watcher_cfg {
+        let sites = incident_watcher::sites::InstallationSites::from_env().unwrap_or_else(|e| {
+            eprintln!("installation sites config error: {e:#}");
+            std::process::exit(1);
+        });
+        match incident_watcher::IncidentWatcher::new(app_state.clone(), sites, cfg) {
+            Ok(watcher) => {
+                watcher.spawn();
+            }
+            Err(e) => {
+                eprintln!("irwin watcher init failed: {e:#}");
+                std::process::exit(1);
+            }
+        }
+    }
     let app = web::routes(app_state.clone());
 
     let port: u16 = env::var("PORT")
@@ -75,6 +136,32 @@ async fn main() {
     println!("listening on http://{}", addr);
 
     axum::serve(tokio::net::TcpListener::bind(addr).await.unwrap(), app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .unwrap();
+
+    // Cached device credentials are released only once we stop serving
+    app_state.device_abstraction_layer.shutdown().await;
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut s) => {
+                s.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 }
@@ -1,8 +1,9 @@
-use crate::policy::Policy;
+use crate::enactor::EnactStep;
+use crate::policy::{DecisionInputs, Policy};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum RunStatus {
     Starting,
@@ -13,23 +14,74 @@ pub enum RunStatus {
     Canceled,
 }
 
+impl RunStatus {
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            RunStatus::Succeeded | RunStatus::Failed | RunStatus::Canceled
+        )
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunRecord {
     pub run_id: String,
     pub installation_id: String,
     pub policy: Policy,
     pub level: u8,
-    pub actions: Vec<String>,
+    /// Enactor steps in execution order, appended live as each one completes.
+    #[serde(default)]
+    pub steps: Vec<EnactStep>,
     pub dry_run: bool,
+    #[serde(default)]
+    pub requested_by: Option<String>,
     pub status: RunStatus,
     pub started_at_ms: u128,
     pub updated_at_ms: u128,
+    /// Why a run ended in `Failed`: the failing step's driver error class
+    /// (e.g. `device_offline`), `step_failed` when the driver reported the
+    /// step not ok without an error, or `interrupted` / `enactor_error`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+    /// Run this one waited behind when it was created (same installation).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub queued_behind: Option<String>,
+    /// In-flight runs this one canceled when it was created with `supersede`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub supersedes: Vec<String>,
+    /// The run that canceled this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub superseded_by: Option<String>,
 
-    // internal flags (not strictly needed yet)
+    // internal flags: set by POST /v1/runs/{run_id}/cancel, observed by the runner
     #[serde(skip)]
     pub cancel_requested: bool,
 }
 
+#[cfg(test)]
+impl RunRecord {
+    /// A fresh run as the create path stores it.
+    pub fn starting(run_id: &str, installation_id: &str, policy: Policy) -> Self {
+        Self {
+            run_id: run_id.to_string(),
+            installation_id: installation_id.to_string(),
+            policy,
+            level: policy.level(),
+            steps: Vec::new(),
+            dry_run: false,
+            requested_by: None,
+            status: RunStatus::Starting,
+            started_at_ms: 1_000,
+            updated_at_ms: 1_000,
+            failure_reason: None,
+            queued_behind: None,
+            supersedes: Vec::new(),
+            superseded_by: None,
+            cancel_requested: false,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct StartRunRequest {
     pub policy: Policy,
@@ -39,6 +91,44 @@ pub struct StartRunRequest {
     pub metadata: Option<HashMap<String, String>>,
     #[serde(default)]
     pub requested_by: Option<String>,
+    /// Cancel runs already queued or running for the installation instead of
+    /// waiting behind them.
+    #[serde(default)]
+    pub supersede: bool,
+}
+
+/// Query string for `GET /v1/runs` and `GET /v1/installations/{id}/runs`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListRunsQuery {
+    pub status: Option<RunStatus>,
+    pub policy: Option<Policy>,
+    pub requested_by: Option<String>,
+    pub dry_run: Option<bool>,
+    /// Inclusive lower bound on `started_at_ms`.
+    /// (u64 here: the query-string decoder has no u128 support.)
+    pub since_ms: Option<u64>,
+    /// Exclusive upper bound on `started_at_ms`.
+    pub until_ms: Option<u64>,
+    /// `next_cursor` from the previous page.
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Body for `POST /v1/installations/{id}/decide`.
+#[derive(Debug, Deserialize)]
+pub struct DecideRequest {
+    #[serde(default)]
+    pub inputs: DecisionInputs,
+    /// Also start a run with the decided policy.
+    #[serde(default)]
+    pub start_run: bool,
+    /// Passed to the run when `start_run` is set.
+    #[serde(default)]
+    pub dry_run: bool,
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    #[serde(default)]
+    pub supersede: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -1,3 +1,5 @@
+pub mod rules;
 pub mod types;
 
+pub use rules::{DecisionInputs, DecisionRules};
 pub use types::Policy;
@@ -0,0 +1,462 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::Policy;
+
+/// Built-in rules, used when `DECISION_RULES_PATH` is not set.
+const DEFAULT_RULES: &str = include_str!("../../config/decision_rules.toml");
+
+/// Observations the decider works from. Every field is optional; a condition
+/// on a missing input never holds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DecisionInputs {
+    /// Distance from the installation to the nearest incident, miles.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub distance_mi: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fire_acres: Option<f64>,
+    /// Percent contained, 0–100.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub containment_pct: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wind_speed_mph: Option<f64>,
+    /// Direction the wind blows *from*, degrees true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wind_from_deg: Option<f64>,
+    /// Bearing from the installation to the fire, degrees true.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fire_bearing_deg: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relative_humidity_pct: Option<f64>,
+    /// Vapor pressure deficit, kPa.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vpd_kpa: Option<f64>,
+}
+
+impl DecisionInputs {
+    /// Wind component pushing from the fire toward the installation, mph.
+    /// Wind blowing *from* the fire's bearing is fully toward us; negative
+    /// values mean it is blowing the fire away.
+    pub fn wind_toward_mph(&self) -> Option<f64> {
+        let speed = self.wind_speed_mph?;
+        let diff = (self.wind_from_deg? - self.fire_bearing_deg?).to_radians();
+        Some(speed * diff.cos())
+    }
+}
+
+/// What a rule condition can test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+    DistanceMi,
+    FireAcres,
+    ContainmentPct,
+    WindSpeedMph,
+    WindTowardMph,
+    RelativeHumidityPct,
+    VpdKpa,
+}
+
+impl Input {
+    fn value(self, inputs: &DecisionInputs) -> Option<f64> {
+        match self {
+            Input::DistanceMi => inputs.distance_mi,
+            Input::FireAcres => inputs.fire_acres,
+            Input::ContainmentPct => inputs.containment_pct,
+            Input::WindSpeedMph => inputs.wind_speed_mph,
+            Input::WindTowardMph => inputs.wind_toward_mph(),
+            Input::RelativeHumidityPct => inputs.relative_humidity_pct,
+            Input::VpdKpa => inputs.vpd_kpa,
+        }
+    }
+}
+
+/// Inclusive threshold; at least one side is set.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bound {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+}
+
+impl Bound {
+    fn holds(&self, v: f64) -> bool {
+        self.min.is_none_or(|m| v >= m) && self.max.is_none_or(|m| v <= m)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Rule {
+    pub name: String,
+    pub policy: Policy,
+    pub when: BTreeMap<Input, Bound>,
+    /// Wider conditions that keep the policy in force once it is; defaults
+    /// to `when`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold: Option<BTreeMap<Input, Bound>>,
+}
+
+impl Rule {
+    fn matches(&self, inputs: &DecisionInputs) -> bool {
+        all_hold(&self.when, inputs)
+    }
+
+    fn holds(&self, inputs: &DecisionInputs) -> bool {
+        all_hold(self.hold.as_ref().unwrap_or(&self.when), inputs)
+    }
+}
+
+fn all_hold(conditions: &BTreeMap<Input, Bound>, inputs: &DecisionInputs) -> bool {
+    conditions
+        .iter()
+        .all(|(input, bound)| input.value(inputs).is_some_and(|v| bound.holds(v)))
+}
+
+/// Limits on how the decider may move an installation between policies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Transitions {
+    /// Seconds the current policy must have been in force before the decider
+    /// may escalate past it.
+    pub min_dwell_escalate_s: u64,
+    /// Same, before it may step down.
+    pub min_dwell_deescalate_s: u64,
+    /// `false`: the decider only ever escalates; stepping down takes a
+    /// manual run.
+    pub auto_deescalate: bool,
+}
+
+impl Default for Transitions {
+    fn default() -> Self {
+        Self {
+            min_dwell_escalate_s: 0,
+            min_dwell_deescalate_s: 0,
+            auto_deescalate: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTransitions {
+    min_dwell_escalate_s: Option<u64>,
+    min_dwell_deescalate_s: Option<u64>,
+    auto_deescalate: Option<bool>,
+}
+
+impl RawTransitions {
+    fn over(self, base: Transitions) -> Transitions {
+        Transitions {
+            min_dwell_escalate_s: self
+                .min_dwell_escalate_s
+                .unwrap_or(base.min_dwell_escalate_s),
+            min_dwell_deescalate_s: self
+                .min_dwell_deescalate_s
+                .unwrap_or(base.min_dwell_deescalate_s),
+            auto_deescalate: self.auto_deescalate.unwrap_or(base.auto_deescalate),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    name: String,
+    policy: String,
+    when: BTreeMap<Input, Bound>,
+    #[serde(default)]
+    hold: Option<BTreeMap<Input, Bound>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRuleSet {
+    #[serde(default)]
+    fallback: Option<String>,
+    #[serde(default)]
+    rules: Vec<RawRule>,
+    #[serde(default)]
+    transitions: RawTransitions,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRulesFile {
+    defaults: RawRuleSet,
+    #[serde(default)]
+    installations: HashMap<String, RawRuleSet>,
+}
+
+#[derive(Debug, Clone)]
+struct RuleSet {
+    fallback: Policy,
+    rules: Vec<Rule>,
+    transitions: Transitions,
+}
+
+/// Where the rules behind a decision came from.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleSource {
+    Installation,
+    Default,
+}
+
+/// A decided policy plus why: the rule that fired and the values it saw.
+#[derive(Debug, Clone, Serialize)]
+pub struct Decision {
+    pub policy: Policy,
+    /// The rule that set the policy; `None` when nothing matched and the
+    /// fallback applied.
+    pub rule: Option<Rule>,
+    /// Names of every matching rule, in file order.
+    pub matched: Vec<String>,
+    pub source: RuleSource,
+    pub inputs: DecisionInputs,
+    /// Values computed from `inputs` that rules can test.
+    pub derived: BTreeMap<Input, f64>,
+}
+
+/// Validated decision rules: defaults plus per-installation replacements.
+#[derive(Debug, Clone)]
+pub struct DecisionRules {
+    defaults: RuleSet,
+    installations: HashMap<String, RuleSet>,
+}
+
+impl DecisionRules {
+    /// Load from `DECISION_RULES_PATH`, or the built-in
+    /// `config/decision_rules.toml` when unset.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("DECISION_RULES_PATH") {
+            Ok(path) => {
+                let raw = std::fs::read_to_string(&path)
+                    .with_context(|| format!("reading decision rules file {path}"))?;
+                Self::parse(&raw).with_context(|| format!("invalid decision rules file {path}"))
+            }
+            Err(_) => Self::parse(DEFAULT_RULES).context("invalid built-in decision rules"),
+        }
+    }
+
+    /// Parse and validate a decision rules TOML document.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let file: RawRulesFile = toml::from_str(raw)?;
+
+        let defaults = rule_set(
+            file.defaults,
+            Policy::Observe,
+            Transitions::default(),
+            "defaults",
+        )?;
+
+        let mut installations = HashMap::new();
+        for (id, raw) in file.installations {
+            let set = rule_set(
+                raw,
+                defaults.fallback,
+                defaults.transitions,
+                &format!("installations.{id}"),
+            )?;
+            installations.insert(id, set);
+        }
+
+        Ok(Self {
+            defaults,
+            installations,
+        })
+    }
+
+    /// Recommend a policy for `installation_id` from `inputs`.
+    pub fn decide(&self, installation_id: &str, inputs: DecisionInputs) -> Decision {
+        let (source, set) = self.rule_set(installation_id);
+
+        let matching: Vec<&Rule> = set.rules.iter().filter(|r| r.matches(&inputs)).collect();
+        // most severe wins; `max_by_key` keeps the last max, so walk in reverse
+        let fired = matching
+            .iter()
+            .rev()
+            .max_by_key(|r| r.policy.level())
+            .map(|r| (*r).clone());
+
+        let mut derived = BTreeMap::new();
+        if let Some(v) = inputs.wind_toward_mph() {
+            derived.insert(Input::WindTowardMph, v);
+        }
+
+        Decision {
+            policy: fired.as_ref().map_or(set.fallback, |r| r.policy),
+            rule: fired,
+            matched: matching.iter().map(|r| r.name.clone()).collect(),
+            source,
+            inputs,
+            derived,
+        }
+    }
+
+    /// The most severe rule at or below `ceiling` whose `hold` conditions
+    /// still cover `inputs`; ties go to the rule listed first.
+    pub fn holding(
+        &self,
+        installation_id: &str,
+        inputs: &DecisionInputs,
+        ceiling: Policy,
+    ) -> Option<&Rule> {
+        let (_, set) = self.rule_set(installation_id);
+        set.rules
+            .iter()
+            .filter(|r| r.policy.level() <= ceiling.level() && r.holds(inputs))
+            .rev()
+            .max_by_key(|r| r.policy.level())
+    }
+
+    pub fn transitions(&self, installation_id: &str) -> Transitions {
+        self.rule_set(installation_id).1.transitions
+    }
+
+    fn rule_set(&self, installation_id: &str) -> (RuleSource, &RuleSet) {
+        match self.installations.get(installation_id) {
+            Some(set) => (RuleSource::Installation, set),
+            None => (RuleSource::Default, &self.defaults),
+        }
+    }
+}
+
+fn parse_policy(raw: &str, table: &str, field: &str) -> Result<Policy> {
+    raw.parse()
+        .map_err(|_| anyhow::anyhow!("[{table}] {field} has unknown policy `{raw}`"))
+}
+
+fn rule_set(
+    raw: RawRuleSet,
+    inherited_fallback: Policy,
+    inherited_transitions: Transitions,
+    table: &str,
+) -> Result<RuleSet> {
+    let fallback = match &raw.fallback {
+        Some(p) => parse_policy(p, table, "fallback")?,
+        None => inherited_fallback,
+    };
+
+    let mut names = HashSet::new();
+    let mut rules = Vec::with_capacity(raw.rules.len());
+    for r in raw.rules {
+        if !names.insert(r.name.clone()) {
+            bail!("[{table}] rule `{}` is listed twice", r.name);
+        }
+        let policy = parse_policy(&r.policy, table, &format!("rule `{}`", r.name))?;
+        if r.when.is_empty() {
+            bail!("[{table}] rule `{}` has no conditions", r.name);
+        }
+        if r.hold.as_ref().is_some_and(BTreeMap::is_empty) {
+            bail!("[{table}] rule `{}` has an empty `hold`", r.name);
+        }
+        for (input, bound) in r.when.iter().chain(r.hold.iter().flatten()) {
+            if bound.min.is_none() && bound.max.is_none() {
+                bail!(
+                    "[{table}] rule `{}`: {input:?} needs `min` or `max`",
+                    r.name
+                );
+            }
+        }
+        rules.push(Rule {
+            name: r.name,
+            policy,
+            when: r.when,
+            hold: r.hold,
+        });
+    }
+    Ok(RuleSet {
+        fallback,
+        rules,
+        transitions: raw.transitions.over(inherited_transitions),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn builtin() -> DecisionRules {
+        DecisionRules::parse(DEFAULT_RULES).unwrap()
+    }
+
+    #[test]
+    fn most_severe_matching_rule_wins() {
+        let d = builtin().decide(
+            "anywhere",
+            DecisionInputs {
+                distance_mi: Some(2.5),
+                wind_speed_mph: Some(25.0),
+                wind_from_deg: Some(270.0),
+                fire_bearing_deg: Some(270.0),
+                ..Default::default()
+            },
+        );
+        assert_eq!(d.policy, Policy::Suppress);
+        assert_eq!(d.rule.unwrap().name, "close-wind-driven");
+        assert!(d.matched.contains(&"close".to_string()));
+        assert_eq!(d.derived[&Input::WindTowardMph], 25.0);
+
+        // same fire, wind blowing it away
+        let d = builtin().decide(
+            "anywhere",
+            DecisionInputs {
+                distance_mi: Some(2.5),
+                wind_speed_mph: Some(25.0),
+                wind_from_deg: Some(90.0),
+                fire_bearing_deg: Some(270.0),
+                ..Default::default()
+            },
+        );
+        assert_eq!(d.policy, Policy::Contain);
+        assert_eq!(d.rule.unwrap().name, "close");
+    }
+
+    #[test]
+    fn missing_inputs_never_match_and_fallback_applies() {
+        let d = builtin().decide("anywhere", DecisionInputs::default());
+        assert_eq!(d.policy, Policy::Observe);
+        assert!(d.rule.is_none());
+        assert!(d.matched.is_empty());
+    }
+
+    #[test]
+    fn installation_rules_replace_defaults() {
+        let rules = DecisionRules::parse(&format!(
+            "{DEFAULT_RULES}\n[installations.cabin]\nfallback = \"prepare\"\n\
+             [[installations.cabin.rules]]\nname = \"any\"\npolicy = \"defend\"\n\
+             when = {{ distance_mi = {{ max = 50.0 }} }}\n"
+        ))
+        .unwrap();
+        let d = rules.decide(
+            "cabin",
+            DecisionInputs {
+                distance_mi: Some(0.5),
+                ..Default::default()
+            },
+        );
+        assert!(matches!(d.source, RuleSource::Installation));
+        assert_eq!(d.policy, Policy::Defend);
+        assert_eq!(
+            rules.decide("cabin", DecisionInputs::default()).policy,
+            Policy::Prepare
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_inputs_policies_and_empty_bounds() {
+        let rule = |policy: &str, when: &str| {
+            format!(
+                "[defaults]\n[[defaults.rules]]\nname = \"r\"\npolicy = \"{policy}\"\nwhen = {when}\n"
+            )
+        };
+        assert!(DecisionRules::parse(&rule("defend", "{ distance_mi = { max = 1 } }")).is_ok());
+        assert!(DecisionRules::parse(&rule("defend", "{ distanse_mi = { max = 1 } }")).is_err());
+        assert!(DecisionRules::parse(&rule("panic", "{ distance_mi = { max = 1 } }")).is_err());
+        assert!(DecisionRules::parse(&rule("defend", "{ distance_mi = {} }")).is_err());
+        assert!(DecisionRules::parse(&rule("defend", "{}")).is_err());
+    }
+}
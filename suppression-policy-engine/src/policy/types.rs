@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
 
 /// Five-level policy scale with Unknown catch-all for extensibility.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Policy {
     Observe,  // L1
@@ -52,3 +53,19 @@ impl fmt::Display for Policy {
         })
     }
 }
+
+impl FromStr for Policy {
+    type Err = ();
+
+    /// Strict parse of the snake_case name; unlike serde, never yields `Unknown`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "observe" => Ok(Policy::Observe),
+            "prepare" => Ok(Policy::Prepare),
+            "defend" => Ok(Policy::Defend),
+            "contain" => Ok(Policy::Contain),
+            "suppress" => Ok(Policy::Suppress),
+            _ => Err(()),
+        }
+    }
+}
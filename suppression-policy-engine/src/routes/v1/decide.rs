@@ -0,0 +1,51 @@
+use axum::{
+    extract::{Path, State},
+    http::{header::LOCATION, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use super::runs::create_run_and_response;
+use crate::{
+    models::{DecideRequest, StartRunRequest},
+    state::AppState,
+};
+
+/* -------------------- POST /v1/installations/{installation_id}/decide --------------------
+Let the engine pick the policy from fire/weather inputs. Returns the evaluation
+with its explanation; with `start_run` it also starts a run for that policy.
+------------------------------------------------------------------ */
+
+pub async fn post_decide(
+    State(state): State<AppState>,
+    Path(installation_id): Path<String>,
+    Json(body): Json<DecideRequest>,
+) -> Response {
+    let eval = state
+        .engine
+        .decide(&installation_id, body.inputs, body.dry_run);
+
+    if !body.start_run {
+        return (StatusCode::OK, Json(eval)).into_response();
+    }
+
+    let start = StartRunRequest {
+        policy: eval.policy,
+        dry_run: body.dry_run,
+        metadata: None,
+        requested_by: Some(body.requested_by.unwrap_or_else(|| "decider".to_string())),
+        supersede: body.supersede,
+    };
+    match create_run_and_response(state.clone(), installation_id, start).await {
+        Ok(created) => (
+            StatusCode::CREATED,
+            [(LOCATION, format!("/v1/runs/{}", created.run_id))],
+            Json(serde_json::json!({
+                "run_id": created.run_id,
+                "evaluation": eval,
+            })),
+        )
+            .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
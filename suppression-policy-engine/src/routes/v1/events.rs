@@ -0,0 +1,181 @@
+use axum::{
+    extract::{Path, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::{error::ApiError, events::RunEvent, state::AppState};
+
+/* -------------------- GET /v1/runs/{run_id}/events --------------------
+Current status first, then live status/step events; closes after the
+terminal status.
+------------------------------------------------------------------ */
+
+pub async fn get_run_events(State(state): State<AppState>, Path(run_id): Path<String>) -> Response {
+    // subscribe before the snapshot so nothing falls in between
+    let rx = state.events.subscribe();
+    let snapshot = match state.runs.get(&run_id).await {
+        Ok(Some(r)) => RunEvent::status(&r),
+        Ok(None) => return ApiError::NotFound("run not found").into_response(),
+        Err(e) => return ApiError::Internal(e.to_string()).into_response(),
+    };
+
+    let done = snapshot.is_terminal();
+    let head = stream::once(async move { to_sse(&snapshot) });
+    let live = run_events(state, run_id, rx);
+    let body = if done {
+        head.boxed()
+    } else {
+        head.chain(live).boxed()
+    };
+    Sse::new(body)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/* -------------------- GET /v1/installations/{installation_id}/events -------------------- */
+
+pub async fn get_installation_events(
+    State(state): State<AppState>,
+    Path(installation_id): Path<String>,
+) -> Response {
+    let live = filtered(state.events.subscribe(), move |ev| {
+        ev.installation_id() == installation_id
+    });
+    Sse::new(live)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/* -------------------- GET /v1/events -------------------- */
+
+pub async fn get_all_events(State(state): State<AppState>) -> Response {
+    Sse::new(filtered(state.events.subscribe(), |_| true))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Live events for one run, ending after its terminal status.
+///
+/// A lag may have dropped that status, so on lag the run is re-read and, if
+/// it has finished, the stream closes with its final status right after the
+/// `lagged` event.
+fn run_events(
+    state: AppState,
+    run_id: String,
+    rx: Receiver<RunEvent>,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static {
+    stream::unfold(
+        (rx, None::<RunEvent>, false),
+        move |(mut rx, pending, done)| {
+            let state = state.clone();
+            let run_id = run_id.clone();
+            async move {
+                if done {
+                    return None;
+                }
+                if let Some(ev) = pending {
+                    let done = ev.is_terminal();
+                    return Some((to_sse(&ev), (rx, None, done)));
+                }
+                loop {
+                    match rx.recv().await {
+                        Ok(ev) if ev.run_id() == run_id => {
+                            let done = ev.is_terminal();
+                            return Some((to_sse(&ev), (rx, None, done)));
+                        }
+                        Ok(_) => continue,
+                        Err(RecvError::Lagged(n)) => {
+                            let run = state.runs.get(&run_id).await.ok().flatten();
+                            let last = run
+                                .filter(|r| r.status.is_terminal())
+                                .map(|r| RunEvent::status(&r));
+                            return Some((lagged(n), (rx, last, false)));
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Turn a bus subscription into an SSE stream of the events `keep` accepts.
+/// A lagging subscriber gets a `lagged` event with the number of dropped events
+/// so it can refetch state.
+fn filtered<F>(
+    rx: Receiver<RunEvent>,
+    keep: F,
+) -> impl Stream<Item = Result<Event, Infallible>> + Send + 'static
+where
+    F: Fn(&RunEvent) -> bool + Send + 'static,
+{
+    stream::unfold((rx, keep), move |(mut rx, keep)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(ev) if keep(&ev) => return Some((to_sse(&ev), (rx, keep))),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(n)) => return Some((lagged(n), (rx, keep))),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+fn lagged(n: u64) -> Result<Event, Infallible> {
+    Ok(Event::default().event("lagged").data(n.to_string()))
+}
+
+fn to_sse(ev: &RunEvent) -> Result<Event, Infallible> {
+    Ok(Event::default()
+        .event(ev.name())
+        .json_data(ev)
+        .unwrap_or_else(|_| Event::default().event("error")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::drivers::MockDriver;
+    use crate::events::RunEvents;
+    use crate::models::{RunRecord, RunStatus};
+    use crate::policy::Policy;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn run_stream_closes_when_the_terminal_status_was_lagged_away() {
+        let mut state = AppState::for_tests(Arc::new(MockDriver)).await;
+        state.events = RunEvents::new(1);
+        let rx = state.events.subscribe();
+
+        // the run finishes while the subscriber is too slow to see it
+        let mut run = RunRecord::starting("r_01", "h", Policy::Observe);
+        state.events.status(&run);
+        run.status = RunStatus::Succeeded;
+        state.runs.insert(run.clone()).await.unwrap();
+        state.events.status(&run);
+        state
+            .events
+            .status(&RunRecord::starting("r_02", "h", Policy::Observe));
+
+        let events = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_events(state, "r_01".into(), rx).collect::<Vec<_>>(),
+        )
+        .await
+        .expect("stream did not close");
+        let events: Vec<String> = events
+            .into_iter()
+            .map(|e| format!("{:?}", e.unwrap()))
+            .collect();
+        assert_eq!(events.len(), 2, "{events:?}");
+        assert!(events[0].contains("lagged"), "{}", events[0]);
+        assert!(events[1].contains("succeeded"), "{}", events[1]);
+    }
+}
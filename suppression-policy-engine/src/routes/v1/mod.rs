@@ -5,18 +5,45 @@ use axum::{
 
 use crate::state::AppState;
 
+pub mod decide;
+pub mod events;
 pub mod health;
+pub mod plans;
 pub mod runs;
+pub mod status;
 
 pub fn router(state: AppState) -> Router {
     Router::new()
         .route("/api/health", get(health::get))
         .route(
             "/v1/installations/{installation_id}/runs",
-            post(runs::post_start_run),
+            post(runs::post_start_run).get(runs::get_list_installation_runs),
         )
+        .route(
+            "/v1/installations/{installation_id}/events",
+            get(events::get_installation_events),
+        )
+        .route(
+            "/v1/installations/{installation_id}/plans",
+            get(plans::get_plans),
+        )
+        .route(
+            "/v1/installations/{installation_id}/plans/{policy}",
+            get(plans::get_plan),
+        )
+        .route(
+            "/v1/installations/{installation_id}/decide",
+            post(decide::post_decide),
+        )
+        .route(
+            "/v1/installations/{installation_id}/status",
+            get(status::get_status),
+        )
+        .route("/v1/events", get(events::get_all_events))
+        .route("/v1/runs", get(runs::get_list_runs))
         .route("/v1/runs/{run_id}", get(runs::get_run))
         .route("/v1/runs/{run_id}/cancel", post(runs::post_cancel_run))
+        .route("/v1/runs/{run_id}/events", get(events::get_run_events))
         // ✅ Keep this for the HTML form
         .route("/api/evaluate", get(runs::get_evaluate_query))
         .with_state(state)
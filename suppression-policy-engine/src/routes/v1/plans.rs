@@ -0,0 +1,37 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{error::ApiError, policy::Policy, state::AppState};
+
+/* -------------------- GET /v1/installations/{installation_id}/plans -------------------- */
+
+pub async fn get_plans(
+    State(state): State<AppState>,
+    Path(installation_id): Path<String>,
+) -> Response {
+    (
+        StatusCode::OK,
+        Json(state.plans.resolve_all(&installation_id)),
+    )
+        .into_response()
+}
+
+/* -------------------- GET /v1/installations/{installation_id}/plans/{policy} -------------------- */
+
+pub async fn get_plan(
+    State(state): State<AppState>,
+    Path((installation_id, policy)): Path<(String, String)>,
+) -> Response {
+    match policy.parse::<Policy>() {
+        Ok(policy) => (
+            StatusCode::OK,
+            Json(state.plans.resolve(&installation_id, policy)),
+        )
+            .into_response(),
+        Err(()) => ApiError::NotFound("unknown policy").into_response(),
+    }
+}
@@ -6,24 +6,68 @@ use axum::{
 };
 use std::sync::atomic::Ordering;
 
-use crate::suppression_policy_runner::spawn_run; // if your path is `crate::spr::runner::spawn_run`, change this import accordingly
+use crate::suppression_policy_runner::{admit_run, request_cancel};
 use crate::{
     engine::Evaluation,
     error::ApiError,
     idempotency::{IdemRecord, IdempotencyStore},
-    models::{EvaluateRequest, RunRecord, RunStatus, StartRunRequest},
+    models::{EvaluateRequest, ListRunsQuery, RunRecord, RunStatus, StartRunRequest},
     state::AppState,
+    storage::{run_id_for, run_seq, RunFilter},
     time::now_ms,
 };
 
 /* -------------------- GET /v1/runs/{run_id} -------------------- */
 
 pub async fn get_run(State(state): State<AppState>, Path(run_id): Path<String>) -> Response {
-    let guard = state.runs.read().await;
-    if let Some(r) = guard.get(&run_id) {
-        return (StatusCode::OK, Json(r.clone())).into_response();
+    match state.runs.get(&run_id).await {
+        Ok(Some(r)) => (StatusCode::OK, Json(r)).into_response(),
+        Ok(None) => ApiError::NotFound("run not found").into_response(),
+        Err(e) => ApiError::Internal(e.to_string()).into_response(),
+    }
+}
+
+/* -------------------- GET /v1/runs, GET /v1/installations/{installation_id}/runs --------------------
+Newest first. `next_cursor` from one page is passed back as `cursor` for the next.
+------------------------------------------------------------------ */
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 200;
+
+pub async fn get_list_runs(
+    State(state): State<AppState>,
+    Query(q): Query<ListRunsQuery>,
+) -> Response {
+    list_runs(state, None, q).await
+}
+
+pub async fn get_list_installation_runs(
+    State(state): State<AppState>,
+    Path(installation_id): Path<String>,
+    Query(q): Query<ListRunsQuery>,
+) -> Response {
+    list_runs(state, Some(installation_id), q).await
+}
+
+async fn list_runs(state: AppState, installation_id: Option<String>, q: ListRunsQuery) -> Response {
+    if q.cursor.as_deref().is_some_and(|c| run_seq(c).is_none()) {
+        return ApiError::BadRequest("invalid cursor").into_response();
+    }
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let filter = RunFilter {
+        installation_id,
+        status: q.status,
+        policy: q.policy,
+        requested_by: q.requested_by,
+        dry_run: q.dry_run,
+        since_ms: q.since_ms.map(u128::from),
+        until_ms: q.until_ms.map(u128::from),
+    };
+
+    match state.runs.list(&filter, q.cursor.as_deref(), limit).await {
+        Ok(page) => (StatusCode::OK, Json(page)).into_response(),
+        Err(e) => ApiError::Internal(e.to_string()).into_response(),
     }
-    ApiError::NotFound("run not found").into_response()
 }
 
 /* -------------------- POST /v1/runs/{run_id}/cancel -------------------- */
@@ -32,26 +76,18 @@ pub async fn post_cancel_run(
     State(state): State<AppState>,
     Path(run_id): Path<String>,
 ) -> Response {
-    let mut guard = state.runs.write().await;
-    if let Some(r) = guard.get_mut(&run_id) {
-        if matches!(
-            r.status,
-            RunStatus::Succeeded | RunStatus::Failed | RunStatus::Canceled
-        ) {
-            return ApiError::Conflict("run already finished").into_response();
-        }
-        r.cancel_requested = true;
-        if matches!(r.status, RunStatus::Starting | RunStatus::Running) {
-            r.status = RunStatus::Canceling;
-            r.updated_at_ms = now_ms();
+    match request_cancel(&state, &run_id, None).await {
+        Ok(Some(r)) if r.status.is_terminal() => {
+            ApiError::Conflict("run already finished").into_response()
         }
-        return (
+        Ok(Some(_)) => (
             StatusCode::ACCEPTED,
             Json(serde_json::json!({"run_id": run_id, "status":"canceling"})),
         )
-            .into_response();
+            .into_response(),
+        Ok(None) => ApiError::NotFound("run not found").into_response(),
+        Err(e) => ApiError::Internal(e.to_string()).into_response(),
     }
-    ApiError::NotFound("run not found").into_response()
 }
 
 /* -------------------- GET /api/evaluate --------------------
@@ -83,6 +119,7 @@ pub async fn get_evaluate_query(
         dry_run: q.dry_run,
         metadata: None,
         requested_by: Some("ui-evaluate".to_string()),
+        supersede: false,
     };
 
     match create_run_and_response(state.clone(), installation_id, start).await {
@@ -115,6 +152,7 @@ pub async fn post_start_run(
         "dry_run": body.dry_run,
         "metadata": body.metadata,
         "requested_by": body.requested_by,
+        "supersede": body.supersede,
     });
     let fp = IdempotencyStore::fingerprint_json(&body_json);
 
@@ -125,50 +163,26 @@ pub async fn post_start_run(
         let scoped = state.idempotency.scope_key(&installation_id, key);
 
         // Replay if we’ve seen this exact request
-        if let Some(rec) = state.idempotency.get(&scoped).await {
-            if rec.body_fingerprint != fp {
-                return ApiError::Conflict("Idempotency-Key reuse with different body")
-                    .into_response();
-            }
-            return (
-                StatusCode::OK,
-                [
-                    (LOCATION, format!("/v1/runs/{}", rec.run_id)),
-                    (
-                        axum::http::HeaderName::from_static("idempotency-replayed"),
-                        "true".into(),
-                    ),
-                ],
-                Json(rec.response),
-            )
-                .into_response();
+        let seen = match state.idempotency.get(&scoped).await {
+            Ok(seen) => seen,
+            Err(e) => return ApiError::Internal(e.to_string()).into_response(),
+        };
+        if let Some(rec) = seen {
+            return replay(rec, fp);
         }
 
-        // First time — create and cache
-        match create_run_and_response(state.clone(), installation_id, body).await {
-            Ok(created) => {
-                state
-                    .idempotency
-                    .put(
-                        scoped,
-                        IdemRecord {
-                            run_id: created.run_id.clone(),
-                            body_fingerprint: fp,
-                            response: created.response.clone(),
-                            created_at_ms: now_ms(),
-                        },
-                    )
-                    .await;
-
-                return (
-                    StatusCode::CREATED,
-                    [(LOCATION, format!("/v1/runs/{}", created.run_id))],
-                    Json(created.response),
-                )
-                    .into_response();
-            }
-            Err(e) => return e.into_response(),
-        }
+        // First time — the key is claimed in the same write as the run, so a
+        // racing duplicate either replays it or loses before anything is spawned
+        return match create_run(state.clone(), installation_id, body, Some((scoped, fp))).await {
+            Ok(Creation::Created(created)) => (
+                StatusCode::CREATED,
+                [(LOCATION, format!("/v1/runs/{}", created.run_id))],
+                Json(created.response),
+            )
+                .into_response(),
+            Ok(Creation::Existing(rec)) => replay(rec, fp),
+            Err(e) => e.into_response(),
+        };
     }
 
     // No Idempotency-Key — normal create
@@ -183,6 +197,25 @@ pub async fn post_start_run(
     }
 }
 
+/// Answer a request whose Idempotency-Key is already taken.
+fn replay(rec: IdemRecord, fp: u64) -> Response {
+    if rec.body_fingerprint != fp {
+        return ApiError::Conflict("Idempotency-Key reuse with different body").into_response();
+    }
+    (
+        StatusCode::OK,
+        [
+            (LOCATION, format!("/v1/runs/{}", rec.run_id)),
+            (
+                axum::http::HeaderName::from_static("idempotency-replayed"),
+                "true".into(),
+            ),
+        ],
+        Json(rec.response),
+    )
+        .into_response()
+}
+
 /* -------------------- shared create helper -------------------- */
 
 pub struct CreatedRun {
@@ -190,62 +223,105 @@ pub struct CreatedRun {
     pub response: serde_json::Value,
 }
 
+enum Creation {
+    Created(CreatedRun),
+    /// The idempotency key was claimed by another request first.
+    Existing(IdemRecord),
+}
+
 pub async fn create_run_and_response(
     state: AppState,
     installation_id: String,
     body: StartRunRequest,
 ) -> Result<CreatedRun, ApiError> {
+    match create_run(state, installation_id, body, None).await? {
+        Creation::Created(created) => Ok(created),
+        Creation::Existing(_) => unreachable!("no idempotency key was given"),
+    }
+}
+
+/// Create, persist and spawn a run. With `key` (scoped key, body fingerprint)
+/// the key is claimed atomically with the run insert; nothing is spawned if
+/// it was already taken.
+async fn create_run(
+    state: AppState,
+    installation_id: String,
+    body: StartRunRequest,
+    key: Option<(String, u64)>,
+) -> Result<Creation, ApiError> {
     let id_num = state.run_counter.fetch_add(1, Ordering::SeqCst);
-    let run_id = format!("r_{id_num:016x}");
+    let run_id = run_id_for(id_num);
     let now = now_ms();
 
     let eval = state
         .engine
         .evaluate(&installation_id, body.policy, body.dry_run);
 
-    {
-        let mut guard = state.runs.write().await;
-        guard.insert(
-            run_id.clone(),
-            RunRecord {
-                run_id: run_id.clone(),
-                installation_id: installation_id.clone(),
-                policy: body.policy,
-                level: eval.level,
-                // If your RunRecord still has an `actions` field, keep this line.
-                // If you removed it from the model, delete this line.
-                actions: Vec::new(),
-                dry_run: body.dry_run,
-                status: RunStatus::Starting,
-                started_at_ms: now,
-                updated_at_ms: now,
-                cancel_requested: false,
-            },
-        );
-    }
-
-    // Run in background
-    // NOTE: use your actual runner path and field name for the DAL.
-    // If your state field is `dal`, this is correct:
-    spawn_run(state.clone(), run_id.clone());
+    // Runs for one installation never overlap: either wait behind whatever is
+    // in flight, or cancel it and take over.
+    let admission = admit_run(&state, &installation_id, &run_id);
+    let (queued_behind, supersedes) = if body.supersede {
+        (None, admission.ahead.clone())
+    } else {
+        (admission.ahead.last().cloned(), Vec::new())
+    };
 
-    let resp = serde_json::json!({
+    let record = RunRecord {
+        run_id: run_id.clone(),
+        installation_id: installation_id.clone(),
+        policy: body.policy,
+        level: eval.level,
+        steps: Vec::new(),
+        dry_run: body.dry_run,
+        requested_by: body.requested_by.clone(),
+        status: RunStatus::Starting,
+        started_at_ms: now,
+        updated_at_ms: now,
+        failure_reason: None,
+        queued_behind: queued_behind.clone(),
+        supersedes: supersedes.clone(),
+        superseded_by: None,
+        cancel_requested: false,
+    };
+    let response = serde_json::json!({
         "run_id": run_id,
         "installation_id": installation_id,
         "status": "starting",
         "policy": eval.policy,
         "level": eval.level,
         "summary": eval.summary,
+        "queued_behind": queued_behind,
+        "supersedes": supersedes,
     });
 
-    let rid = resp
-        .get("run_id")
-        .and_then(|v| v.as_str())
-        .unwrap_or_default()
-        .to_string();
+    let inserted = match key {
+        Some((scoped, fp)) => {
+            let idem = IdemRecord {
+                run_id: run_id.clone(),
+                body_fingerprint: fp,
+                response: response.clone(),
+                created_at_ms: now,
+            };
+            state.runs.insert_keyed(record.clone(), scoped, idem).await
+        }
+        None => state.runs.insert(record.clone()).await.map(|()| None),
+    };
+    match inserted {
+        Ok(None) => {}
+        // dropping the admission withdraws the run from the queue
+        Ok(Some(existing)) => return Ok(Creation::Existing(existing)),
+        Err(e) => return Err(ApiError::Internal(e.to_string())),
+    }
+    state.events.status(&record);
+
+    for old in &supersedes {
+        if let Err(e) = request_cancel(&state, old, Some(run_id.clone())).await {
+            tracing::error!(run_id = %old, error = %e, "failed to supersede run");
+        }
+    }
+
+    // Run in background, after whatever is still ahead in the queue
+    admission.release();
 
-    Ok(CreatedRun {
-        run_id: rid,
-        response: resp,
-    })
+    Ok(Creation::Created(CreatedRun { run_id, response }))
 }
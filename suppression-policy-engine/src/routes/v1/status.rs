@@ -0,0 +1,26 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{error::ApiError, state::AppState};
+
+/* -------------------- GET /v1/installations/{installation_id}/status --------------------
+Live relay and input state read from the installation's device.
+------------------------------------------------------------------ */
+
+pub async fn get_status(
+    State(state): State<AppState>,
+    Path(installation_id): Path<String>,
+) -> Response {
+    match state
+        .device_abstraction_layer
+        .status(&installation_id)
+        .await
+    {
+        Ok(status) => (StatusCode::OK, Json(status)).into_response(),
+        Err(e) => ApiError::Driver(e).into_response(),
+    }
+}
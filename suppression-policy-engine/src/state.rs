@@ -3,42 +3,81 @@ use std::{
     sync::{atomic::AtomicU64, Arc},
 };
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
-use crate::enactor::InstallationEnactor;
+use crate::enactor::{InstallationEnactor, PlanBook};
+use crate::events::RunEvents;
+use crate::storage::{RunStore, Storage};
+use crate::suppression_policy_runner::RunQueue;
 use crate::{
     device_abstraction_layer::DeviceDriver, engine::Engine, idempotency::IdempotencyStore,
-    models::RunRecord, telemetry::TelemetrySink,
+    telemetry::TelemetrySink,
 };
 
 #[derive(Clone)]
 pub struct AppState {
     pub engine: Arc<Engine>,
-    pub runs: Arc<RwLock<HashMap<String, RunRecord>>>,
+    pub runs: Arc<dyn RunStore>,
+    /// Cancel tokens for runs that currently have a runner task, keyed by run_id.
+    pub cancellations: Arc<RwLock<HashMap<String, CancellationToken>>>,
     pub run_counter: Arc<AtomicU64>,
+    pub queue: Arc<RunQueue>,
+    pub events: RunEvents,
     pub idempotency: Arc<IdempotencyStore>,
     pub device_abstraction_layer: Arc<dyn DeviceDriver>,
     pub enactor: Arc<dyn InstallationEnactor>,
+    pub plans: Arc<PlanBook>,
     pub telemetry: Arc<dyn TelemetrySink>,
 }
 
 impl AppState {
-    pub fn new(
+    pub async fn new(
         engine: Arc<Engine>,
         device_abstraction_layer: Arc<dyn DeviceDriver>,
         telemetry: Arc<dyn TelemetrySink>,
-    ) -> Self {
+        storage: Storage,
+        plans: Arc<PlanBook>,
+    ) -> anyhow::Result<Self> {
         let enactor = Arc::new(crate::enactor::SimpleEnactor::new(
             device_abstraction_layer.clone(),
+            plans.clone(),
         )) as Arc<dyn InstallationEnactor>;
-        let idempotency = Arc::new(IdempotencyStore::new());
-        Self {
+        let idempotency = Arc::new(IdempotencyStore::new(storage.idempotency));
+        // resume numbering after the highest stored run so ids are never reused
+        let next_seq = storage.runs.max_seq().await? + 1;
+        Ok(Self {
             engine,
-            runs: Arc::new(RwLock::new(HashMap::new())),
-            run_counter: Arc::new(AtomicU64::new(1)),
+            runs: storage.runs,
+            cancellations: Arc::new(RwLock::new(HashMap::new())),
+            run_counter: Arc::new(AtomicU64::new(next_seq)),
+            queue: Arc::new(RunQueue::new()),
+            events: RunEvents::new(256),
             idempotency,
             device_abstraction_layer,
             enactor,
+            plans,
             telemetry,
-        }
+        })
+    }
+}
+
+#[cfg(test)]
+impl AppState {
+    /// In-memory state over `driver`, with the built-in rules and plans.
+    pub async fn for_tests(driver: Arc<dyn DeviceDriver>) -> Self {
+        let rules =
+            crate::policy::DecisionRules::parse(include_str!("../config/decision_rules.toml"))
+                .unwrap();
+        let plans = PlanBook::parse(include_str!("../config/plans.toml")).unwrap();
+        let storage = Storage::open(&crate::storage::StorageConfig::Memory).unwrap();
+        Self::new(
+            Arc::new(Engine::new(rules)),
+            driver,
+            Arc::new(crate::telemetry::NoopSink),
+            storage,
+            Arc::new(plans),
+        )
+        .await
+        .unwrap()
     }
 }
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+
+use super::{run_seq, IdempotencyBackend, RunFilter, RunPage, RunStore, RunUpdate};
+use crate::idempotency::IdemRecord;
+use crate::models::RunRecord;
+
+/// Process-local store. Everything is lost on restart.
+#[derive(Default)]
+pub struct MemoryStore {
+    runs: RwLock<HashMap<String, RunRecord>>,
+    idempotency: RwLock<HashMap<String, IdemRecord>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RunStore for MemoryStore {
+    async fn get(&self, run_id: &str) -> Result<Option<RunRecord>> {
+        Ok(self.runs.read().await.get(run_id).cloned())
+    }
+
+    async fn insert(&self, rec: RunRecord) -> Result<()> {
+        self.runs.write().await.insert(rec.run_id.clone(), rec);
+        Ok(())
+    }
+
+    async fn insert_keyed(
+        &self,
+        rec: RunRecord,
+        scoped_key: String,
+        idem: IdemRecord,
+    ) -> Result<Option<IdemRecord>> {
+        // hold the key lock across the run insert so a racing request sees
+        // either no key or a key whose run exists
+        let mut keys = self.idempotency.write().await;
+        if let Some(existing) = keys.get(&scoped_key) {
+            return Ok(Some(existing.clone()));
+        }
+        self.runs.write().await.insert(rec.run_id.clone(), rec);
+        keys.insert(scoped_key, idem);
+        Ok(None)
+    }
+
+    async fn update(&self, run_id: &str, f: RunUpdate) -> Result<Option<RunRecord>> {
+        let mut w = self.runs.write().await;
+        Ok(w.get_mut(run_id).map(|r| {
+            f(r);
+            r.clone()
+        }))
+    }
+
+    async fn list(
+        &self,
+        filter: &RunFilter,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<RunPage> {
+        let g = self.runs.read().await;
+        // compare seqs, not strings: a cursor like `r_5` or `r_A` is valid
+        // but doesn't sort against the fixed-width ids
+        let before = cursor.map(|c| run_seq(c).unwrap_or(0));
+        let mut rows: Vec<RunRecord> = g
+            .values()
+            .filter(|r| before.is_none_or(|b| run_seq(&r.run_id).is_some_and(|s| s < b)))
+            .filter(|r| filter.matches(r))
+            .cloned()
+            .collect();
+        rows.sort_by(|a, b| b.run_id.cmp(&a.run_id));
+        rows.truncate(limit + 1);
+        Ok(RunPage::from_rows(rows, limit))
+    }
+
+    async fn list_unfinished(&self) -> Result<Vec<RunRecord>> {
+        let g = self.runs.read().await;
+        let mut out: Vec<RunRecord> = g
+            .values()
+            .filter(|r| !r.status.is_terminal())
+            .cloned()
+            .collect();
+        out.sort_by(|a, b| a.run_id.cmp(&b.run_id));
+        Ok(out)
+    }
+
+    async fn max_seq(&self) -> Result<u64> {
+        let g = self.runs.read().await;
+        Ok(g.keys().filter_map(|k| run_seq(k)).max().unwrap_or(0))
+    }
+}
+
+#[async_trait]
+impl IdempotencyBackend for MemoryStore {
+    async fn get(&self, scoped_key: &str) -> Result<Option<IdemRecord>> {
+        Ok(self.idempotency.read().await.get(scoped_key).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Policy;
+    use crate::storage::run_id_for;
+
+    #[tokio::test]
+    async fn short_or_uppercase_cursors_page_by_seq() {
+        let store = MemoryStore::new();
+        for seq in 1..=12 {
+            let run = RunRecord::starting(&run_id_for(seq), "house-1", Policy::Defend);
+            store.insert(run).await.unwrap();
+        }
+        let filter = RunFilter::default();
+        for (cursor, first) in [("r_5", 4), ("r_A", 9), ("r_00a", 9)] {
+            let page = store.list(&filter, Some(cursor), 2).await.unwrap();
+            let seqs: Vec<_> = page.runs.iter().map(|r| run_seq(&r.run_id)).collect();
+            assert_eq!(seqs, [Some(first), Some(first - 1)], "cursor {cursor}");
+        }
+    }
+}
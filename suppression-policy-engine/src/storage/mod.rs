@@ -0,0 +1,175 @@
+//! Durable storage for run records and idempotency keys.
+//!
+//! Both the run table and the idempotency cache sit behind small async traits so
+//! the HTTP layer never cares where records live. `StorageConfig::from_env`
+//! picks the backend at startup.
+
+pub mod memory;
+pub mod sqlite;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::idempotency::IdemRecord;
+use crate::models::{RunRecord, RunStatus};
+use crate::policy::Policy;
+
+pub use memory::MemoryStore;
+pub use sqlite::SqliteStore;
+
+/// Mutation applied to a stored run under the store's write lock.
+pub type RunUpdate = Box<dyn FnOnce(&mut RunRecord) + Send>;
+
+#[async_trait]
+pub trait RunStore: Send + Sync {
+    async fn get(&self, run_id: &str) -> Result<Option<RunRecord>>;
+
+    async fn insert(&self, rec: RunRecord) -> Result<()>;
+
+    /// Insert `rec` and claim `scoped_key` for it in one atomic step.
+    /// If the key is already taken nothing is written and the record that
+    /// holds it is returned instead.
+    async fn insert_keyed(
+        &self,
+        rec: RunRecord,
+        scoped_key: String,
+        idem: IdemRecord,
+    ) -> Result<Option<IdemRecord>>;
+
+    /// Apply `f` to the stored record and persist it atomically.
+    /// Returns the updated record, or `None` if the run does not exist.
+    async fn update(&self, run_id: &str, f: RunUpdate) -> Result<Option<RunRecord>>;
+
+    /// Runs matching `filter`, newest first, starting strictly after `cursor`
+    /// (a run_id from a previous page).
+    async fn list(&self, filter: &RunFilter, cursor: Option<&str>, limit: usize)
+        -> Result<RunPage>;
+
+    /// Runs that never reached a terminal status (Starting, Running, Canceling).
+    async fn list_unfinished(&self) -> Result<Vec<RunRecord>>;
+
+    /// Highest run sequence number ever stored (0 when empty).
+    async fn max_seq(&self) -> Result<u64>;
+}
+
+/// Conjunctive filter over stored runs; `None` fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct RunFilter {
+    pub installation_id: Option<String>,
+    pub status: Option<RunStatus>,
+    pub policy: Option<Policy>,
+    pub requested_by: Option<String>,
+    pub dry_run: Option<bool>,
+    /// Inclusive lower bound on `started_at_ms`.
+    pub since_ms: Option<u128>,
+    /// Exclusive upper bound on `started_at_ms`.
+    pub until_ms: Option<u128>,
+}
+
+impl RunFilter {
+    pub fn matches(&self, r: &RunRecord) -> bool {
+        self.installation_id
+            .as_deref()
+            .is_none_or(|id| r.installation_id == id)
+            && self.status.is_none_or(|s| r.status == s)
+            && self.policy.is_none_or(|p| r.policy == p)
+            && self
+                .requested_by
+                .as_deref()
+                .is_none_or(|who| r.requested_by.as_deref() == Some(who))
+            && self.dry_run.is_none_or(|d| r.dry_run == d)
+            && self.since_ms.is_none_or(|t| r.started_at_ms >= t)
+            && self.until_ms.is_none_or(|t| r.started_at_ms < t)
+    }
+}
+
+/// One page of [`RunStore::list`] results.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunPage {
+    pub runs: Vec<RunRecord>,
+    /// Pass back as `cursor` to fetch the next page; `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl RunPage {
+    /// Build a page from up to `limit + 1` newest-first rows.
+    fn from_rows(mut rows: Vec<RunRecord>, limit: usize) -> Self {
+        let next_cursor = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|r| r.run_id.clone())
+        } else {
+            None
+        };
+        Self {
+            runs: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[async_trait]
+pub trait IdempotencyBackend: Send + Sync {
+    async fn get(&self, scoped_key: &str) -> Result<Option<IdemRecord>>;
+}
+
+/// Which backend to open.
+#[derive(Debug, Clone)]
+pub enum StorageConfig {
+    Memory,
+    Sqlite { path: String },
+}
+
+impl StorageConfig {
+    /// `RUN_STORE=memory|sqlite` (default `memory`), `RUN_STORE_PATH` for sqlite
+    /// (default `spe.sqlite3`).
+    pub fn from_env() -> Result<Self> {
+        let kind = std::env::var("RUN_STORE").unwrap_or_else(|_| "memory".to_string());
+        match kind.as_str() {
+            "memory" => Ok(Self::Memory),
+            "sqlite" => Ok(Self::Sqlite {
+                path: std::env::var("RUN_STORE_PATH").unwrap_or_else(|_| "spe.sqlite3".to_string()),
+            }),
+            other => anyhow::bail!("unknown RUN_STORE {other:?} (expected memory or sqlite)"),
+        }
+    }
+}
+
+/// Handles to the opened backend.
+#[derive(Clone)]
+pub struct Storage {
+    pub runs: Arc<dyn RunStore>,
+    pub idempotency: Arc<dyn IdempotencyBackend>,
+}
+
+impl Storage {
+    pub fn open(cfg: &StorageConfig) -> Result<Self> {
+        match cfg {
+            StorageConfig::Memory => {
+                let store = Arc::new(MemoryStore::new());
+                Ok(Self {
+                    runs: store.clone(),
+                    idempotency: store,
+                })
+            }
+            StorageConfig::Sqlite { path } => {
+                let store = Arc::new(SqliteStore::open(path)?);
+                Ok(Self {
+                    runs: store.clone(),
+                    idempotency: store,
+                })
+            }
+        }
+    }
+}
+
+/// Format a run sequence number as a run_id.
+pub fn run_id_for(seq: u64) -> String {
+    format!("r_{seq:016x}")
+}
+
+/// Inverse of [`run_id_for`].
+pub fn run_seq(run_id: &str) -> Option<u64> {
+    u64::from_str_radix(run_id.strip_prefix("r_")?, 16).ok()
+}
@@ -0,0 +1,364 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use std::sync::{Arc, Mutex};
+
+use super::{run_seq, IdempotencyBackend, RunFilter, RunPage, RunStore, RunUpdate};
+use crate::idempotency::IdemRecord;
+use crate::models::RunRecord;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    run_id           TEXT PRIMARY KEY,
+    seq              INTEGER NOT NULL,
+    installation_id  TEXT NOT NULL,
+    status           TEXT NOT NULL,
+    started_at_ms    INTEGER NOT NULL,
+    cancel_requested INTEGER NOT NULL DEFAULT 0,
+    record           TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_installation ON runs (installation_id, seq);
+CREATE TABLE IF NOT EXISTS idempotency (
+    scoped_key TEXT PRIMARY KEY,
+    record     TEXT NOT NULL
+);
+";
+
+/// SQLite-backed store. Records are kept as JSON next to the few columns we
+/// index on; calls run on the blocking pool.
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("opening sqlite {path}"))?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")?;
+        conn.execute_batch(SCHEMA)
+            .context("creating sqlite schema")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    async fn with_conn<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut guard = conn
+                .lock()
+                .map_err(|_| anyhow::anyhow!("sqlite connection poisoned"))?;
+            f(&mut guard)
+        })
+        .await?
+    }
+}
+
+fn load_run(record: String, cancel_requested: bool) -> Result<RunRecord> {
+    let mut r: RunRecord = serde_json::from_str(&record).context("parsing stored run")?;
+    r.cancel_requested = cancel_requested;
+    Ok(r)
+}
+
+fn save_run(conn: &Connection, r: &RunRecord) -> Result<()> {
+    let seq = run_seq(&r.run_id).context("run_id is not r_<hex>")?;
+    conn.execute(
+        "INSERT INTO runs (run_id, seq, installation_id, status, started_at_ms, cancel_requested, record)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (run_id) DO UPDATE SET
+             status = excluded.status,
+             cancel_requested = excluded.cancel_requested,
+             record = excluded.record",
+        params![
+            r.run_id,
+            seq as i64,
+            r.installation_id,
+            variant_str(r.status)?,
+            r.started_at_ms as i64,
+            r.cancel_requested,
+            serde_json::to_string(r)?,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Snake-case serde name of a unit enum variant (matches what is stored).
+fn variant_str<T: serde::Serialize>(v: T) -> Result<String> {
+    Ok(serde_json::to_value(v)?
+        .as_str()
+        .unwrap_or_default()
+        .to_string())
+}
+
+fn get_run(conn: &Connection, run_id: &str) -> Result<Option<RunRecord>> {
+    let row: Option<(String, bool)> = conn
+        .query_row(
+            "SELECT record, cancel_requested FROM runs WHERE run_id = ?1",
+            params![run_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    row.map(|(record, cancel)| load_run(record, cancel))
+        .transpose()
+}
+
+fn get_idem(conn: &Connection, scoped_key: &str) -> Result<Option<IdemRecord>> {
+    let rec: Option<String> = conn
+        .query_row(
+            "SELECT record FROM idempotency WHERE scoped_key = ?1",
+            params![scoped_key],
+            |row| row.get(0),
+        )
+        .optional()?;
+    rec.map(|s| serde_json::from_str(&s).context("parsing stored idempotency record"))
+        .transpose()
+}
+
+#[async_trait]
+impl RunStore for SqliteStore {
+    async fn get(&self, run_id: &str) -> Result<Option<RunRecord>> {
+        let run_id = run_id.to_string();
+        self.with_conn(move |c| get_run(c, &run_id)).await
+    }
+
+    async fn insert(&self, rec: RunRecord) -> Result<()> {
+        self.with_conn(move |c| save_run(c, &rec)).await
+    }
+
+    async fn insert_keyed(
+        &self,
+        rec: RunRecord,
+        scoped_key: String,
+        idem: IdemRecord,
+    ) -> Result<Option<IdemRecord>> {
+        self.with_conn(move |c| {
+            let tx = c.transaction()?;
+            let claimed = tx.execute(
+                "INSERT INTO idempotency (scoped_key, record) VALUES (?1, ?2)
+                 ON CONFLICT (scoped_key) DO NOTHING",
+                params![scoped_key, serde_json::to_string(&idem)?],
+            )?;
+            if claimed == 0 {
+                return get_idem(&tx, &scoped_key);
+            }
+            save_run(&tx, &rec)?;
+            tx.commit()?;
+            Ok(None)
+        })
+        .await
+    }
+
+    async fn update(&self, run_id: &str, f: RunUpdate) -> Result<Option<RunRecord>> {
+        let run_id = run_id.to_string();
+        self.with_conn(move |c| {
+            let tx = c.transaction()?;
+            let Some(mut r) = get_run(&tx, &run_id)? else {
+                return Ok(None);
+            };
+            f(&mut r);
+            save_run(&tx, &r)?;
+            tx.commit()?;
+            Ok(Some(r))
+        })
+        .await
+    }
+
+    async fn list(
+        &self,
+        filter: &RunFilter,
+        cursor: Option<&str>,
+        limit: usize,
+    ) -> Result<RunPage> {
+        let mut sql = String::from("SELECT record, cancel_requested FROM runs WHERE 1 = 1");
+        let mut args: Vec<Value> = Vec::new();
+        if let Some(c) = cursor {
+            sql.push_str(" AND seq < ?");
+            args.push(Value::Integer(run_seq(c).unwrap_or(0) as i64));
+        }
+        if let Some(id) = &filter.installation_id {
+            sql.push_str(" AND installation_id = ?");
+            args.push(Value::Text(id.clone()));
+        }
+        if let Some(status) = filter.status {
+            sql.push_str(" AND status = ?");
+            args.push(Value::Text(variant_str(status)?));
+        }
+        if let Some(policy) = filter.policy {
+            sql.push_str(" AND json_extract(record, '$.policy') = ?");
+            args.push(Value::Text(variant_str(policy)?));
+        }
+        if let Some(who) = &filter.requested_by {
+            sql.push_str(" AND json_extract(record, '$.requested_by') = ?");
+            args.push(Value::Text(who.clone()));
+        }
+        if let Some(dry_run) = filter.dry_run {
+            sql.push_str(" AND json_extract(record, '$.dry_run') = ?");
+            args.push(Value::Integer(dry_run as i64));
+        }
+        if let Some(t) = filter.since_ms {
+            sql.push_str(" AND started_at_ms >= ?");
+            args.push(Value::Integer(t as i64));
+        }
+        if let Some(t) = filter.until_ms {
+            sql.push_str(" AND started_at_ms < ?");
+            args.push(Value::Integer(t as i64));
+        }
+        sql.push_str(" ORDER BY seq DESC LIMIT ?");
+        args.push(Value::Integer(limit as i64 + 1));
+
+        self.with_conn(move |c| {
+            let mut stmt = c.prepare(&sql)?;
+            let rows =
+                stmt.query_map(params_from_iter(args), |row| Ok((row.get(0)?, row.get(1)?)))?;
+            let mut out = Vec::new();
+            for row in rows {
+                let (record, cancel) = row?;
+                out.push(load_run(record, cancel)?);
+            }
+            Ok(RunPage::from_rows(out, limit))
+        })
+        .await
+    }
+
+    async fn list_unfinished(&self) -> Result<Vec<RunRecord>> {
+        self.with_conn(|c| {
+            let mut stmt = c.prepare(
+                "SELECT record, cancel_requested FROM runs
+                 WHERE status IN ('starting', 'running', 'canceling')
+                 ORDER BY seq",
+            )?;
+            let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            let mut out = Vec::new();
+            for row in rows {
+                let (record, cancel) = row?;
+                out.push(load_run(record, cancel)?);
+            }
+            Ok(out)
+        })
+        .await
+    }
+
+    async fn max_seq(&self) -> Result<u64> {
+        self.with_conn(|c| {
+            let max: Option<i64> =
+                c.query_row("SELECT MAX(seq) FROM runs", [], |row| row.get(0))?;
+            Ok(max.unwrap_or(0) as u64)
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl IdempotencyBackend for SqliteStore {
+    async fn get(&self, scoped_key: &str) -> Result<Option<IdemRecord>> {
+        let key = scoped_key.to_string();
+        self.with_conn(move |c| get_idem(c, &key)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RunStatus;
+    use crate::policy::Policy;
+    use crate::storage::run_id_for;
+
+    fn temp_db(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "spe-{name}-{}-{}.sqlite3",
+            std::process::id(),
+            crate::time::now_ms()
+        ));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn run(seq: u64) -> RunRecord {
+        RunRecord::starting(&run_id_for(seq), "house-1", Policy::Defend)
+    }
+
+    fn idem(run_id: &str, fp: u64) -> IdemRecord {
+        IdemRecord {
+            run_id: run_id.into(),
+            body_fingerprint: fp,
+            response: serde_json::json!({ "run_id": run_id }),
+            created_at_ms: 1_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn runs_and_keys_survive_reopen_and_seq_resumes() {
+        let path = temp_db("reopen");
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.insert(run(7)).await.unwrap();
+            let claimed = store
+                .insert_keyed(run(9), "house-1::k".into(), idem(&run_id_for(9), 1))
+                .await
+                .unwrap();
+            assert!(claimed.is_none());
+            store
+                .update(
+                    &run_id_for(7),
+                    Box::new(|r| {
+                        r.status = RunStatus::Canceling;
+                        r.cancel_requested = true;
+                    }),
+                )
+                .await
+                .unwrap();
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.max_seq().await.unwrap(), 9);
+        let r = RunStore::get(&store, &run_id_for(7))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.status, RunStatus::Canceling);
+        assert!(r.cancel_requested);
+        let unfinished: Vec<_> = store
+            .list_unfinished()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.run_id)
+            .collect();
+        assert_eq!(unfinished, [run_id_for(7), run_id_for(9)]);
+        let key = IdempotencyBackend::get(&store, "house-1::k").await.unwrap();
+        assert_eq!(key.unwrap().run_id, run_id_for(9));
+
+        drop(store);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{path}{suffix}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn taken_key_returns_the_holder_and_writes_nothing() {
+        let store = SqliteStore::open(":memory:").unwrap();
+        assert!(store
+            .insert_keyed(run(1), "house-1::k".into(), idem(&run_id_for(1), 1))
+            .await
+            .unwrap()
+            .is_none());
+
+        // same key again, whatever the body: the first holder comes back and
+        // the second run is never stored
+        let holder = store
+            .insert_keyed(run(2), "house-1::k".into(), idem(&run_id_for(2), 2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(holder.run_id, run_id_for(1));
+        assert_eq!(holder.body_fingerprint, 1);
+        assert!(RunStore::get(&store, &run_id_for(2))
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(store.max_seq().await.unwrap(), 1);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod queue;
+pub mod recovery;
 pub mod runner;
 
-pub use runner::spawn_run;
+pub use queue::RunQueue;
+pub use recovery::{reconcile, restore_current_policies, RecoveryPolicy};
+pub use runner::{admit_run, request_cancel};
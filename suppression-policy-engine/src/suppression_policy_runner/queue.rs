@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::{mpsc, oneshot};
+
+/// Per-installation FIFO of runs. Each installation gets one worker task, so
+/// two runs never drive the same relays at the same time.
+#[derive(Default)]
+pub struct RunQueue {
+    lanes: Mutex<HashMap<String, Lane>>,
+}
+
+struct Lane {
+    tx: Option<mpsc::UnboundedSender<Queued>>,
+    /// Admitted runs that have not finished yet, oldest first.
+    in_flight: Vec<String>,
+}
+
+/// A run as its installation's worker sees it.
+pub struct Queued {
+    pub run_id: String,
+    /// Resolves once the run is released; errors if it was withdrawn.
+    pub ready: oneshot::Receiver<()>,
+}
+
+/// A reserved place in an installation's lane.
+pub struct Admission {
+    /// Runs already ahead of this one, oldest first.
+    pub ahead: Vec<String>,
+    ready: oneshot::Sender<()>,
+}
+
+impl Admission {
+    /// Let the run execute once everything ahead of it has finished. Call
+    /// after its record is stored; dropping the admission withdraws the run.
+    pub fn release(self) {
+        // the worker going away only happens at shutdown
+        let _ = self.ready.send(());
+    }
+}
+
+impl RunQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve a place for `run_id` and hand it to the installation's worker,
+    /// in one step so runs always execute in admission order. Also returns a
+    /// receiver when the lane has no worker yet; the caller must start one
+    /// draining it.
+    pub fn admit(
+        &self,
+        installation_id: &str,
+        run_id: &str,
+    ) -> (Admission, Option<mpsc::UnboundedReceiver<Queued>>) {
+        let mut lanes = self.lanes.lock().unwrap_or_else(|e| e.into_inner());
+        let lane = lanes
+            .entry(installation_id.to_string())
+            .or_insert_with(|| Lane {
+                tx: None,
+                in_flight: Vec::new(),
+            });
+        let ahead = lane.in_flight.clone();
+        lane.in_flight.push(run_id.to_string());
+
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let admission = Admission {
+            ahead,
+            ready: ready_tx,
+        };
+        let mut queued = Queued {
+            run_id: run_id.to_string(),
+            ready: ready_rx,
+        };
+        if let Some(tx) = &lane.tx {
+            match tx.send(queued) {
+                Ok(()) => return (admission, None),
+                Err(mpsc::error::SendError(q)) => queued = q,
+            }
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        // a fresh channel always has its receiver alive
+        let _ = tx.send(queued);
+        lane.tx = Some(tx);
+        (admission, Some(rx))
+    }
+
+    /// Forget a run once it is finished (or was withdrawn).
+    pub fn done(&self, installation_id: &str, run_id: &str) {
+        let mut lanes = self.lanes.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(lane) = lanes.get_mut(installation_id) {
+            lane.in_flight.retain(|id| id != run_id);
+        }
+    }
+}
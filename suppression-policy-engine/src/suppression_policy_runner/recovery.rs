@@ -0,0 +1,286 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use tracing::{error, info, warn};
+
+use super::admit_run;
+use crate::models::{RunRecord, RunStatus};
+use crate::policy::Policy;
+use crate::state::AppState;
+use crate::storage::RunFilter;
+use crate::telemetry::sink::TelemetryEvent;
+use crate::time::now_ms;
+
+/// Which installations may have interrupted runs re-driven after a restart.
+///
+/// Re-driving replays the whole plan from the first step; that is only safe
+/// where every command sets absolute relay state, so it is opt-in per site.
+#[derive(Debug, Clone, Default)]
+pub struct RecoveryPolicy {
+    resume_all: bool,
+    resumable: HashSet<String>,
+}
+
+impl RecoveryPolicy {
+    /// `RECOVERY_RESUME_INSTALLATIONS`: comma-separated installation ids, or `*`.
+    /// Unset means every interrupted run is failed.
+    pub fn from_env() -> Self {
+        let raw = std::env::var("RECOVERY_RESUME_INSTALLATIONS").unwrap_or_default();
+        let mut policy = Self::default();
+        for id in raw.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            if id == "*" {
+                policy.resume_all = true;
+            } else {
+                policy.resumable.insert(id.to_string());
+            }
+        }
+        policy
+    }
+
+    pub fn can_resume(&self, installation_id: &str) -> bool {
+        self.resume_all || self.resumable.contains(installation_id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecoveryAction {
+    /// Marked `Failed` with reason `interrupted`.
+    Failed,
+    /// A cancel was already pending; landed in `Canceled`.
+    Canceled,
+    /// Reset to `Starting` and handed back to the runner.
+    Resumed,
+}
+
+/// Reconcile runs orphaned by a previous process. Call once at startup,
+/// before serving traffic.
+pub async fn reconcile(state: &AppState, policy: &RecoveryPolicy) -> anyhow::Result<usize> {
+    let orphans = state.runs.list_unfinished().await?;
+    if orphans.is_empty() {
+        return Ok(0);
+    }
+    warn!(
+        count = orphans.len(),
+        "reconciling runs interrupted by restart"
+    );
+
+    for run in &orphans {
+        let action = if run.cancel_requested || run.status == RunStatus::Canceling {
+            RecoveryAction::Canceled
+        } else if policy.can_resume(&run.installation_id) {
+            RecoveryAction::Resumed
+        } else {
+            RecoveryAction::Failed
+        };
+
+        let updated = state
+            .runs
+            .update(
+                &run.run_id,
+                Box::new(move |r| {
+                    match action {
+                        RecoveryAction::Failed => {
+                            r.status = RunStatus::Failed;
+                            r.failure_reason = Some("interrupted".into());
+                        }
+                        RecoveryAction::Canceled => r.status = RunStatus::Canceled,
+                        RecoveryAction::Resumed => {
+                            // the plan replays from the first step, so the
+                            // interrupted attempt's steps no longer apply
+                            r.status = RunStatus::Starting;
+                            r.steps.clear();
+                            r.failure_reason = None;
+                        }
+                    }
+                    r.updated_at_ms = now_ms();
+                }),
+            )
+            .await;
+        match updated {
+            Ok(Some(r)) => state.events.status(&r),
+            Ok(None) => continue,
+            Err(e) => {
+                error!(run_id = %run.run_id, error = %e, "failed to reconcile run");
+                continue;
+            }
+        }
+
+        info!(run_id = %run.run_id, previous = ?run.status, ?action, "run reconciled");
+        emit(state, run, action).await;
+
+        if matches!(action, RecoveryAction::Resumed) {
+            admit_run(state, &run.installation_id, &run.run_id).release();
+        }
+    }
+
+    Ok(orphans.len())
+}
+
+async fn emit(state: &AppState, run: &RunRecord, action: RecoveryAction) {
+    state
+        .telemetry
+        .send(TelemetryEvent {
+            installation_id: &run.installation_id,
+            run_id: Some(&run.run_id),
+            kind: "run_reconciled",
+            data: serde_json::json!({
+                "previous_status": run.status,
+                "action": action,
+                "policy": run.policy,
+            }),
+        })
+        .await;
+}
+
+/// Rebuild the engine's current policy per installation from stored
+/// succeeded non-dry-run runs, so transition limits survive a restart. The
+/// policy's `since_ms` is the start of the oldest run in the newest unbroken streak
+/// of that policy. Returns how many installations were restored.
+pub async fn restore_current_policies(state: &AppState) -> anyhow::Result<usize> {
+    let filter = RunFilter {
+        status: Some(RunStatus::Succeeded),
+        dry_run: Some(false),
+        ..Default::default()
+    };
+    // installation → (policy, since_ms, streak still unbroken)
+    let mut current: HashMap<String, (Policy, u128, bool)> = HashMap::new();
+    let mut cursor = None;
+    loop {
+        let page = state.runs.list(&filter, cursor.as_deref(), 200).await?;
+        for run in &page.runs {
+            match current.get_mut(&run.installation_id) {
+                None => {
+                    current.insert(
+                        run.installation_id.clone(),
+                        (run.policy, run.started_at_ms, true),
+                    );
+                }
+                Some((policy, since, open)) if *open => {
+                    if *policy == run.policy {
+                        *since = run.started_at_ms;
+                    } else {
+                        *open = false;
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        match page.next_cursor {
+            Some(c) => cursor = Some(c),
+            None => break,
+        }
+    }
+
+    for (id, (policy, since, _)) in &current {
+        state.engine.record(id, *policy, *since);
+    }
+    Ok(current.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::drivers::{MockDriver, Stalled};
+    use crate::device_abstraction_layer::Command;
+    use crate::enactor::EnactStep;
+    use crate::events::RunEvent;
+    use std::sync::Arc;
+
+    fn stored(run_id: &str, installation_id: &str, status: RunStatus) -> RunRecord {
+        let mut r = RunRecord::starting(run_id, installation_id, Policy::Defend);
+        r.status = status;
+        r.steps.push(EnactStep {
+            command: Command::ArmSensors,
+            ok: true,
+            message: "ok".into(),
+            started_at_ms: 1_000,
+            finished_at_ms: 1_001,
+            response: None,
+            retries: 0,
+            error_kind: None,
+        });
+        r
+    }
+
+    #[tokio::test]
+    async fn reconcile_fails_cancels_or_resumes_orphans() {
+        let state = AppState::for_tests(Arc::new(Stalled)).await;
+        let mut canceling = stored("r_02", "cabin", RunStatus::Running);
+        canceling.cancel_requested = true;
+        for r in [
+            stored("r_01", "cabin", RunStatus::Running),
+            canceling,
+            stored("r_03", "house", RunStatus::Running),
+            stored("r_04", "house", RunStatus::Succeeded),
+        ] {
+            state.runs.insert(r).await.unwrap();
+        }
+        let mut events = state.events.subscribe();
+
+        let policy = RecoveryPolicy {
+            resumable: HashSet::from(["house".to_string()]),
+            ..Default::default()
+        };
+        assert_eq!(reconcile(&state, &policy).await.unwrap(), 3);
+
+        let failed = state.runs.get("r_01").await.unwrap().unwrap();
+        assert_eq!(failed.status, RunStatus::Failed);
+        assert_eq!(failed.failure_reason.as_deref(), Some("interrupted"));
+        let canceled = state.runs.get("r_02").await.unwrap().unwrap();
+        assert_eq!(canceled.status, RunStatus::Canceled);
+        let untouched = state.runs.get("r_04").await.unwrap().unwrap();
+        assert_eq!(untouched.status, RunStatus::Succeeded);
+
+        // the resumed run is re-driven from the first step, without the
+        // interrupted attempt's steps
+        loop {
+            if let RunEvent::Status {
+                run_id,
+                status: RunStatus::Running,
+                ..
+            } = events.recv().await.unwrap()
+            {
+                if run_id == "r_03" {
+                    break;
+                }
+            }
+        }
+        let resumed = state.runs.get("r_03").await.unwrap().unwrap();
+        assert_eq!(resumed.status, RunStatus::Running);
+        assert!(resumed.steps.is_empty());
+        assert!(resumed.failure_reason.is_none());
+    }
+
+    #[tokio::test]
+    async fn current_policy_comes_from_the_latest_succeeded_streak() {
+        let state = AppState::for_tests(Arc::new(MockDriver)).await;
+        let run = |seq: u64, id: &str, policy, status, started_at_ms| {
+            let mut r = RunRecord::starting(&format!("r_{seq:016x}"), id, policy);
+            r.status = status;
+            r.started_at_ms = started_at_ms;
+            r
+        };
+        for r in [
+            run(1, "house", Policy::Defend, RunStatus::Succeeded, 1_000),
+            run(2, "house", Policy::Defend, RunStatus::Succeeded, 2_000),
+            // never reached the device
+            run(3, "house", Policy::Suppress, RunStatus::Failed, 3_000),
+            run(4, "house", Policy::Suppress, RunStatus::Canceled, 4_000),
+            run(5, "cabin", Policy::Observe, RunStatus::Succeeded, 1_000),
+            run(6, "cabin", Policy::Prepare, RunStatus::Succeeded, 5_000),
+            {
+                let mut dry = run(7, "cabin", Policy::Suppress, RunStatus::Succeeded, 6_000);
+                dry.dry_run = true;
+                dry
+            },
+        ] {
+            state.runs.insert(r).await.unwrap();
+        }
+
+        assert_eq!(restore_current_policies(&state).await.unwrap(), 2);
+        let house = state.engine.current("house").unwrap();
+        assert_eq!((house.policy, house.since_ms), (Policy::Defend, 1_000));
+        let cabin = state.engine.current("cabin").unwrap();
+        assert_eq!((cabin.policy, cabin.since_ms), (Policy::Prepare, 5_000));
+    }
+}
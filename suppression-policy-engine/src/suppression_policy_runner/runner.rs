@@ -1,53 +1,357 @@
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 
+use super::queue::{Admission, Queued};
+use crate::enactor::EnactStep;
 use crate::state::AppState;
-use crate::{models::RunStatus, time::now_ms};
+use crate::{
+    models::{RunRecord, RunStatus},
+    time::now_ms,
+};
 
-pub fn spawn_run(state: AppState, run_id: String) {
+/// Reserve the next place in `installation_id`'s queue for `run_id`, starting
+/// the installation's worker if it has none. Runs for one installation execute
+/// strictly one at a time, in admission order; each waits until its
+/// [`Admission`] is released.
+pub fn admit_run(state: &AppState, installation_id: &str, run_id: &str) -> Admission {
+    let (admission, worker) = state.queue.admit(installation_id, run_id);
+    if let Some(rx) = worker {
+        spawn_worker(state.clone(), installation_id.to_string(), rx);
+    }
+    admission
+}
+
+fn spawn_worker(state: AppState, installation_id: String, mut rx: UnboundedReceiver<Queued>) {
     tokio::spawn(async move {
-        // load run
-        let (installation_id, policy, dry_run) = {
-            let g = state.runs.read().await;
-            if let Some(r) = g.get(&run_id) {
-                (r.installation_id.clone(), r.policy, r.dry_run)
-            } else {
-                return;
+        while let Some(Queued { run_id, ready }) = rx.recv().await {
+            if ready.await.is_err() {
+                // withdrawn before its record was stored
+                state.queue.done(&installation_id, &run_id);
+                continue;
             }
-        };
+            // register the cancel token before reading the record so a cancel
+            // that lands in between is never lost
+            let cancel = CancellationToken::new();
+            state
+                .cancellations
+                .write()
+                .await
+                .insert(run_id.clone(), cancel.clone());
 
-        {
-            let mut w = state.runs.write().await;
-            if let Some(r) = w.get_mut(&run_id) {
-                r.status = RunStatus::Running;
-                r.updated_at_ms = now_ms();
-            }
+            run(&state, &run_id, cancel).await;
+
+            state.cancellations.write().await.remove(&run_id);
+            state.queue.done(&installation_id, &run_id);
         }
+    });
+}
 
-        match state.enactor.enact(&installation_id, policy, dry_run).await {
-            Ok(report) if report.ok => {
-                info!(%run_id, ?policy, "run succeeded");
-                let mut w = state.runs.write().await;
-                if let Some(r) = w.get_mut(&run_id) {
-                    r.status = RunStatus::Succeeded;
+/// Ask a run to stop: flag it, flip it to `Canceling` and wake its runner if it
+/// has one. Queued runs see the flag when they reach the front and cancel
+/// without running any step. `superseded_by` records the run that replaced it.
+///
+/// Returns the updated record; a terminal status means it was already finished.
+pub async fn request_cancel(
+    state: &AppState,
+    run_id: &str,
+    superseded_by: Option<String>,
+) -> anyhow::Result<Option<RunRecord>> {
+    let updated = state
+        .runs
+        .update(
+            run_id,
+            Box::new(move |r| {
+                if r.status.is_terminal() {
+                    return;
+                }
+                r.cancel_requested = true;
+                if superseded_by.is_some() {
+                    r.superseded_by = superseded_by;
+                }
+                if matches!(r.status, RunStatus::Starting | RunStatus::Running) {
+                    r.status = RunStatus::Canceling;
                     r.updated_at_ms = now_ms();
                 }
+            }),
+        )
+        .await?;
+
+    if let Some(r) = updated.as_ref().filter(|r| !r.status.is_terminal()) {
+        state.events.status(r);
+        // Wake the runner; it lands the run in `Canceled` once the enactor stops.
+        if let Some(token) = state.cancellations.read().await.get(run_id) {
+            token.cancel();
+        }
+    }
+    Ok(updated)
+}
+
+async fn run(state: &AppState, run_id: &str, cancel: CancellationToken) {
+    // load run
+    let (installation_id, policy, dry_run, started_at_ms) = match state.runs.get(run_id).await {
+        Ok(Some(r)) => {
+            if r.cancel_requested {
+                cancel.cancel();
             }
-            Ok(report) => {
-                error!(%run_id, ?policy, steps=?report.steps, "run failed");
-                let mut w = state.runs.write().await;
-                if let Some(r) = w.get_mut(&run_id) {
-                    r.status = RunStatus::Failed;
-                    r.updated_at_ms = now_ms();
+            (r.installation_id, r.policy, r.dry_run, r.started_at_ms)
+        }
+        Ok(None) => return,
+        Err(e) => {
+            error!(%run_id, error=%e, "failed to load run");
+            return;
+        }
+    };
+
+    if cancel.is_cancelled() {
+        info!(%run_id, "run canceled before start");
+        finish(state, run_id, RunStatus::Canceled, None, None).await;
+        return;
+    }
+
+    let started = state
+        .runs
+        .update(
+            run_id,
+            Box::new(|r| {
+                if !matches!(r.status, RunStatus::Canceling) {
+                    r.status = RunStatus::Running;
                 }
+                r.updated_at_ms = now_ms();
+            }),
+        )
+        .await;
+    match started {
+        Ok(Some(r)) => state.events.status(&r),
+        Ok(None) => {}
+        Err(e) => error!(%run_id, error=%e, "failed to mark run running"),
+    }
+
+    // Persist each step as soon as the enactor reports it
+    let (tx, mut rx) = mpsc::unbounded_channel::<EnactStep>();
+    let record_steps = async {
+        while let Some(step) = rx.recv().await {
+            let stored = step.clone();
+            let appended = state
+                .runs
+                .update(
+                    run_id,
+                    Box::new(move |r| {
+                        r.steps.push(stored);
+                        r.updated_at_ms = now_ms();
+                    }),
+                )
+                .await;
+            match appended {
+                Ok(Some(r)) => state.events.step(&r, step),
+                Ok(None) => {}
+                Err(e) => error!(%run_id, error=%e, "failed to persist run step"),
             }
-            Err(e) => {
-                error!(%run_id, error=%e, "enactor error");
-                let mut w = state.runs.write().await;
-                if let Some(r) = w.get_mut(&run_id) {
-                    r.status = RunStatus::Failed;
-                    r.updated_at_ms = now_ms();
+        }
+    };
+    let (result, ()) = tokio::join!(
+        state
+            .enactor
+            .enact(&installation_id, policy, dry_run, cancel, tx),
+        record_steps
+    );
+
+    match result {
+        Ok(report) if report.canceled => {
+            info!(%run_id, ?policy, steps=?report.steps, "run canceled");
+            finish(state, run_id, RunStatus::Canceled, Some(report.steps), None).await;
+        }
+        Ok(report) if report.ok => {
+            info!(%run_id, ?policy, "run succeeded");
+            // only a policy that actually reached the device is in force
+            if !dry_run {
+                state.engine.record(&installation_id, policy, started_at_ms);
+            }
+            finish(
+                state,
+                run_id,
+                RunStatus::Succeeded,
+                Some(report.steps),
+                None,
+            )
+            .await;
+        }
+        Ok(report) => {
+            error!(%run_id, ?policy, failure=?report.failure, steps=?report.steps, "run failed");
+            // a step the driver ran but reported not ok carries no error class
+            let reason = report.failure.map_or("step_failed", |k| k.as_str());
+            finish(
+                state,
+                run_id,
+                RunStatus::Failed,
+                Some(report.steps),
+                Some(reason.to_string()),
+            )
+            .await;
+        }
+        Err(e) => {
+            error!(%run_id, error=%e, "enactor error");
+            finish(
+                state,
+                run_id,
+                RunStatus::Failed,
+                None,
+                Some("enactor_error".into()),
+            )
+            .await;
+        }
+    }
+}
+
+/// Land the run in a terminal status. `steps`, when given, is the enactor's
+/// final report and replaces whatever was appended live. `failure_reason`
+/// is recorded for failed runs.
+async fn finish(
+    state: &AppState,
+    run_id: &str,
+    status: RunStatus,
+    steps: Option<Vec<EnactStep>>,
+    failure_reason: Option<String>,
+) {
+    let finished = state
+        .runs
+        .update(
+            run_id,
+            Box::new(move |r| {
+                r.status = status;
+                if let Some(steps) = steps {
+                    r.steps = steps;
                 }
+                if failure_reason.is_some() {
+                    r.failure_reason = failure_reason;
+                }
+                r.updated_at_ms = now_ms();
+            }),
+        )
+        .await;
+    match finished {
+        Ok(Some(r)) => state.events.status(&r),
+        Ok(None) => {}
+        Err(e) => error!(%run_id, ?status, error=%e, "failed to persist run result"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_abstraction_layer::drivers::{MockDriver, Refusing};
+    use crate::events::RunEvent;
+    use crate::models::StartRunRequest;
+    use crate::policy::Policy;
+    use crate::routes::v1::runs::create_run_and_response;
+    use std::sync::Arc;
+    use tokio::sync::broadcast;
+
+    /// Run ids in the order they started running, once `n` runs have finished.
+    async fn started_order(events: &mut broadcast::Receiver<RunEvent>, n: usize) -> Vec<String> {
+        let mut started = Vec::new();
+        let mut finished = 0;
+        while finished < n {
+            match events.recv().await.unwrap() {
+                RunEvent::Status {
+                    run_id,
+                    status: RunStatus::Running,
+                    ..
+                } => started.push(run_id),
+                e if e.is_terminal() => finished += 1,
+                _ => {}
             }
         }
-    });
+        started
+    }
+
+    #[tokio::test]
+    async fn runs_execute_in_admission_order_whenever_released() {
+        let state = AppState::for_tests(Arc::new(MockDriver)).await;
+        let mut events = state.events.subscribe();
+
+        let ids = ["r_01", "r_02", "r_03"];
+        let admissions: Vec<_> = ids.iter().map(|id| admit_run(&state, "h", id)).collect();
+        assert_eq!(admissions[2].ahead, ["r_01", "r_02"]);
+        for id in ids {
+            let rec = RunRecord::starting(id, "h", Policy::Observe);
+            state.runs.insert(rec).await.unwrap();
+        }
+        for admission in admissions.into_iter().rev() {
+            admission.release();
+            tokio::task::yield_now().await;
+        }
+
+        assert_eq!(started_order(&mut events, 3).await, ids);
+    }
+
+    #[tokio::test]
+    async fn withdrawn_admission_does_not_block_the_lane() {
+        let state = AppState::for_tests(Arc::new(MockDriver)).await;
+        let mut events = state.events.subscribe();
+
+        drop(admit_run(&state, "h", "r_01"));
+        let next = admit_run(&state, "h", "r_02");
+        let rec = RunRecord::starting("r_02", "h", Policy::Observe);
+        state.runs.insert(rec).await.unwrap();
+        next.release();
+
+        assert_eq!(started_order(&mut events, 1).await, ["r_02"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_creates_run_behind_the_run_they_queued_behind() {
+        let state = AppState::for_tests(Arc::new(MockDriver)).await;
+        let mut events = state.events.subscribe();
+
+        let creates: Vec<_> = (0..16)
+            .map(|_| {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let body = StartRunRequest {
+                        policy: Policy::Observe,
+                        dry_run: false,
+                        metadata: None,
+                        requested_by: None,
+                        supersede: false,
+                    };
+                    create_run_and_response(state, "h".into(), body)
+                        .await
+                        .ok()
+                        .unwrap()
+                        .response
+                })
+            })
+            .collect();
+        let mut responses = Vec::new();
+        for create in creates {
+            responses.push(create.await.unwrap());
+        }
+
+        let started = started_order(&mut events, responses.len()).await;
+        let position = |id: &str| started.iter().position(|s| s == id).unwrap();
+        for resp in &responses {
+            if let Some(behind) = resp["queued_behind"].as_str() {
+                let run_id = resp["run_id"].as_str().unwrap();
+                assert!(
+                    position(behind) < position(run_id),
+                    "{run_id} overtook {behind}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn step_reported_not_ok_fails_the_run_with_a_reason() {
+        let state = AppState::for_tests(Arc::new(Refusing)).await;
+        let mut events = state.events.subscribe();
+        let rec = RunRecord::starting("r_01", "h", Policy::Observe);
+        state.runs.insert(rec).await.unwrap();
+        admit_run(&state, "h", "r_01").release();
+        started_order(&mut events, 1).await;
+
+        let run = state.runs.get("r_01").await.unwrap().unwrap();
+        assert_eq!(run.status, RunStatus::Failed);
+        assert_eq!(run.failure_reason.as_deref(), Some("step_failed"));
+    }
 }